@@ -1,12 +1,16 @@
 use std::{
     collections::{HashMap, hash_map::Entry},
     io,
-    io::{Seek, SeekFrom},
+    io::{Seek, SeekFrom, Write},
 };
 
 use binrw::{BinRead, BinResult, BinWrite, Endian};
 use bytes::Buf;
 use image::GrayImage;
+use shin_text::CharRemap;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 
 #[derive(BinRead, BinWrite, Debug)]
 #[brw(little, magic = b"FNT4")]
@@ -17,6 +21,19 @@ struct Font0Header {
     pub padding: u32,
 }
 
+/// Header of the newer FNT4 fonts, indexed by unicode codepoints instead of Shift-JIS ones
+///
+/// The version field is at the same place as the size in [`Font0Header`], and a font can't be one byte long, so this is how the formats are told apart.
+#[derive(BinRead, BinWrite, Debug)]
+#[brw(little, magic = b"FNT4")]
+struct Font1Header {
+    #[br(assert(version == 1))]
+    pub version: u32,
+    pub size: u32,
+    pub ascent: u16,
+    pub descent: u16,
+}
+
 #[derive(BinRead, BinWrite, Debug)]
 #[brw(little)]
 struct Font0GlyphHeader {
@@ -50,6 +67,33 @@ impl From<Font0GlyphHeader> for GlyphMetrics {
     }
 }
 
+#[derive(BinRead, BinWrite, Debug)]
+#[brw(little)]
+struct Font1GlyphHeader {
+    pub bearing_y: i8,
+    pub bearing_x: i8,
+    pub width: u8,
+    pub height: u8,
+    pub advance_width: u8,
+    pub unk: u8,
+    /// Size of the glyph image, which is padded to a power of two
+    pub texture_width: u8,
+    pub texture_height: u8,
+    pub compressed_size: u32,
+}
+
+impl From<Font1GlyphHeader> for GlyphMetrics {
+    fn from(value: Font1GlyphHeader) -> Self {
+        Self {
+            bearing_x: value.bearing_x,
+            bearing_y: value.bearing_y,
+            advance_width: value.advance_width,
+            width: value.width,
+            height: value.height,
+        }
+    }
+}
+
 // this is similar to lz77 used on newer versions, but it stores the backseek spec in a single byte, has length and offset swapped and has different length bias
 pub fn fnt0_lz77_decompress(input: &[u8], output: &mut Vec<u8>) {
     let mut input = io::Cursor::new(input);
@@ -91,6 +135,45 @@ pub fn fnt0_lz77_decompress(input: &[u8], output: &mut Vec<u8>) {
     }
 }
 
+// the lz77 variant used by the newer formats, storing the backseek spec in two bytes
+pub fn fnt1_lz77_decompress(input: &[u8], output: &mut Vec<u8>) {
+    let mut input = io::Cursor::new(input);
+
+    while input.has_remaining() {
+        let map = input.get_u8();
+        for i in 0..8 {
+            if !input.has_remaining() {
+                break;
+            }
+
+            if ((map >> i) & 1) == 0 {
+                /* literal value */
+                output.push(input.get_u8());
+            } else {
+                /* back seek */
+                let backseek_spec = input.get_u16();
+
+                /*  MSB  XXXXXXXX          YYYYYYYY    LSB
+                    val         len        backOffset
+                    size (16-OFFSET_BITS)  OFFSET_BITS
+                */
+
+                const OFFSET_BITS: u32 = 10;
+
+                let offset_mask = (1 << OFFSET_BITS) - 1;
+
+                let back_offset = (backseek_spec & offset_mask) + 1;
+                let len = (backseek_spec >> OFFSET_BITS) + 3;
+
+                for _ in 0..len {
+                    let last = output.len() - back_offset as usize;
+                    output.push(output[last]);
+                }
+            }
+        }
+    }
+}
+
 /// A newtype representing an ID of the glyph within the FNT file
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct GlyphId(pub u32);
@@ -150,11 +233,39 @@ impl BinRead for Font0Glyph {
     }
 }
 
-fn stream_size(reader: &mut impl Seek) -> BinResult<u64> {
-    let pos = reader.stream_position()?;
-    let size = reader.seek(SeekFrom::End(0))?;
-    reader.seek(SeekFrom::Start(pos))?;
-    Ok(size)
+/// Reads a FNT1 glyph, converting it to the same representation as the FNT0 ones
+fn font1_read_glyph<R: io::Read + Seek>(reader: &mut R, endian: Endian) -> BinResult<Font0Glyph> {
+    let header = Font1GlyphHeader::read_options(reader, endian, ())?;
+    let compressed_size = header.compressed_size as usize;
+
+    // the glyph is stored as an 8bpp texture, followed by its smaller mip levels
+    let texture_width = header.texture_width as usize;
+    let texture_size = texture_width * header.texture_height as usize;
+    let info: GlyphMetrics = header.into();
+
+    let data = if compressed_size == 0 {
+        let mut data = vec![0; texture_size];
+        reader.read_exact(&mut data)?;
+        data
+    } else {
+        let mut data = vec![0; compressed_size];
+        reader.read_exact(&mut data)?;
+        let mut decompressed_data = Vec::with_capacity(texture_size);
+        fnt1_lz77_decompress(&data, &mut decompressed_data);
+        decompressed_data
+    };
+    if data.len() < texture_size || info.width as usize > texture_width {
+        return Err(binrw::Error::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "glyph data is smaller than its texture",
+        )));
+    }
+
+    let image = GrayImage::from_fn(info.width as u32, info.height as u32, |x, y| {
+        image::Luma([data[y as usize * texture_width + x as usize]])
+    });
+
+    Ok(Font0Glyph { info, image })
 }
 
 fn font0_map_sjis_to_index(sjis_codepoint: u32) -> Option<u32> {
@@ -234,50 +345,18 @@ macro_rules! box_array {
 }
 
 const FONT0_GLYPH_COUNT: usize = 8244;
+/// FNT1 has a slot for every codepoint in the basic multilingual plane
+const FONT1_GLYPH_COUNT: usize = 0x10000;
 
-#[expect(unused)] // extraction of actual glyph data is implemented, but is not exposed currently (because it's been hacked together)
+#[cfg(test)] // dumps all the glyphs, useful for poking at the font
 fn read_font0<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<()> {
-    let endian = binrw::Endian::Little;
-
-    let stream_position = reader.stream_position()?;
-    let header = Font0Header::read_options(reader, endian, ())?;
-    let size = stream_size(reader)?;
-
-    let mut character_table = box_array![0u32; FONT0_GLYPH_COUNT];
-    for c in character_table.iter_mut() {
-        *c = u32::read_options(reader, endian, ())?;
-    }
-
-    let mut known_glyph_offsets = HashMap::new();
-    let mut characters = box_array![GlyphId(0); FONT0_GLYPH_COUNT];
-    let mut glyphs = HashMap::new();
-
-    for (character_index, &glyph_offset) in character_table.iter().enumerate() {
-        let next_glyph_id = GlyphId(known_glyph_offsets.len() as u32);
-        let glyph_id = *known_glyph_offsets
-            .entry(glyph_offset)
-            .or_insert(next_glyph_id);
-        characters[character_index] = glyph_id;
-
-        match glyphs.entry(glyph_id) {
-            Entry::Occupied(_) => continue,
-            Entry::Vacant(entry) => {
-                reader.seek(SeekFrom::Start(glyph_offset as u64))?;
-                entry.insert(Font0Glyph::read_options(
-                    reader,
-                    endian,
-                    Default::default(),
-                )?);
-            }
-        }
-    }
+    let glyphs = FontGlyphs::from_font0(reader)?;
 
-    for character in 0..FONT0_GLYPH_COUNT {
-        let glyph_id = characters[character];
-        let glyph = glyphs.get(&glyph_id).unwrap();
+    for (character, glyph_id) in glyphs.characters.iter().enumerate() {
+        let glyph = &glyphs.glyphs[glyph_id.0 as usize];
         glyph
             .image
-            .save(&format!("glyphs/{character:05}.png"))
+            .save(format!("glyphs/{character:05}.png"))
             .unwrap();
     }
 
@@ -290,9 +369,134 @@ fn read_font0<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<()> {
     Ok(())
 }
 
+/// Encodes an 8-bit grayscale image into 4bpp glyph data, as stored in FNT0
+fn font0_encode_glyph_data(image: &GrayImage) -> Vec<u8> {
+    let stride = image.width().div_ceil(2) as usize;
+    let mut data = vec![0; stride * image.height() as usize];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let v = pixel.0[0] >> 4;
+        let byte = &mut data[y as usize * stride + x as usize / 2];
+        if x & 1 == 0 {
+            *byte |= v << 4;
+        } else {
+            *byte |= v;
+        }
+    }
+    data
+}
+
+/// Adds glyphs to a FNT0 font, making the given Shift-JIS codepoints display them.
+///
+/// This is intended to be used with [`shin_text::CharRemap`] to draw characters that Shift-JIS doesn't have in the unused codepoints.
+///
+/// The glyph images are placed at the top of the line, so they should be as tall as the whole line (ascent + descent). The advance width is taken from the image width.
+pub fn font0_add_glyphs<'a>(
+    font: &[u8],
+    glyphs: impl IntoIterator<Item = (u16, &'a GrayImage)>,
+) -> BinResult<Vec<u8>> {
+    let endian = binrw::Endian::Little;
+    let invalid_input =
+        |message: String| binrw::Error::Io(io::Error::new(io::ErrorKind::InvalidInput, message));
+
+    let mut reader = io::Cursor::new(font);
+    let header = Font0Header::read_options(&mut reader, endian, ())?;
+    let table_offset = reader.position();
+
+    let mut output = io::Cursor::new(font.to_vec());
+    output.seek(SeekFrom::End(0))?;
+
+    for (codepoint, image) in glyphs {
+        let index = font0_map_sjis_to_index(codepoint as u32).ok_or_else(|| {
+            invalid_input(format!(
+                "codepoint 0x{:04x} doesn't have a slot in the font",
+                codepoint
+            ))
+        })?;
+        let (Ok(width), Ok(height)) = (u8::try_from(image.width()), u8::try_from(image.height()))
+        else {
+            return Err(invalid_input(format!(
+                "glyph for codepoint 0x{:04x} is too large ({}x{})",
+                codepoint,
+                image.width(),
+                image.height()
+            )));
+        };
+
+        // keep the glyphs aligned, like the rest of the font
+        while !output.position().is_multiple_of(4) {
+            0u8.write_options(&mut output, endian, ())?;
+        }
+        let glyph_offset = output.position() as u32;
+
+        Font0GlyphHeader {
+            bearing_x: 0,
+            bearing_y: header.ascent.min(i8::MAX as u16) as i8,
+            width,
+            height,
+            advance_width: width,
+            unk: 0,
+            // store the data uncompressed
+            compressed_size: 0,
+        }
+        .write_options(&mut output, endian, ())?;
+        output.write_all(&font0_encode_glyph_data(image))?;
+        let end = output.position();
+
+        output.seek(SeekFrom::Start(table_offset + index as u64 * 4))?;
+        glyph_offset.write_options(&mut output, endian, ())?;
+        output.seek(SeekFrom::Start(end))?;
+    }
+
+    let size = output.position() as u32;
+    output.seek(SeekFrom::Start(0))?;
+    Font0Header { size, ..header }.write_options(&mut output, endian, ())?;
+
+    Ok(output.into_inner())
+}
+
+/// Returns the index of the character in the FNT0 character table
+fn font0_character_index(char_remap: &CharRemap, codepoint: char) -> Option<usize> {
+    if let Some(sjis) = char_remap.get(codepoint) {
+        // remapped codepoints are user-provided and might not have a slot in the font
+        Some(font0_map_sjis_to_index(sjis as u32)? as usize)
+    } else {
+        let sjis = shin_text::encode_sjis_codepoint(codepoint, false)?;
+        Some(
+            font0_map_sjis_to_index(sjis as u32)
+                .expect("BUG: a just-encoded sjis codepoint is unmapped to font")
+                as usize,
+        )
+    }
+}
+
+/// Returns the index of the character in the character table of the font
+fn character_index(r#type: &FontType, char_remap: &CharRemap, codepoint: char) -> Option<usize> {
+    match r#type {
+        FontType::Font0 => font0_character_index(char_remap, codepoint),
+        FontType::Font1 => {
+            // the game decodes the strings before looking up the glyphs, and the unused Shift-JIS codepoints don't decode to anything
+            // so the remapped characters can't be displayed with these fonts
+            if char_remap.get(codepoint).is_some() {
+                return None;
+            }
+            let index = codepoint as usize;
+            (index < FONT1_GLYPH_COUNT).then_some(index)
+        }
+    }
+}
+
+/// Checks whether the font is in the newer (FNT1) format, leaving the reader where it was
+fn is_font1<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<bool> {
+    let start = reader.stream_position()?;
+    let mut head = [0u8; 8];
+    reader.read_exact(&mut head)?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    Ok(u32::from_le_bytes(head[4..].try_into().unwrap()) == 1)
+}
+
 enum FontType {
     Font0,
-    #[expect(unused)] // FNTv1 support not yet fully implemented, but planned
     Font1,
 }
 
@@ -308,9 +512,19 @@ pub struct FontMetrics {
     r#type: FontType,
     metrics: Box<[GlyphMetrics]>,
     info: FontInfo,
+    char_remap: CharRemap,
 }
 
 impl FontMetrics {
+    /// Reads the font metrics, detecting the font format from the header
+    pub fn read<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<Self> {
+        if is_font1(reader)? {
+            Self::from_font1(reader)
+        } else {
+            Self::from_font0(reader)
+        }
+    }
+
     pub fn from_font0<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<Self> {
         let endian = binrw::Endian::Little;
 
@@ -337,34 +551,213 @@ impl FontMetrics {
                 ascent: header.ascent,
                 descent: header.descent,
             },
+            char_remap: CharRemap::new(),
+        })
+    }
+
+    pub fn from_font1<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<Self> {
+        let endian = binrw::Endian::Little;
+
+        let header = Font1Header::read_options(reader, endian, ())?;
+
+        let mut character_table = box_array![0u32; FONT1_GLYPH_COUNT];
+        for c in character_table.iter_mut() {
+            *c = u32::read_options(reader, endian, ())?;
+        }
+
+        // most of the characters share the same placeholder glyph, no need to read it over and over
+        let mut known_glyphs = HashMap::new();
+        let mut metrics = Vec::with_capacity(FONT1_GLYPH_COUNT);
+
+        for &glyph_offset in character_table.iter() {
+            let glyph_metrics = match known_glyphs.entry(glyph_offset) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    reader.seek(SeekFrom::Start(glyph_offset as u64))?;
+                    let glyph_header =
+                        Font1GlyphHeader::read_options(reader, endian, Default::default())?;
+                    *entry.insert(glyph_header.into())
+                }
+            };
+
+            metrics.push(glyph_metrics);
+        }
+
+        Ok(Self {
+            r#type: FontType::Font1,
+            metrics: metrics.into_boxed_slice(),
+            info: FontInfo {
+                ascent: header.ascent,
+                descent: header.descent,
+            },
+            char_remap: CharRemap::new(),
         })
     }
 
+    /// Whether the font can display the remapped characters (see [`FontMetrics::with_char_remap`])
+    ///
+    /// Only the older (FNT0) fonts can, as they are indexed by the Shift-JIS codepoints.
+    pub fn supports_char_remap(&self) -> bool {
+        matches!(self.r#type, FontType::Font0)
+    }
+
+    /// Looks up the characters from the table by their assigned codepoints
+    pub fn with_char_remap(mut self, char_remap: CharRemap) -> Self {
+        self.char_remap = char_remap;
+        self
+    }
+
     pub fn get_info(&self) -> FontInfo {
         self.info
     }
 
     pub fn get_glyph_metrics(&self, codepoint: char) -> Option<GlyphMetrics> {
-        let index = match self.r#type {
-            FontType::Font0 => {
-                let sjis = shin_text::encode_sjis_codepoint(codepoint, false)?;
-                font0_map_sjis_to_index(sjis as u32)
-                    .expect("BUG: a just-encoded sjis codepoint is unmapped to font")
-                    as usize
-            }
-            FontType::Font1 => {
-                todo!()
-            }
-        };
+        let index = character_index(&self.r#type, &self.char_remap, codepoint)?;
 
         Some(self.metrics[index])
     }
 }
 
+/// Glyph images of a font, for drawing the text the way the game does
+pub struct FontGlyphs {
+    r#type: FontType,
+    characters: Box<[GlyphId]>,
+    glyphs: Box<[Font0Glyph]>,
+    info: FontInfo,
+    char_remap: CharRemap,
+}
+
+impl FontGlyphs {
+    /// Reads the glyph images, detecting the font format from the header
+    pub fn read<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<Self> {
+        if is_font1(reader)? {
+            Self::from_font1(reader)
+        } else {
+            Self::from_font0(reader)
+        }
+    }
+
+    pub fn from_font0<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<Self> {
+        let endian = binrw::Endian::Little;
+
+        let header = Font0Header::read_options(reader, endian, ())?;
+
+        let mut character_table = box_array![0u32; FONT0_GLYPH_COUNT];
+        for c in character_table.iter_mut() {
+            *c = u32::read_options(reader, endian, ())?;
+        }
+
+        // many characters share the same glyph, only read it once
+        let mut known_glyph_offsets = HashMap::new();
+        let mut characters = Vec::with_capacity(FONT0_GLYPH_COUNT);
+        let mut glyphs = Vec::new();
+
+        for &glyph_offset in character_table.iter() {
+            let glyph_id = match known_glyph_offsets.entry(glyph_offset) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    reader.seek(SeekFrom::Start(glyph_offset as u64))?;
+                    glyphs.push(Font0Glyph::read_options(reader, endian, ())?);
+                    *entry.insert(GlyphId(glyphs.len() as u32 - 1))
+                }
+            };
+
+            characters.push(glyph_id);
+        }
+
+        Ok(Self {
+            r#type: FontType::Font0,
+            characters: characters.into_boxed_slice(),
+            glyphs: glyphs.into_boxed_slice(),
+            info: FontInfo {
+                ascent: header.ascent,
+                descent: header.descent,
+            },
+            char_remap: CharRemap::new(),
+        })
+    }
+
+    pub fn from_font1<R: io::Read + io::Seek>(reader: &mut R) -> BinResult<Self> {
+        let endian = binrw::Endian::Little;
+
+        let header = Font1Header::read_options(reader, endian, ())?;
+
+        let mut character_table = box_array![0u32; FONT1_GLYPH_COUNT];
+        for c in character_table.iter_mut() {
+            *c = u32::read_options(reader, endian, ())?;
+        }
+
+        // most of the characters share the same placeholder glyph, only read it once
+        let mut known_glyph_offsets = HashMap::new();
+        let mut characters = Vec::with_capacity(FONT1_GLYPH_COUNT);
+        let mut glyphs = Vec::new();
+
+        for &glyph_offset in character_table.iter() {
+            let glyph_id = match known_glyph_offsets.entry(glyph_offset) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    reader.seek(SeekFrom::Start(glyph_offset as u64))?;
+                    glyphs.push(font1_read_glyph(reader, endian)?);
+                    *entry.insert(GlyphId(glyphs.len() as u32 - 1))
+                }
+            };
+
+            characters.push(glyph_id);
+        }
+
+        Ok(Self {
+            r#type: FontType::Font1,
+            characters: characters.into_boxed_slice(),
+            glyphs: glyphs.into_boxed_slice(),
+            info: FontInfo {
+                ascent: header.ascent,
+                descent: header.descent,
+            },
+            char_remap: CharRemap::new(),
+        })
+    }
+
+    /// Looks up the characters from the table by their assigned codepoints
+    pub fn with_char_remap(mut self, char_remap: CharRemap) -> Self {
+        self.char_remap = char_remap;
+        self
+    }
+
+    pub fn get_info(&self) -> FontInfo {
+        self.info
+    }
+
+    pub fn get_glyph(&self, codepoint: char) -> Option<&Font0Glyph> {
+        let index = character_index(&self.r#type, &self.char_remap, codepoint)?;
+        let glyph_id = self.characters[index];
+
+        Some(&self.glyphs[glyph_id.0 as usize])
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io;
 
+    use binrw::{BinRead, Endian};
+    use image::{GrayImage, Luma};
+    use shin_text::CharRemap;
+
+    use super::{
+        Font0Glyph, Font0Header, FontGlyphs, FontMetrics, fnt1_lz77_decompress, font0_add_glyphs,
+        font0_map_sjis_to_index, test_util::TestFont,
+    };
+
+    /// A font where all the characters share a single blank glyph, 2 units wide
+    fn blank_font() -> Vec<u8> {
+        TestFont::new(20, 4, 2).font0()
+    }
+
+    /// A unicode font where `A` is 10 units wide and everything else shares a 5 units wide glyph
+    fn font1() -> Vec<u8> {
+        TestFont::new(40, 10, 5).glyph('A', 10).font1()
+    }
+
     #[test]
     #[ignore]
     pub fn smoke() {
@@ -374,4 +767,85 @@ mod tests {
 
         super::read_font0(&mut io::Cursor::new(font_data.as_slice())).unwrap();
     }
+
+    #[test]
+    fn read_font1() {
+        let metrics = FontMetrics::read(&mut io::Cursor::new(font1())).unwrap();
+        assert_eq!(metrics.get_info().ascent, 40);
+        assert_eq!(metrics.get_glyph_metrics('A').unwrap().advance_width, 10);
+        assert_eq!(metrics.get_glyph_metrics('é').unwrap().advance_width, 5);
+        assert!(metrics.get_glyph_metrics('🍣').is_none());
+        assert!(!metrics.supports_char_remap());
+
+        // the older format is still detected
+        let metrics = FontMetrics::read(&mut io::Cursor::new(blank_font())).unwrap();
+        assert_eq!(metrics.get_glyph_metrics('あ').unwrap().advance_width, 2);
+    }
+
+    #[test]
+    fn add_glyphs() {
+        let mut image = GrayImage::new(3, 24);
+        image.put_pixel(0, 0, Luma([0xff]));
+        image.put_pixel(1, 0, Luma([0x80]));
+        image.put_pixel(2, 23, Luma([0x30]));
+
+        let font = font0_add_glyphs(&blank_font(), [(0xf040, &image)]).unwrap();
+
+        let mut reader = io::Cursor::new(font.as_slice());
+        let header = Font0Header::read(&mut reader).unwrap();
+        assert_eq!(header.size as usize, font.len());
+
+        let index = font0_map_sjis_to_index(0xf040).unwrap() as usize;
+        let offset = u32::from_le_bytes(font[16 + index * 4..][..4].try_into().unwrap());
+        reader.set_position(offset as u64);
+        let glyph = Font0Glyph::read_options(&mut reader, Endian::Little, ()).unwrap();
+        assert_eq!(glyph.info.advance_width, 3);
+        assert_eq!(glyph.image.get_pixel(0, 0).0, [0xf0]);
+        assert_eq!(glyph.image.get_pixel(1, 0).0, [0x80]);
+        assert_eq!(glyph.image.get_pixel(2, 23).0, [0x30]);
+
+        let mut remap = CharRemap::new();
+        remap.insert('é', 0xf040).unwrap();
+        let metrics = FontMetrics::from_font0(&mut io::Cursor::new(font))
+            .unwrap()
+            .with_char_remap(remap);
+        assert!(metrics.supports_char_remap());
+        assert_eq!(metrics.get_glyph_metrics('é').unwrap().advance_width, 3);
+        assert_eq!(metrics.get_glyph_metrics('あ').unwrap().advance_width, 2);
+        assert!(metrics.get_glyph_metrics('ü').is_none());
+    }
+
+    #[test]
+    fn read_glyphs() {
+        let mut image = GrayImage::new(3, 24);
+        image.put_pixel(1, 2, Luma([0xff]));
+        let font = font0_add_glyphs(&blank_font(), [(0xf040, &image)]).unwrap();
+
+        let mut remap = CharRemap::new();
+        remap.insert('é', 0xf040).unwrap();
+        let glyphs = FontGlyphs::read(&mut io::Cursor::new(font))
+            .unwrap()
+            .with_char_remap(remap);
+        assert_eq!(glyphs.get_info().ascent, 20);
+
+        let glyph = glyphs.get_glyph('é').unwrap();
+        assert_eq!(glyph.info.width, 3);
+        assert_eq!(glyph.image.get_pixel(1, 2).0, [0xf0]);
+        assert_eq!(glyphs.get_glyph('あ').unwrap().image.dimensions(), (2, 24));
+        assert!(glyphs.get_glyph('ü').is_none());
+
+        let glyphs = FontGlyphs::read(&mut io::Cursor::new(font1())).unwrap();
+        assert_eq!(glyphs.get_info().ascent, 40);
+        assert_eq!(glyphs.get_glyph('A').unwrap().image.dimensions(), (10, 50));
+        assert_eq!(glyphs.get_glyph('é').unwrap().info.advance_width, 5);
+        assert!(glyphs.get_glyph('🍣').is_none());
+    }
+
+    #[test]
+    fn lz77() {
+        // two literals, then four bytes copied from two bytes back
+        let mut output = Vec::new();
+        fnt1_lz77_decompress(&[0b100, b'a', b'b', 0x04, 0x01], &mut output);
+        assert_eq!(output, b"ababab");
+    }
 }
@@ -0,0 +1,155 @@
+//! Builders of small fonts, for testing the code that measures and draws text without the fonts from the games.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+use binrw::{BinResult, BinWrite};
+use image::GrayImage;
+use shin_text::CharRemap;
+
+use crate::{
+    FONT0_GLYPH_COUNT, FONT1_GLYPH_COUNT, Font0GlyphHeader, Font0Header, Font1GlyphHeader,
+    Font1Header, FontMetrics, font0_character_index, font0_encode_glyph_data,
+};
+
+/// Describes a font where all the characters share a single glyph, except for the ones added with [`TestFont::glyph`]
+///
+/// The glyphs are blank and as tall as the whole line (ascent + descent), with their width equal to the advance width.
+pub struct TestFont {
+    ascent: u16,
+    descent: u16,
+    advance_width: u8,
+    glyphs: Vec<(char, u8)>,
+}
+
+impl TestFont {
+    pub fn new(ascent: u16, descent: u16, advance_width: u8) -> Self {
+        Self {
+            ascent,
+            descent,
+            advance_width,
+            glyphs: Vec::new(),
+        }
+    }
+
+    /// A font with all the characters being 10 units wide, 20 units of ascent and 4 of descent
+    pub fn monospace() -> Self {
+        Self::new(20, 4, 10)
+    }
+
+    /// Gives the character its own glyph with a different advance width
+    pub fn glyph(mut self, c: char, advance_width: u8) -> Self {
+        self.glyphs.push((c, advance_width));
+        self
+    }
+
+    /// Encodes the font in the older (FNT0) format, indexed by Shift-JIS codepoints
+    ///
+    /// Panics if a character added with [`TestFont::glyph`] can't be encoded in Shift-JIS.
+    pub fn font0(&self) -> Vec<u8> {
+        self.encode(
+            FONT0_GLYPH_COUNT,
+            |c| font0_character_index(&CharRemap::new(), c).expect("no Shift-JIS codepoint"),
+            |output, size| {
+                Font0Header {
+                    size,
+                    ascent: self.ascent,
+                    descent: self.descent,
+                    padding: 0,
+                }
+                .write(output)
+            },
+            |output, image| {
+                Font0GlyphHeader {
+                    bearing_x: 0,
+                    bearing_y: self.ascent as i8,
+                    width: image.width() as u8,
+                    height: image.height() as u8,
+                    advance_width: image.width() as u8,
+                    unk: 0,
+                    compressed_size: 0,
+                }
+                .write(output)?;
+                Ok(output.write_all(&font0_encode_glyph_data(image))?)
+            },
+        )
+    }
+
+    /// Encodes the font in the newer (FNT1) format, indexed by unicode codepoints
+    ///
+    /// Only the first mip level of the glyph textures is stored.
+    pub fn font1(&self) -> Vec<u8> {
+        self.encode(
+            FONT1_GLYPH_COUNT,
+            |c| c as usize,
+            |output, size| {
+                Font1Header {
+                    version: 1,
+                    size,
+                    ascent: self.ascent,
+                    descent: self.descent,
+                }
+                .write(output)
+            },
+            |output, image| {
+                let texture_width = image.width().next_power_of_two();
+                let texture_height = image.height().next_power_of_two();
+                Font1GlyphHeader {
+                    bearing_y: self.ascent as i8,
+                    bearing_x: 0,
+                    width: image.width() as u8,
+                    height: image.height() as u8,
+                    advance_width: image.width() as u8,
+                    unk: 0,
+                    texture_width: texture_width as u8,
+                    texture_height: texture_height as u8,
+                    compressed_size: 0,
+                }
+                .write(output)?;
+                let mut texture = vec![0; (texture_width * texture_height) as usize];
+                for (x, y, pixel) in image.enumerate_pixels() {
+                    texture[(y * texture_width + x) as usize] = pixel.0[0];
+                }
+                Ok(output.write_all(&texture)?)
+            },
+        )
+    }
+
+    /// Reads the metrics of the font in the newer (FNT1) format
+    pub fn metrics(&self) -> FontMetrics {
+        FontMetrics::read(&mut io::Cursor::new(self.font1())).unwrap()
+    }
+
+    fn encode(
+        &self,
+        glyph_count: usize,
+        character_index: impl Fn(char) -> usize,
+        write_header: impl FnOnce(&mut io::Cursor<Vec<u8>>, u32) -> BinResult<()>,
+        write_glyph: impl Fn(&mut io::Cursor<Vec<u8>>, &GrayImage) -> BinResult<()>,
+    ) -> Vec<u8> {
+        let mut output = io::Cursor::new(Vec::new());
+        // the header is written last, when the size is known
+        output.write_all(&[0; 16]).unwrap();
+        output.write_all(&vec![0; glyph_count * 4]).unwrap();
+
+        let mut add_glyph = |advance_width: u8| {
+            let offset = output.position() as u32;
+            let image = GrayImage::new(advance_width as u32, (self.ascent + self.descent) as u32);
+            write_glyph(&mut output, &image).unwrap();
+            offset
+        };
+        let default_offset = add_glyph(self.advance_width);
+        let mut offsets = vec![default_offset; glyph_count];
+        for &(c, advance_width) in &self.glyphs {
+            offsets[character_index(c)] = add_glyph(advance_width);
+        }
+
+        let size = output.position() as u32;
+        output.seek(SeekFrom::Start(0)).unwrap();
+        write_header(&mut output, size).unwrap();
+        for offset in offsets {
+            offset.write_le(&mut output).unwrap();
+        }
+
+        output.into_inner()
+    }
+}
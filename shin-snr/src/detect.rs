@@ -0,0 +1,181 @@
+//! Guessing the engine version of an SNR file.
+//!
+//! SNR files don't store the version they are made for, so each of the [`ENGINE_SCHEMAS`] is tried in turn, checking how well the file parses with it.
+
+use bumpalo::Bump;
+use shin_text::StringArrayIter;
+use shin_versions::{ShinVersion, StringEncoding};
+
+use crate::{
+    operation::{
+        OperationElementRepr,
+        arena::OperationArena,
+        schema::{ENGINE_SCHEMAS, Opcode, OperationSchema},
+    },
+    reactor::{
+        ParseErrorKind, Reactor, StringArraySource, StringSource,
+        offset_validator::OffsetValidatorReactor, react_fallible,
+    },
+    reader::Reader,
+    text::decode_zstring,
+};
+
+/// How well the SNR file matches one of the engine versions
+#[derive(Debug, Clone)]
+pub struct VersionCandidate {
+    pub version: ShinVersion,
+    /// Position and kind of the error if the file couldn't be parsed to the end
+    pub parse_error: Option<(u32, ParseErrorKind)>,
+    /// Whether all jumps point to the start of some operation
+    pub valid_offsets: bool,
+    pub strings: usize,
+    /// Strings that couldn't be decoded with the version's encoding
+    pub undecodable_strings: usize,
+}
+
+impl VersionCandidate {
+    /// Whether the file looks like it was made for this version: it parses without errors, all the jumps are valid and all the strings are decodable.
+    pub fn is_plausible(&self) -> bool {
+        self.parse_error.is_none() && self.valid_offsets && self.undecodable_strings == 0
+    }
+
+    /// Key to sort the candidates by, the larger the better
+    fn score(&self) -> (bool, bool, bool, u32, usize) {
+        (
+            self.is_plausible(),
+            self.parse_error.is_none(),
+            self.valid_offsets,
+            // how far the parsing got
+            self.parse_error.map_or(u32::MAX, |(position, _)| position),
+            usize::MAX - self.undecodable_strings,
+        )
+    }
+}
+
+struct DetectReactor<'a> {
+    encoding: StringEncoding,
+    offsets: &'a mut OffsetValidatorReactor,
+    strings: usize,
+    undecodable_strings: usize,
+    bump: Bump,
+}
+
+impl DetectReactor<'_> {
+    /// Decodes the string the same way the readers of the version do, with the fixup for the strings with commands
+    fn on_string(&mut self, s: &[u8], contains_commands: bool) {
+        self.strings += 1;
+        if decode_zstring(&self.bump, self.encoding, s, contains_commands).is_err() {
+            self.undecodable_strings += 1;
+        }
+        self.bump.reset();
+    }
+}
+
+impl Reactor for DetectReactor<'_> {
+    fn react(
+        &mut self,
+        operation_position: u32,
+        raw_opcode: u8,
+        opcode: Opcode,
+        op_schema: &OperationSchema,
+        arena: &OperationArena,
+    ) {
+        self.offsets
+            .react(operation_position, raw_opcode, opcode, op_schema, arena);
+
+        for element in arena.iter(op_schema) {
+            match element {
+                OperationElementRepr::String(_, string) => {
+                    // with a wrong version, MSGSET may not even start with the message id, which doesn't matter here anyway
+                    let contains_commands = StringSource::for_opcode(opcode, || 0)
+                        .is_some_and(|source| source.contains_commands());
+                    self.on_string(string, contains_commands);
+                }
+                OperationElementRepr::StringArray(_, string_array) => {
+                    let contains_commands =
+                        StringArraySource::for_operation(opcode, op_schema, arena)
+                            .is_some_and(|source| source.contains_commands());
+                    for string in StringArrayIter::new(string_array) {
+                        self.on_string(string, contains_commands);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Tries to parse the SNR file with every known engine version.
+///
+/// Returns the candidates sorted from the most to the least likely. If there are several plausible ones (see [`VersionCandidate::is_plausible`]), the file can't be told apart between them.
+pub fn detect_version(reader: Reader) -> Vec<VersionCandidate> {
+    let mut candidates = ENGINE_SCHEMAS
+        .iter()
+        .map(|(version, schema)| {
+            let mut offsets = OffsetValidatorReactor::new();
+            let mut reactor = DetectReactor {
+                encoding: version.string_encoding(),
+                offsets: &mut offsets,
+                strings: 0,
+                undecodable_strings: 0,
+                bump: Bump::new(),
+            };
+            let parse_error = react_fallible(reader.clone(), schema, &mut reactor)
+                .err()
+                .map(|(kind, position, _)| (position, kind));
+            let DetectReactor {
+                strings,
+                undecodable_strings,
+                ..
+            } = reactor;
+
+            VersionCandidate {
+                version,
+                parse_error,
+                // the offsets past the error are not seen, so they can't be checked
+                valid_offsets: parse_error.is_none() && offsets.validate().is_ok(),
+                strings,
+                undecodable_strings,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.score()));
+
+    candidates
+}
+
+#[cfg(test)]
+mod test {
+    use shin_versions::ShinVersion;
+
+    use super::detect_version;
+    use crate::{operation::schema::ENGINE_SCHEMAS, reader::Reader};
+
+    #[test]
+    fn detect() {
+        // SAVEINFO 0, "共通ルート", followed by the padding
+        let mut data = vec![
+            0xa0, 0x00, 0x00, 0x0b, 0x8b, 0xa4, 0x92, 0xca, 0x83, 0x8b, 0x81, 0x5b, 0x83, 0x67,
+            0x00,
+        ];
+        data.resize(data.len() + 16, 0);
+
+        let candidates = detect_version(Reader::new(&data, 0));
+
+        assert_eq!(candidates.len(), ENGINE_SCHEMAS.len());
+        let sui = candidates
+            .iter()
+            .find(|c| c.version == ShinVersion::HigurashiSui)
+            .unwrap();
+        assert!(sui.is_plausible());
+        assert_eq!(sui.strings, 1);
+        // versions that use u16 string lengths run out of data
+        let umineko = candidates
+            .iter()
+            .find(|c| c.version == ShinVersion::Umineko)
+            .unwrap();
+        assert!(umineko.parse_error.is_some());
+        assert!(!umineko.is_plausible());
+    }
+}
@@ -0,0 +1,122 @@
+//! A glossary of terms that must be translated consistently (character names, invented terms, etc.)
+
+use std::io;
+
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Record {
+    source: String,
+    translation: String,
+    #[serde(default)]
+    case_sensitive: Option<bool>,
+}
+
+pub struct GlossaryTerm {
+    /// The term in the original text
+    pub source: String,
+    /// The translation that must be used for the term
+    pub translation: String,
+    /// Whether the translation must match the case exactly
+    pub case_sensitive: bool,
+}
+
+impl GlossaryTerm {
+    fn is_translated_in(&self, translated: &str) -> bool {
+        if self.case_sensitive {
+            translated.contains(&self.translation)
+        } else {
+            translated
+                .to_lowercase()
+                .contains(&self.translation.to_lowercase())
+        }
+    }
+}
+
+/// A list of terms with their required translations.
+///
+/// Can be read from a CSV file with `source`, `translation` and optional `case_sensitive` (`true` or `false`) columns.
+#[derive(Default)]
+pub struct Glossary {
+    terms: Vec<GlossaryTerm>,
+}
+
+impl Glossary {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn from_csv<R: io::Read>(reader: csv::Reader<R>) -> csv::Result<Self> {
+        let mut result = Self::new();
+        for record in reader.into_deserialize() {
+            let record: Record = record?;
+            if record.source.is_empty() {
+                continue;
+            }
+            result.terms.push(GlossaryTerm {
+                source: record.source,
+                translation: record.translation,
+                case_sensitive: record.case_sensitive.unwrap_or(false),
+            });
+        }
+
+        Ok(result)
+    }
+
+    pub fn terms(&self) -> &[GlossaryTerm] {
+        &self.terms
+    }
+
+    /// Returns the terms that occur in the original string, but whose required translation is missing from the translated one
+    ///
+    /// Each term comes with the byte offset of its first occurrence in the original string.
+    pub fn missing_terms<'a>(
+        &'a self,
+        original: &'a str,
+        translated: &'a str,
+    ) -> impl Iterator<Item = (usize, &'a GlossaryTerm)> + 'a {
+        self.terms.iter().filter_map(move |term| {
+            let offset = original.find(&term.source)?;
+            (!term.is_translated_in(translated)).then_some((offset, term))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Glossary;
+
+    #[test]
+    fn missing_terms() {
+        let glossary = Glossary::from_csv(csv::Reader::from_reader(
+            "source,translation,case_sensitive
+圭一,Keiichi,
+レナ,Rena,true
+雛見沢,Hinamizawa,false
+"
+            .as_bytes(),
+        ))
+        .unwrap();
+
+        let missing = |original, translated| {
+            glossary
+                .missing_terms(original, translated)
+                .map(|(_, term)| term.translation.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(missing("圭一「おはよう」", "KEIICHI: Morning").is_empty());
+        assert_eq!(
+            missing("レナと圭一", "rena and Keichi"),
+            ["Keiichi", "Rena"]
+        );
+        assert_eq!(missing("雛見沢へようこそ", "Welcome!"), ["Hinamizawa"]);
+        assert!(missing("こんにちは", "Hello").is_empty());
+
+        let offsets = glossary
+            .missing_terms("レナと圭一", "")
+            .map(|(offset, _)| offset)
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, ["レナと".len(), 0]);
+    }
+}
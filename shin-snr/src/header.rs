@@ -0,0 +1,1041 @@
+//! Parsing of the SNR header and the asset tables it points to.
+//!
+//! The header consists of the fixed part (magic, file size, some unknown fields and the code offset), followed by a table of `u32` offsets to the sections.
+//! Each section starts with its `u32` size (including this prefix) and a `u32` entry count, followed by the entries. The sections end where the code starts.
+//!
+//! The order of the sections depends on the engine version, see [`section_order`]. If a section doesn't decode cleanly with the expected layout, it's still listed, but not decoded.
+//! The layouts of the older (Vita) games are not known, so their sections are listed as [`SectionKind::Unknown`] and never decoded.
+
+use std::{collections::HashMap, fmt, io};
+
+use bumpalo::Bump;
+use serde::Serialize;
+use shin_text::{CharRemap, encode_sjis_zstring_with_remap};
+use shin_versions::{ShinVersion, StringEncoding};
+
+use crate::{
+    reactor::{AnyStringSource, StringSource},
+    reader::Reader,
+    text::{decode_zstring, encode_utf8_zstring},
+};
+
+/// Offset of the section offsets table
+const SECTION_TABLE_OFFSET: u32 = 0x24;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HeaderError {
+    #[error("SNR file magic mismatch")]
+    BadMagic,
+    #[error("the file is too small to contain the SNR header")]
+    Truncated,
+    #[error("code offset 0x{code_offset:08x} is outside of the file")]
+    BadCodeOffset { code_offset: u32 },
+}
+
+/// Smallest possible size of a table entry: every layout has at least a string length or two numbers
+const MIN_ENTRY_SIZE: usize = 2;
+
+/// The sections of the Switch games, in the order they appear in the header
+const SWITCH_SECTIONS: [SectionKind; 12] = [
+    SectionKind::Masks,
+    SectionKind::Pictures,
+    SectionKind::Bustups,
+    SectionKind::Bgm,
+    SectionKind::Se,
+    SectionKind::Movies,
+    SectionKind::Voices,
+    SectionKind::PictureBox,
+    SectionKind::MusicBox,
+    SectionKind::CharacterBox,
+    SectionKind::CharsSprite,
+    SectionKind::Tips,
+];
+
+/// Returns the kinds of the sections in the order they appear in the header of the engine version, or `None` if the layout is not known
+pub fn section_order(version: ShinVersion) -> Option<&'static [SectionKind]> {
+    match version {
+        ShinVersion::HigurashiHou
+        | ShinVersion::HigurashiHouV2
+        | ShinVersion::DC4
+        | ShinVersion::Konosuba
+        | ShinVersion::Umineko
+        | ShinVersion::Gerokasu2 => Some(&SWITCH_SECTIONS),
+        ShinVersion::HigurashiSui
+        | ShinVersion::AliasCarnival
+        | ShinVersion::WhiteEternity
+        | ShinVersion::WorldRe => None,
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SectionKind {
+    Masks,
+    Pictures,
+    Bustups,
+    Bgm,
+    Se,
+    Movies,
+    Voices,
+    PictureBox,
+    MusicBox,
+    CharacterBox,
+    CharsSprite,
+    Tips,
+    Unknown,
+}
+
+impl SectionKind {
+    fn for_index(order: &[SectionKind], index: usize) -> Self {
+        order.get(index).copied().unwrap_or(SectionKind::Unknown)
+    }
+}
+
+impl fmt::Display for SectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SectionKind::Masks => "masks",
+            SectionKind::Pictures => "pictures",
+            SectionKind::Bustups => "bustups",
+            SectionKind::Bgm => "bgm",
+            SectionKind::Se => "se",
+            SectionKind::Movies => "movies",
+            SectionKind::Voices => "voices",
+            SectionKind::PictureBox => "picture_box",
+            SectionKind::MusicBox => "music_box",
+            SectionKind::CharacterBox => "character_box",
+            SectionKind::CharsSprite => "chars_sprite",
+            SectionKind::Tips => "tips",
+            SectionKind::Unknown => "unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaskInfo {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PictureInfo {
+    pub name: String,
+    pub linked_cg_id: i16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BustupInfo {
+    pub name: String,
+    pub emotion: String,
+    pub lipsync_character_id: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BgmInfo {
+    pub name: String,
+    /// The track name shown in the music room
+    pub display_name: String,
+    pub linked_bgm_id: i16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeInfo {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MovieInfo {
+    pub name: String,
+    pub linked_picture_id: i16,
+    pub flags: u16,
+    pub linked_bgm_id: i16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VoiceInfo {
+    /// Prefix of the voice file names this entry applies to
+    pub name_pattern: String,
+    pub lipsync_character_ids: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PictureBoxInfo {
+    pub name: String,
+    /// The pictures shown together in this entry of the picture gallery
+    pub picture_ids: Vec<i16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MusicBoxInfo {
+    pub bgm_id: u16,
+    pub name_index: u16,
+    pub once_flag: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CharacterBoxInfo {
+    /// The character name shown in the character list
+    pub name: String,
+    /// The bustups shown for this character
+    pub bustup_ids: Vec<i16>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CharsSpriteInfo {
+    pub episode: u16,
+    pub character_id: u16,
+    pub picture_id: i16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TipsInfo {
+    pub episode: u8,
+    pub title_index: u16,
+    pub title: String,
+    pub title_sort_key: String,
+    pub content: String,
+}
+
+/// Decoded entries of a section
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum AssetTable {
+    Masks(Vec<MaskInfo>),
+    Pictures(Vec<PictureInfo>),
+    Bustups(Vec<BustupInfo>),
+    Bgm(Vec<BgmInfo>),
+    Se(Vec<SeInfo>),
+    Movies(Vec<MovieInfo>),
+    Voices(Vec<VoiceInfo>),
+    PictureBox(Vec<PictureBoxInfo>),
+    MusicBox(Vec<MusicBoxInfo>),
+    CharacterBox(Vec<CharacterBoxInfo>),
+    CharsSprite(Vec<CharsSpriteInfo>),
+    Tips(Vec<TipsInfo>),
+}
+
+impl AssetTable {
+    pub fn len(&self) -> usize {
+        match self {
+            AssetTable::Masks(entries) => entries.len(),
+            AssetTable::Pictures(entries) => entries.len(),
+            AssetTable::Bustups(entries) => entries.len(),
+            AssetTable::Bgm(entries) => entries.len(),
+            AssetTable::Se(entries) => entries.len(),
+            AssetTable::Movies(entries) => entries.len(),
+            AssetTable::Voices(entries) => entries.len(),
+            AssetTable::PictureBox(entries) => entries.len(),
+            AssetTable::MusicBox(entries) => entries.len(),
+            AssetTable::CharacterBox(entries) => entries.len(),
+            AssetTable::CharsSprite(entries) => entries.len(),
+            AssetTable::Tips(entries) => entries.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Section {
+    pub kind: SectionKind,
+    pub offset: u32,
+    /// Number of bytes until the next section (or the code)
+    pub size: u32,
+    /// Number of entries, as stated in the section
+    pub count: u32,
+    /// The decoded entries, if the section layout is known and matches the data
+    pub table: Option<AssetTable>,
+    /// Size of the data described by the section's size field, the rest is padding
+    #[serde(skip)]
+    table_size: u32,
+    /// Whether the entries were changed and the section has to be re-encoded
+    #[serde(skip)]
+    modified: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SnrHeader {
+    /// Size of the whole file
+    pub size: u32,
+    /// The fields between the size and the code offset, their meaning is unknown
+    pub unknown: [u32; 6],
+    pub code_offset: u32,
+    pub sections: Vec<Section>,
+}
+
+impl SnrHeader {
+    pub fn parse(snr_file: &[u8], version: ShinVersion) -> Result<Self, HeaderError> {
+        if snr_file.len() < SECTION_TABLE_OFFSET as usize {
+            return Err(HeaderError::Truncated);
+        }
+        if &snr_file[0..4] != b"SNR " {
+            return Err(HeaderError::BadMagic);
+        }
+
+        let mut reader = Reader::new(snr_file, 4);
+        let size = reader.take_u32();
+        let unknown = std::array::from_fn(|_| reader.take_u32());
+        let code_offset = reader.take_u32();
+        if code_offset as usize > snr_file.len() || code_offset < SECTION_TABLE_OFFSET {
+            return Err(HeaderError::BadCodeOffset { code_offset });
+        }
+
+        // the offsets table doesn't store its length, but it ends where the first section starts
+        let mut offsets = Vec::new();
+        let mut table_end = code_offset;
+        while reader.position() + 4 <= table_end {
+            let offset = reader.take_u32();
+            if offset < reader.position() || offset > code_offset {
+                // not an offset, so the table must have ended already
+                break;
+            }
+            offsets.push(offset);
+            table_end = table_end.min(offset);
+        }
+
+        let order = section_order(version);
+        let mut bump = Bump::new();
+        let sections = offsets
+            .iter()
+            .enumerate()
+            .map(|(index, &offset)| {
+                // sections are laid out back-to-back
+                let end = offsets
+                    .iter()
+                    .copied()
+                    .filter(|&other| other > offset)
+                    .min()
+                    .unwrap_or(code_offset);
+                let kind = order.map_or(SectionKind::Unknown, |order| {
+                    SectionKind::for_index(order, index)
+                });
+                let data = &snr_file[..end as usize];
+
+                let mut reader = Reader::new(data, offset as usize);
+                let section_size = reader.take_u32();
+                let count = reader.take_u32();
+                let table_end = offset
+                    .checked_add(section_size)
+                    .filter(|&table_end| reader.error().is_none() && table_end <= end);
+                let table = table_end.and_then(|table_end| {
+                    let data = &snr_file[..table_end as usize];
+                    decode_table(
+                        &bump,
+                        kind,
+                        Reader::new(data, offset as usize + 8),
+                        count,
+                        version.string_encoding(),
+                    )
+                });
+                bump.reset();
+
+                Section {
+                    kind,
+                    offset,
+                    size: end - offset,
+                    count,
+                    table,
+                    table_size: section_size,
+                    modified: false,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            size,
+            unknown,
+            code_offset,
+            sections,
+        })
+    }
+
+    /// Returns the first section of the specified kind
+    pub fn section(&self, kind: SectionKind) -> Option<&Section> {
+        self.sections.iter().find(|section| section.kind == kind)
+    }
+
+    /// Returns the translatable strings from the tables, along with the offsets of their sections
+    pub fn strings(&self) -> Vec<(u32, AnyStringSource, &str)> {
+        let mut result = Vec::new();
+        for section in &self.sections {
+            match &section.table {
+                Some(AssetTable::Bgm(entries)) => {
+                    for (index, entry) in (0..).zip(entries) {
+                        let source = AnyStringSource::Singular(StringSource::BgmName(index));
+                        result.push((section.offset, source, entry.display_name.as_str()));
+                    }
+                }
+                Some(AssetTable::Tips(entries)) => {
+                    for (index, entry) in (0..).zip(entries) {
+                        let title = AnyStringSource::Singular(StringSource::TipsTitle(index));
+                        let content = AnyStringSource::Singular(StringSource::TipsContent(index));
+                        result.push((section.offset, title, entry.title.as_str()));
+                        result.push((section.offset, content, entry.content.as_str()));
+                    }
+                }
+                Some(AssetTable::CharacterBox(entries)) => {
+                    for (index, entry) in (0..).zip(entries) {
+                        let source = AnyStringSource::Singular(StringSource::CharacterName(index));
+                        result.push((section.offset, source, entry.name.as_str()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+
+    /// Replaces the translatable strings (see [`SnrHeader::strings`]) with the ones returned by `rewrite`, if any
+    pub fn rewrite_strings(
+        &mut self,
+        mut rewrite: impl FnMut(u32, AnyStringSource, &str) -> Option<String>,
+    ) {
+        for section in &mut self.sections {
+            let offset = section.offset;
+            let mut modified = false;
+            let mut rewrite = |source: StringSource, s: &mut String| {
+                if let Some(replacement) = rewrite(offset, AnyStringSource::Singular(source), s)
+                    && replacement != *s
+                {
+                    *s = replacement;
+                    modified = true;
+                }
+            };
+
+            match &mut section.table {
+                Some(AssetTable::Bgm(entries)) => {
+                    for (index, entry) in (0..).zip(entries) {
+                        rewrite(StringSource::BgmName(index), &mut entry.display_name);
+                    }
+                }
+                Some(AssetTable::Tips(entries)) => {
+                    for (index, entry) in (0..).zip(entries) {
+                        rewrite(StringSource::TipsTitle(index), &mut entry.title);
+                        rewrite(StringSource::TipsContent(index), &mut entry.content);
+                    }
+                }
+                Some(AssetTable::CharacterBox(entries)) => {
+                    for (index, entry) in (0..).zip(entries) {
+                        rewrite(StringSource::CharacterName(index), &mut entry.name);
+                    }
+                }
+                _ => {}
+            }
+
+            section.modified |= modified;
+        }
+    }
+
+    /// Encodes the header, up to the start of the code.
+    ///
+    /// The sections that were changed are re-encoded, moving the sections after them (and the code) as needed. Everything else is copied from the original file as-is.
+    /// The file size field is not updated.
+    pub fn encode(
+        &self,
+        snr_file: &[u8],
+        encoding: StringEncoding,
+        char_remap: &CharRemap,
+    ) -> io::Result<Vec<u8>> {
+        let code_offset = self.code_offset as usize;
+        let Some(first_offset) = self.sections.iter().map(|section| section.offset).min() else {
+            return Ok(snr_file[..code_offset].to_vec());
+        };
+        if !self.sections.iter().any(|section| section.modified) {
+            return Ok(snr_file[..code_offset].to_vec());
+        }
+
+        let mut output = snr_file[..first_offset as usize].to_vec();
+
+        // several offsets may point to the same (empty) section, so write each of them only once
+        let mut new_offsets = HashMap::new();
+        let mut sections = self.sections.iter().collect::<Vec<_>>();
+        sections.sort_by_key(|section| section.offset);
+        let bump = Bump::new();
+        for section in sections {
+            if new_offsets.contains_key(&section.offset) {
+                continue;
+            }
+            new_offsets.insert(section.offset, output.len() as u32);
+
+            let start = section.offset as usize;
+            let end = start + section.size as usize;
+            match &section.table {
+                Some(table) if section.modified => {
+                    let mut writer = TableWriter {
+                        bump: &bump,
+                        output: Vec::new(),
+                        encoding,
+                        char_remap,
+                    };
+                    writer.table(table)?;
+
+                    output.extend_from_slice(&(writer.output.len() as u32 + 8).to_le_bytes());
+                    output.extend_from_slice(&section.count.to_le_bytes());
+                    output.extend_from_slice(&writer.output);
+
+                    // the alignment is restored below, but keep whatever else follows the table
+                    let rest = &snr_file[start + section.table_size as usize..end];
+                    if rest.iter().any(|&b| b != 0) {
+                        output.extend_from_slice(rest);
+                    }
+                }
+                _ => output.extend_from_slice(&snr_file[start..end]),
+            }
+        }
+
+        // keep the code aligned the same way it was
+        while output.len() % 16 != code_offset % 16 {
+            output.push(0);
+        }
+
+        let new_code_offset = output.len() as u32;
+        output[0x20..0x24].copy_from_slice(&new_code_offset.to_le_bytes());
+        for (index, section) in self.sections.iter().enumerate() {
+            let at = SECTION_TABLE_OFFSET as usize + index * 4;
+            output[at..at + 4].copy_from_slice(&new_offsets[&section.offset].to_le_bytes());
+        }
+
+        Ok(output)
+    }
+}
+
+impl fmt::Display for SnrHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "File size:   0x{:08x}", self.size)?;
+        writeln!(f, "Code offset: 0x{:08x}", self.code_offset)?;
+        write!(f, "Unknown:    ")?;
+        for value in self.unknown {
+            write!(f, " 0x{:08x}", value)?;
+        }
+        writeln!(f)?;
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<14} {:>10} {:>8} {:>8}  Decoded",
+            "Section", "Offset", "Size", "Entries"
+        )?;
+        for section in &self.sections {
+            writeln!(
+                f,
+                "{:<14} 0x{:08x} {:>8} {:>8}  {}",
+                section.kind.to_string(),
+                section.offset,
+                section.size,
+                section.count,
+                if section.table.is_some() { "yes" } else { "no" }
+            )?;
+        }
+
+        for section in &self.sections {
+            let Some(table) = section.table.as_ref().filter(|table| !table.is_empty()) else {
+                continue;
+            };
+            writeln!(f)?;
+            writeln!(f, "{} ({} entries):", section.kind, table.len())?;
+            match table {
+                AssetTable::Masks(entries) => write_entries(f, entries)?,
+                AssetTable::Pictures(entries) => write_entries(f, entries)?,
+                AssetTable::Bustups(entries) => write_entries(f, entries)?,
+                AssetTable::Bgm(entries) => write_entries(f, entries)?,
+                AssetTable::Se(entries) => write_entries(f, entries)?,
+                AssetTable::Movies(entries) => write_entries(f, entries)?,
+                AssetTable::Voices(entries) => write_entries(f, entries)?,
+                AssetTable::PictureBox(entries) => write_entries(f, entries)?,
+                AssetTable::MusicBox(entries) => write_entries(f, entries)?,
+                AssetTable::CharacterBox(entries) => write_entries(f, entries)?,
+                AssetTable::CharsSprite(entries) => write_entries(f, entries)?,
+                AssetTable::Tips(entries) => write_entries(f, entries)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_entries<T: fmt::Debug>(f: &mut fmt::Formatter<'_>, entries: &[T]) -> fmt::Result {
+    for (index, entry) in entries.iter().enumerate() {
+        writeln!(f, "{:>6}: {:?}", index, entry)?;
+    }
+    Ok(())
+}
+
+struct TableWriter<'a, 'bump> {
+    bump: &'bump Bump,
+    output: Vec<u8>,
+    encoding: StringEncoding,
+    char_remap: &'a CharRemap,
+}
+
+impl TableWriter<'_, '_> {
+    fn string(&mut self, s: &str) -> io::Result<()> {
+        let encoded = match self.encoding {
+            StringEncoding::ShiftJis => {
+                encode_sjis_zstring_with_remap(self.bump, s, false, self.char_remap)?
+            }
+            StringEncoding::Utf8 => encode_utf8_zstring(self.bump, s),
+        };
+        let length = u16::try_from(encoded.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("string is too long to be stored in the header: {:?}", s),
+            )
+        })?;
+        self.u16(length);
+        self.output.extend_from_slice(encoded);
+        Ok(())
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.output.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.output.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i16(&mut self, value: i16) {
+        self.output.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u8_list(&mut self, values: &[u8]) {
+        self.u8(values.len() as u8);
+        self.output.extend_from_slice(values);
+    }
+
+    fn i16_list(&mut self, values: &[i16]) {
+        self.u8(values.len() as u8);
+        for &value in values {
+            self.i16(value);
+        }
+    }
+
+    /// Writes the entries of the table, in the same layout as [`decode_table`] expects
+    fn table(&mut self, table: &AssetTable) -> io::Result<()> {
+        match table {
+            AssetTable::Masks(entries) => {
+                for entry in entries {
+                    self.string(&entry.name)?;
+                }
+            }
+            AssetTable::Pictures(entries) => {
+                for entry in entries {
+                    self.string(&entry.name)?;
+                    self.i16(entry.linked_cg_id);
+                }
+            }
+            AssetTable::Bustups(entries) => {
+                for entry in entries {
+                    self.string(&entry.name)?;
+                    self.string(&entry.emotion)?;
+                    self.u16(entry.lipsync_character_id);
+                }
+            }
+            AssetTable::Bgm(entries) => {
+                for entry in entries {
+                    self.string(&entry.name)?;
+                    self.string(&entry.display_name)?;
+                    self.i16(entry.linked_bgm_id);
+                }
+            }
+            AssetTable::Se(entries) => {
+                for entry in entries {
+                    self.string(&entry.name)?;
+                }
+            }
+            AssetTable::Movies(entries) => {
+                for entry in entries {
+                    self.string(&entry.name)?;
+                    self.i16(entry.linked_picture_id);
+                    self.u16(entry.flags);
+                    self.i16(entry.linked_bgm_id);
+                }
+            }
+            AssetTable::Voices(entries) => {
+                for entry in entries {
+                    self.string(&entry.name_pattern)?;
+                    self.u8_list(&entry.lipsync_character_ids);
+                }
+            }
+            AssetTable::PictureBox(entries) => {
+                for entry in entries {
+                    self.string(&entry.name)?;
+                    self.i16_list(&entry.picture_ids);
+                }
+            }
+            AssetTable::MusicBox(entries) => {
+                for entry in entries {
+                    self.u16(entry.bgm_id);
+                    self.u16(entry.name_index);
+                    self.u16(entry.once_flag);
+                }
+            }
+            AssetTable::CharacterBox(entries) => {
+                for entry in entries {
+                    self.string(&entry.name)?;
+                    self.i16_list(&entry.bustup_ids);
+                }
+            }
+            AssetTable::CharsSprite(entries) => {
+                for entry in entries {
+                    self.u16(entry.episode);
+                    self.u16(entry.character_id);
+                    self.i16(entry.picture_id);
+                }
+            }
+            AssetTable::Tips(entries) => {
+                for entry in entries {
+                    self.u8(entry.episode);
+                    self.u16(entry.title_index);
+                    self.string(&entry.title)?;
+                    self.string(&entry.title_sort_key)?;
+                    self.string(&entry.content)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct TableReader<'a, 'bump> {
+    bump: &'bump Bump,
+    reader: Reader<'a>,
+    encoding: StringEncoding,
+}
+
+impl TableReader<'_, '_> {
+    fn string(&mut self) -> Option<String> {
+        let s = self.reader.take_u16string();
+        let s = decode_zstring(self.bump, self.encoding, s, false).ok()?;
+        Some(s.to_string())
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.reader.take_u8()
+    }
+
+    fn u16(&mut self) -> u16 {
+        self.reader.take_u16()
+    }
+
+    fn i16(&mut self) -> i16 {
+        self.reader.take_u16() as i16
+    }
+
+    fn u8_list(&mut self) -> Vec<u8> {
+        let count = self.reader.take_u8();
+        (0..count).map(|_| self.reader.take_u8()).collect()
+    }
+
+    fn i16_list(&mut self) -> Vec<i16> {
+        let count = self.reader.take_u8();
+        (0..count).map(|_| self.i16()).collect()
+    }
+
+    fn entries<T>(
+        &mut self,
+        count: u32,
+        mut entry: impl FnMut(&mut Self) -> Option<T>,
+    ) -> Option<Vec<T>> {
+        // don't trust the count to allocate, it can be bogus if the layout doesn't match
+        let remaining = self.reader.size() - self.reader.position() as usize;
+        if count as usize > remaining / MIN_ENTRY_SIZE {
+            return None;
+        }
+
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let value = entry(self)?;
+            if self.reader.error().is_some() {
+                return None;
+            }
+            entries.push(value);
+        }
+        Some(entries)
+    }
+}
+
+/// Decodes the section entries, returning `None` if the data doesn't match the expected layout
+fn decode_table(
+    bump: &Bump,
+    kind: SectionKind,
+    reader: Reader,
+    count: u32,
+    encoding: StringEncoding,
+) -> Option<AssetTable> {
+    let mut r = TableReader {
+        bump,
+        reader,
+        encoding,
+    };
+
+    let table = match kind {
+        SectionKind::Masks => {
+            AssetTable::Masks(r.entries(count, |r| Some(MaskInfo { name: r.string()? }))?)
+        }
+        SectionKind::Pictures => AssetTable::Pictures(r.entries(count, |r| {
+            Some(PictureInfo {
+                name: r.string()?,
+                linked_cg_id: r.i16(),
+            })
+        })?),
+        SectionKind::Bustups => AssetTable::Bustups(r.entries(count, |r| {
+            Some(BustupInfo {
+                name: r.string()?,
+                emotion: r.string()?,
+                lipsync_character_id: r.u16(),
+            })
+        })?),
+        SectionKind::Bgm => AssetTable::Bgm(r.entries(count, |r| {
+            Some(BgmInfo {
+                name: r.string()?,
+                display_name: r.string()?,
+                linked_bgm_id: r.i16(),
+            })
+        })?),
+        SectionKind::Se => {
+            AssetTable::Se(r.entries(count, |r| Some(SeInfo { name: r.string()? }))?)
+        }
+        SectionKind::Movies => AssetTable::Movies(r.entries(count, |r| {
+            Some(MovieInfo {
+                name: r.string()?,
+                linked_picture_id: r.i16(),
+                flags: r.u16(),
+                linked_bgm_id: r.i16(),
+            })
+        })?),
+        SectionKind::Voices => AssetTable::Voices(r.entries(count, |r| {
+            Some(VoiceInfo {
+                name_pattern: r.string()?,
+                lipsync_character_ids: r.u8_list(),
+            })
+        })?),
+        SectionKind::PictureBox => AssetTable::PictureBox(r.entries(count, |r| {
+            Some(PictureBoxInfo {
+                name: r.string()?,
+                picture_ids: r.i16_list(),
+            })
+        })?),
+        SectionKind::MusicBox => AssetTable::MusicBox(r.entries(count, |r| {
+            Some(MusicBoxInfo {
+                bgm_id: r.u16(),
+                name_index: r.u16(),
+                once_flag: r.u16(),
+            })
+        })?),
+        SectionKind::CharacterBox => AssetTable::CharacterBox(r.entries(count, |r| {
+            Some(CharacterBoxInfo {
+                name: r.string()?,
+                bustup_ids: r.i16_list(),
+            })
+        })?),
+        SectionKind::CharsSprite => AssetTable::CharsSprite(r.entries(count, |r| {
+            Some(CharsSpriteInfo {
+                episode: r.u16(),
+                character_id: r.u16(),
+                picture_id: r.i16(),
+            })
+        })?),
+        SectionKind::Tips => AssetTable::Tips(r.entries(count, |r| {
+            Some(TipsInfo {
+                episode: r.u8(),
+                title_index: r.u16(),
+                title: r.string()?,
+                title_sort_key: r.string()?,
+                content: r.string()?,
+            })
+        })?),
+        SectionKind::Unknown => return None,
+    };
+
+    // the whole section should be consumed, otherwise the layout is wrong
+    let consumed_all =
+        r.reader.error().is_none() && r.reader.position() as usize == r.reader.size();
+
+    consumed_all.then_some(table)
+}
+
+#[cfg(test)]
+mod test {
+    use shin_text::CharRemap;
+    use shin_versions::{ShinVersion, StringEncoding};
+
+    use super::{AssetTable, SectionKind, SnrHeader, section_order};
+
+    fn string(data: &mut Vec<u8>, s: &[u8]) {
+        data.extend_from_slice(&(s.len() as u16 + 1).to_le_bytes());
+        data.extend_from_slice(s);
+        data.push(0);
+    }
+
+    fn section(data: &mut Vec<u8>, count: u32, entries: &[u8]) {
+        data.extend_from_slice(&(entries.len() as u32 + 8).to_le_bytes());
+        data.extend_from_slice(&count.to_le_bytes());
+        data.extend_from_slice(entries);
+    }
+
+    /// Builds an SNR file with the specified sections, followed by 16 bytes of code
+    fn snr_file(sections: &[(u32, &Vec<u8>)]) -> Vec<u8> {
+        let mut data = b"SNR ".to_vec();
+        data.resize(0x24, 0);
+        let offsets_start = data.len();
+        data.resize(offsets_start + sections.len() * 4, 0);
+        let mut offsets = Vec::new();
+        for &(count, entries) in sections {
+            offsets.push(data.len() as u32);
+            section(&mut data, count, entries);
+        }
+        for (i, offset) in offsets.iter().enumerate() {
+            let at = offsets_start + i * 4;
+            data[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+        let code_offset = data.len() as u32;
+        data[0x20..0x24].copy_from_slice(&code_offset.to_le_bytes());
+        data.extend_from_slice(&[0; 16]);
+        data
+    }
+
+    #[test]
+    fn parse() {
+        let mut masks = Vec::new();
+        string(&mut masks, b"mask1");
+        string(&mut masks, b"mask2");
+        let mut pictures = Vec::new();
+        string(&mut pictures, b"bg01");
+        pictures.extend_from_slice(&(-1i16).to_le_bytes());
+        // a bustup entry with a missing field doesn't match the layout
+        let mut bustups = Vec::new();
+        string(&mut bustups, b"kei");
+
+        let data = snr_file(&[(2, &masks), (1, &pictures), (1, &bustups)]);
+
+        let header = SnrHeader::parse(&data, ShinVersion::Umineko).unwrap();
+
+        assert_eq!(header.code_offset as usize, data.len() - 16);
+        assert_eq!(header.sections.len(), 3);
+
+        let masks = header.section(SectionKind::Masks).unwrap();
+        let Some(AssetTable::Masks(masks)) = &masks.table else {
+            panic!("unexpected table: {:?}", masks.table);
+        };
+        let names = masks.iter().map(|m| m.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["mask1", "mask2"]);
+
+        let pictures = header.section(SectionKind::Pictures).unwrap();
+        let Some(AssetTable::Pictures(pictures)) = &pictures.table else {
+            panic!("unexpected table: {:?}", pictures.table);
+        };
+        assert_eq!(pictures[0].name, "bg01");
+        assert_eq!(pictures[0].linked_cg_id, -1);
+
+        let bustups = header.section(SectionKind::Bustups).unwrap();
+        assert_eq!(bustups.count, 1);
+        assert!(bustups.table.is_none());
+    }
+
+    #[test]
+    fn rewrite() {
+        let mut bgm = Vec::new();
+        string(&mut bgm, b"bgm01");
+        string(&mut bgm, b"Summer");
+        bgm.extend_from_slice(&(-1i16).to_le_bytes());
+        let mut masks = Vec::new();
+        string(&mut masks, b"mask1");
+
+        // the sections are identified by their position in the table
+        let data = snr_file(&[(1, &masks), (0, &vec![]), (0, &vec![]), (1, &bgm)]);
+        let mut header = SnrHeader::parse(&data, ShinVersion::Umineko).unwrap();
+
+        let strings = header.strings();
+        assert_eq!(strings.len(), 1);
+        assert_eq!(strings[0].2, "Summer");
+
+        // nothing changed, so the header is copied as-is
+        let encoded = header
+            .encode(&data, StringEncoding::Utf8, &CharRemap::new())
+            .unwrap();
+        assert_eq!(encoded, &data[..header.code_offset as usize]);
+
+        header.rewrite_strings(|_, _, _| Some("A much longer summer".to_string()));
+        let mut encoded = header
+            .encode(&data, StringEncoding::Utf8, &CharRemap::new())
+            .unwrap();
+        // the alignment of the code is kept
+        assert_eq!(encoded.len() % 16, header.code_offset as usize % 16);
+        encoded.extend_from_slice(&[0; 16]);
+
+        let rewritten = SnrHeader::parse(&encoded, ShinVersion::Umineko).unwrap();
+        assert_eq!(rewritten.code_offset as usize, encoded.len() - 16);
+        let Some(AssetTable::Bgm(bgm)) = &rewritten.section(SectionKind::Bgm).unwrap().table else {
+            panic!("unexpected sections: {:?}", rewritten.sections);
+        };
+        assert_eq!(bgm[0].name, "bgm01");
+        assert_eq!(bgm[0].display_name, "A much longer summer");
+        assert_eq!(bgm[0].linked_bgm_id, -1);
+        let Some(AssetTable::Masks(masks)) = &rewritten.section(SectionKind::Masks).unwrap().table
+        else {
+            panic!("unexpected sections: {:?}", rewritten.sections);
+        };
+        assert_eq!(masks[0].name, "mask1");
+    }
+
+    #[test]
+    fn bogus_count() {
+        let mut masks = Vec::new();
+        string(&mut masks, b"mask1");
+
+        let data = snr_file(&[(u32::MAX, &masks)]);
+
+        let header = SnrHeader::parse(&data, ShinVersion::Umineko).unwrap();
+        let masks = header.section(SectionKind::Masks).unwrap();
+        assert_eq!(masks.count, u32::MAX);
+        assert!(masks.table.is_none());
+    }
+
+    #[test]
+    fn character_box() {
+        let mut character_box = Vec::new();
+        string(&mut character_box, b"Keiichi");
+        character_box.push(2);
+        character_box.extend_from_slice(&3i16.to_le_bytes());
+        character_box.extend_from_slice(&(-1i16).to_le_bytes());
+
+        let empty = vec![];
+        let mut sections = vec![(0, &empty); 9];
+        sections.push((1, &character_box));
+        let data = snr_file(&sections);
+
+        let header = SnrHeader::parse(&data, ShinVersion::Umineko).unwrap();
+        let Some(AssetTable::CharacterBox(characters)) =
+            &header.section(SectionKind::CharacterBox).unwrap().table
+        else {
+            panic!("unexpected sections: {:?}", header.sections);
+        };
+        assert_eq!(characters[0].name, "Keiichi");
+        assert_eq!(characters[0].bustup_ids, [3, -1]);
+
+        let strings = header.strings();
+        assert_eq!(strings.len(), 1);
+        assert_eq!(strings[0].2, "Keiichi");
+    }
+
+    #[test]
+    fn unknown_version() {
+        let mut masks = Vec::new();
+        string(&mut masks, b"mask1");
+
+        let data = snr_file(&[(1, &masks)]);
+
+        assert!(section_order(ShinVersion::Umineko).is_some());
+        assert!(section_order(ShinVersion::WorldRe).is_none());
+
+        // the sections are still listed, but not identified or decoded
+        let header = SnrHeader::parse(&data, ShinVersion::WorldRe).unwrap();
+        assert_eq!(header.sections.len(), 1);
+        assert_eq!(header.sections[0].kind, SectionKind::Unknown);
+        assert_eq!(header.sections[0].count, 1);
+        assert!(header.sections[0].table.is_none());
+    }
+}
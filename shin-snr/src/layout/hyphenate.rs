@@ -0,0 +1,82 @@
+//! Finding the places where the words can be broken with a hyphen, using the Liang patterns bundled with the `hyphenation` crate.
+//!
+//! The patterns take several megabytes, so they are only bundled with the `hyphenation` feature. Without it, no language can be loaded.
+
+#[cfg(feature = "hyphenation")]
+use hyphenation::{Hyphenator as _, Language, Load as _, Standard};
+
+#[derive(Debug, thiserror::Error)]
+pub enum HyphenatorError {
+    #[error(
+        "Unknown hyphenation language `{0}`. Use a language code like `en-us`, `de-1996` or `ru`"
+    )]
+    UnknownLanguage(String),
+    #[error(
+        "Hyphenation is not supported, as shin-snr was built without the `hyphenation` feature"
+    )]
+    Unsupported,
+}
+
+/// Hyphenation patterns for a single language
+pub struct Hyphenator {
+    #[cfg(feature = "hyphenation")]
+    dictionary: Standard,
+}
+
+#[cfg(feature = "hyphenation")]
+impl Hyphenator {
+    /// Loads the bundled patterns for the language, identified by its code (like `en-us`, `de-1996` or `ru`)
+    pub fn for_language(code: &str) -> Result<Self, HyphenatorError> {
+        let language = Language::try_from_code(code.to_ascii_lowercase())
+            .ok_or_else(|| HyphenatorError::UnknownLanguage(code.to_string()))?;
+        let dictionary = Standard::from_embedded(language)
+            .expect("BUG: the patterns for all the languages should be bundled");
+
+        Ok(Self { dictionary })
+    }
+
+    /// Returns the byte offsets in the word where it can be broken with a hyphen
+    pub fn hyphenate_word(&self, word: &str) -> Vec<usize> {
+        self.dictionary.hyphenate(word).breaks
+    }
+}
+
+#[cfg(not(feature = "hyphenation"))]
+impl Hyphenator {
+    /// Always fails, as the patterns are not bundled without the `hyphenation` feature
+    pub fn for_language(_code: &str) -> Result<Self, HyphenatorError> {
+        Err(HyphenatorError::Unsupported)
+    }
+
+    /// Returns the byte offsets in the word where it can be broken with a hyphen
+    pub fn hyphenate_word(&self, _word: &str) -> Vec<usize> {
+        Vec::new()
+    }
+}
+
+#[cfg(all(test, feature = "hyphenation"))]
+mod test {
+    use super::Hyphenator;
+
+    #[test]
+    fn hyphenate() {
+        let hyphenator = Hyphenator::for_language("de-1996").unwrap();
+        let word = "Silbentrennung";
+        let breaks = hyphenator.hyphenate_word(word);
+        let syllables = std::iter::once(0)
+            .chain(breaks.iter().copied())
+            .zip(breaks.iter().copied().chain(std::iter::once(word.len())))
+            .map(|(start, end)| &word[start..end])
+            .collect::<Vec<_>>();
+        assert_eq!(syllables, ["Sil", "ben", "tren", "nung"]);
+
+        // the offsets are in bytes
+        let hyphenator = Hyphenator::for_language("RU").unwrap();
+        let word = "переносить";
+        for offset in hyphenator.hyphenate_word(word) {
+            assert!(word.is_char_boundary(offset));
+        }
+
+        assert!(Hyphenator::for_language("klingon").is_err());
+    }
+}
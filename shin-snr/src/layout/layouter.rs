@@ -45,7 +45,8 @@ pub struct LightLayouter<'bump, 'font, 's> {
     current_scale: f32,
     default_scale: f32,
     overall_scale_factor: f32,
-    furigana_open: bool, // TODO: newer versions auto-close furigana
+    furigana_open: bool,
+    auto_close_furigana: bool,
     furigana_start_x: f32,
     furigana_start_index: usize,
     furigana_content_size: f32,
@@ -60,30 +61,78 @@ pub fn parse_font_scale(int: u32) -> f32 {
     }
 }
 
+/// Returns the first character of the message (including its furigana) that the font doesn't have
+pub fn find_missing_glyph(font_metrics: &FontMetrics, tokens: &[MessageToken]) -> Option<char> {
+    let is_missing = |c: char| font_metrics.get_glyph_metrics(c).is_none();
+
+    for token in tokens {
+        match *token {
+            MessageToken::Literal(c) if is_missing(c) => return Some(c),
+            MessageToken::Command(c)
+                if matches!(
+                    MessageCommand::parse(c.command),
+                    Some(MessageCommand::RubiContent)
+                ) =>
+            {
+                if let Some(c) = c
+                    .argument
+                    .unwrap_or_default()
+                    .chars()
+                    .find(|&c| is_missing(c))
+                {
+                    return Some(c);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct GameLayoutInfo {
     pub default_scale: f32,
     pub overall_scale_factor: f32,
     pub width: f32,
+    /// How many lines fit into the message box, not counting the speaker name
+    pub max_lines: usize,
+    /// Whether furigana left open at the end of the line (or before the next one starts) is closed implicitly
+    pub auto_close_furigana: bool,
 }
 
 impl GameLayoutInfo {
+    /// Returns the message box layout of the version, if it was measured against the game
     pub fn for_version(version: ShinVersion) -> Option<Self> {
         match version {
             ShinVersion::HigurashiSui => Some(GameLayoutInfo {
                 default_scale: 1.0,
                 overall_scale_factor: 1.3333334,
                 width: 1082.0,
+                max_lines: 3,
+                auto_close_furigana: false,
             }),
             _ => None,
         }
     }
 }
 
+/// Size of the text area of the choice buttons
+///
+/// It wasn't measured for any of the versions yet, so it has to be specified by the user.
+#[derive(Debug, Copy, Clone)]
+pub struct ChoiceBoxInfo {
+    /// Width of the text in the choice box, in the same units as [`GameLayoutInfo::width`]
+    pub width: f32,
+    /// How many lines fit into the choice box
+    pub max_lines: usize,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct LightLayouterParams {
     pub default_scale: f32,
     pub overall_scale_factor: f32,
+    pub auto_close_furigana: bool,
 }
 
 impl LightLayouterParams {
@@ -97,6 +146,7 @@ impl From<GameLayoutInfo> for LightLayouterParams {
         Self {
             default_scale: value.default_scale,
             overall_scale_factor: value.overall_scale_factor,
+            auto_close_furigana: value.auto_close_furigana,
         }
     }
 }
@@ -110,6 +160,7 @@ impl<'bump, 'font, 's> LightLayouter<'bump, 'font, 's> {
         let LightLayouterParams {
             default_scale,
             overall_scale_factor,
+            auto_close_furigana,
         } = params.into();
 
         Self {
@@ -119,6 +170,7 @@ impl<'bump, 'font, 's> LightLayouter<'bump, 'font, 's> {
             default_scale,
             overall_scale_factor,
             furigana_open: false,
+            auto_close_furigana,
             furigana_start_x: 0.0,
             furigana_start_index: 0,
             furigana_content_size: 0.0,
@@ -171,6 +223,32 @@ impl<'bump, 'font, 's> LightLayouter<'bump, 'font, 's> {
         width
     }
 
+    fn close_furigana(&mut self) {
+        self.furigana_open = false;
+
+        let furigana_width = self.furigana_content_size;
+        let base_width = self.pos_x - self.furigana_start_x;
+        let base_range = self.furigana_start_index..self.buffer.len();
+        let base_char_count = self.buffer[base_range.clone()]
+            .iter()
+            .filter(|c| matches!(c, Command::Char(_)))
+            .count();
+
+        if base_char_count > 0 && furigana_width > base_width {
+            // base text needs reflowing to fit the furigana text
+            let reflow_space_around = (furigana_width - base_width) / (base_char_count + 1) as f32;
+            let mut reflow_position = reflow_space_around;
+            for command in &mut self.buffer[base_range] {
+                let Command::Char(char) = command else {
+                    continue;
+                };
+                char.pos_x += reflow_position;
+                reflow_position += reflow_space_around;
+            }
+            self.pos_x = self.furigana_start_x + furigana_width;
+        }
+    }
+
     pub fn push(&mut self, value: MessageToken<'s>) -> PushResult {
         match value {
             MessageToken::Command(c) => {
@@ -209,11 +287,18 @@ impl<'bump, 'font, 's> LightLayouter<'bump, 'font, 's> {
                             .argument
                             .expect("RubiContent command should have an argument");
 
+                        // the furigana left open has to be closed with its own size
+                        if self.auto_close_furigana && self.furigana_open {
+                            self.close_furigana();
+                        }
                         self.furigana_content_size = self.measure_furigana(content);
 
                         PushResult::Nothing
                     }
                     MessageCommand::RubiBaseStart => {
+                        if self.auto_close_furigana && self.furigana_open {
+                            self.close_furigana();
+                        }
                         self.furigana_start_x = self.pos_x;
                         self.furigana_start_index = self.buffer.len();
                         self.furigana_open = true;
@@ -221,30 +306,7 @@ impl<'bump, 'font, 's> LightLayouter<'bump, 'font, 's> {
                         PushResult::Nothing
                     }
                     MessageCommand::RubiBaseEnd => {
-                        self.furigana_open = false;
-
-                        let furigana_width = self.furigana_content_size;
-                        let base_width = self.pos_x - self.furigana_start_x;
-                        let base_range = self.furigana_start_index..self.buffer.len();
-                        let base_char_count = self.buffer[base_range.clone()]
-                            .iter()
-                            .filter(|c| matches!(c, Command::Char(_)))
-                            .count();
-
-                        if base_char_count > 0 && furigana_width > base_width {
-                            // base text needs reflowing to fit the furigana text
-                            let reflow_space_around =
-                                (furigana_width - base_width) / (base_char_count + 1) as f32;
-                            let mut reflow_position = reflow_space_around;
-                            for command in &mut self.buffer[base_range] {
-                                let Command::Char(char) = command else {
-                                    continue;
-                                };
-                                char.pos_x += reflow_position;
-                                reflow_position += reflow_space_around;
-                            }
-                            self.pos_x = self.furigana_start_x + furigana_width;
-                        }
+                        self.close_furigana();
 
                         PushResult::Nothing
                     }
@@ -262,7 +324,13 @@ impl<'bump, 'font, 's> LightLayouter<'bump, 'font, 's> {
 
                         PushResult::Nothing
                     }
-                    MessageCommand::Newline => PushResult::ParagraphComplete,
+                    MessageCommand::Newline => {
+                        if self.auto_close_furigana && self.furigana_open {
+                            self.close_furigana();
+                        }
+
+                        PushResult::ParagraphComplete
+                    }
                 };
 
                 if !matches!(command, MessageCommand::Unicode) {
@@ -346,6 +414,7 @@ pub fn validate_light_layouter_against_dump(
         LightLayouterParams {
             default_scale: 0.0,
             overall_scale_factor: 0.0,
+            auto_close_furigana: false,
         },
     );
 
@@ -361,6 +430,8 @@ pub fn validate_light_layouter_against_dump(
                     LightLayouterParams {
                         default_scale: parse_font_scale(params.default_scale.try_into().unwrap()),
                         overall_scale_factor: params.font_scale,
+                        // the dumps are only made for the vita versions
+                        auto_close_furigana: false,
                     },
                 );
             }
@@ -402,7 +473,7 @@ pub(super) mod test {
     use std::sync::LazyLock;
 
     use bumpalo::Bump;
-    use shin_font::FontMetrics;
+    use shin_font::{FontMetrics, test_util::TestFont};
     use shin_versions::{MessageCommandStyle, ShinVersion};
 
     use crate::layout::{
@@ -470,6 +541,47 @@ pub(super) mod test {
         println!("{:?}", paragraphs);
     }
 
+    #[test]
+    fn auto_close_furigana() {
+        let font = TestFont::monospace().metrics();
+        let positions = |auto_close_furigana| {
+            let mut tokens = Vec::new();
+            message_parser::parse(
+                MessageCommandStyle::Escaped,
+                "@bkanakana.@<x@baa.@<y@>",
+                &mut tokens,
+            );
+
+            let bump = Bump::new();
+            let mut layouter = LightLayouter::new(
+                &bump,
+                &font,
+                LightLayouterParams {
+                    default_scale: 1.0,
+                    overall_scale_factor: 1.0,
+                    auto_close_furigana,
+                },
+            );
+            for token in tokens {
+                layouter.push(token);
+            }
+
+            layouter
+                .peek_buffer()
+                .iter()
+                .filter_map(|command| match command {
+                    Command::Char(c) => Some((c.codepoint, c.pos_x)),
+                    Command::Unparsed(_) => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the second furigana closes the first one, so its base is spread out to fit it
+        assert_eq!(positions(true), [('x', 13.0), ('y', 36.0)]);
+        // otherwise the first one is left open and its base is laid out as-is
+        assert_eq!(positions(false), [('x', 0.0), ('y', 10.0)]);
+    }
+
     #[test]
     fn test_against_dump() {
         super::validate_light_layouter_against_dump(&RESOURCES.metrics, &RESOURCES.layout_dump);
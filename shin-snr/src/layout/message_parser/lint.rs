@@ -1,14 +1,26 @@
-use bumpalo::{collections::Vec, Bump};
+use bumpalo::{Bump, collections::Vec};
 use miette::{SourceOffset, SourceSpan};
-use shin_versions::MessageCommandStyle;
+use shin_font::FontMetrics;
+use shin_text::{CharRemap, FixupDetectResult};
+use shin_versions::{
+    AnyStringKind, LengthKind, MessageCommandStyle, ShinVersion, StringEncoding, StringPolicy,
+};
 
 use crate::{
-    layout::message_parser::{lint::diagnostics::LineReport, MessageCommand, MessageToken},
-    reactor::AnyStringSource,
+    layout::{
+        layouter::{ChoiceBoxInfo, find_missing_glyph},
+        message_parser::{
+            CommandToken, MessageCommand, MessageReflowMode, MessageToken,
+            lint::diagnostics::LineReport,
+        },
+        reflow::{self, ChoiceFitPolicy, ReflowAlgorithm, ReflowParams},
+    },
+    reactor::{AnyStringSource, StringArraySource, StringSource},
+    substitution::{self, SubstitutionTable},
 };
 
 pub mod diagnostics {
-    use miette::{Diagnostic, SourceSpan};
+    use miette::{Diagnostic, SourceOffset, SourceSpan};
     use thiserror::Error;
 
     #[derive(Diagnostic, Debug, Error)]
@@ -28,6 +40,127 @@ pub mod diagnostics {
         pub err_span: SourceSpan,
     }
 
+    #[derive(Diagnostic, Debug, Error)]
+    #[error(
+        "String is too long: it takes {length} bytes when encoded, but at most {limit} bytes fit"
+    )]
+    pub struct StringTooLong {
+        #[label]
+        pub err_span: SourceSpan,
+        pub length: usize,
+        pub limit: usize,
+        #[help]
+        pub advice: Option<&'static str>,
+    }
+
+    #[derive(Diagnostic, Debug, Error)]
+    #[error("Character `{character}` (U+{code:04X}) can't be encoded")]
+    pub struct UnencodableCharacter {
+        #[label]
+        pub err_span: SourceSpan,
+        pub character: char,
+        pub code: u32,
+        #[help]
+        pub advice: Option<String>,
+    }
+
+    #[derive(Diagnostic, Debug, Error)]
+    #[error("The font has no glyph for `{character}` (U+{code:04X})")]
+    #[diagnostic(help(
+        "The message can't be laid out with this character, so it is not reflowed or checked for overflow"
+    ))]
+    pub struct MissingGlyph {
+        #[label]
+        pub err_span: SourceSpan,
+        pub character: char,
+        pub code: u32,
+    }
+
+    #[derive(Diagnostic, Debug, Error)]
+    #[error("Glossary term `{source_term}` is not translated as `{translation}`")]
+    #[diagnostic(
+        severity(Warning),
+        help(
+            "The original text contains `{source_term}`, so the translation should use `{translation}`"
+        )
+    )]
+    pub struct MissingGlossaryTerm {
+        /// The original string, as the term can't be pointed at in the translation
+        #[source_code]
+        pub original: String,
+        #[label("The term in the original")]
+        pub err_span: SourceSpan,
+        pub source_term: String,
+        pub translation: String,
+    }
+
+    #[derive(Diagnostic, Debug, Error)]
+    #[error("The translation is missing the `{command}` command from the original")]
+    #[diagnostic(
+        severity(Warning),
+        help(
+            "Voice and timing commands should be kept, otherwise voice playback may go out of sync"
+        )
+    )]
+    pub struct MissingTimingCommand {
+        #[label]
+        pub err_span: SourceSpan,
+        pub command: String,
+    }
+
+    #[derive(Diagnostic, Debug, Error)]
+    #[error("The `{command}` command is not in the original")]
+    #[diagnostic(severity(Warning))]
+    pub struct UnexpectedTimingCommand {
+        #[label]
+        pub err_span: SourceSpan,
+        pub command: String,
+    }
+
+    #[derive(Diagnostic, Debug, Error)]
+    #[error("The `{command}` command is out of order compared to the original")]
+    #[diagnostic(
+        severity(Warning),
+        help("Voice and timing commands should be kept in the same order as in the original")
+    )]
+    pub struct ReorderedTimingCommand {
+        #[label]
+        pub err_span: SourceSpan,
+        pub command: String,
+    }
+
+    #[derive(Diagnostic, Debug, Error)]
+    #[error("The message takes {lines} lines, but only {limit} fit into the message box")]
+    #[diagnostic(severity(Warning), help("The message is displayed as:\n{breakdown}"))]
+    pub struct MessageOverflow {
+        #[label]
+        pub err_span: SourceSpan,
+        pub lines: usize,
+        pub limit: usize,
+        /// The rendered lines, one per line, with their widths
+        pub breakdown: String,
+    }
+
+    #[derive(Diagnostic, Debug, Error)]
+    #[error("The choice is {width:.0} units wide, but only {limit:.0} fit into the choice box")]
+    #[diagnostic(severity(Warning))]
+    pub struct ChoiceTooWide {
+        #[label]
+        pub err_span: SourceSpan,
+        pub width: f32,
+        pub limit: f32,
+    }
+
+    #[derive(Diagnostic, Debug, Error)]
+    #[error("The choice takes {lines} lines, but only {limit} fit into the choice box")]
+    #[diagnostic(severity(Warning))]
+    pub struct ChoiceOverflow {
+        #[label]
+        pub err_span: SourceSpan,
+        pub lines: usize,
+        pub limit: usize,
+    }
+
     #[derive(Diagnostic, Debug, Error)]
     pub enum AnyDiagnostic {
         #[error(transparent)]
@@ -36,11 +169,41 @@ pub mod diagnostics {
         #[error(transparent)]
         #[diagnostic(transparent)]
         MissingCommandArgument(#[from] MissingCommandArgument),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        StringTooLong(#[from] StringTooLong),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        UnencodableCharacter(#[from] UnencodableCharacter),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        MissingGlyph(#[from] MissingGlyph),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        MissingGlossaryTerm(#[from] MissingGlossaryTerm),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        MissingTimingCommand(#[from] MissingTimingCommand),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        UnexpectedTimingCommand(#[from] UnexpectedTimingCommand),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        ReorderedTimingCommand(#[from] ReorderedTimingCommand),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        MessageOverflow(#[from] MessageOverflow),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        ChoiceTooWide(#[from] ChoiceTooWide),
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        ChoiceOverflow(#[from] ChoiceOverflow),
     }
 
     #[derive(Diagnostic, Debug, Error)]
     #[diagnostic()]
-    #[error("line #{index} contains errors")]
+    #[error("line #{index} contains issues")]
     pub struct LineReport {
         #[source_code]
         pub src: String,
@@ -48,6 +211,56 @@ pub mod diagnostics {
         #[related]
         pub diagnostics: Vec<AnyDiagnostic>,
     }
+
+    impl AnyDiagnostic {
+        /// Returns the span pointing into the string of the report, if the diagnostic has one
+        fn err_span_mut(&mut self) -> Option<&mut SourceSpan> {
+            Some(match self {
+                AnyDiagnostic::UnknownCommand(d) => &mut d.err_span,
+                AnyDiagnostic::MissingCommandArgument(d) => &mut d.err_span,
+                AnyDiagnostic::StringTooLong(d) => &mut d.err_span,
+                AnyDiagnostic::UnencodableCharacter(d) => &mut d.err_span,
+                AnyDiagnostic::MissingGlyph(d) => &mut d.err_span,
+                AnyDiagnostic::MissingGlossaryTerm(_) => return None,
+                AnyDiagnostic::MissingTimingCommand(d) => &mut d.err_span,
+                AnyDiagnostic::UnexpectedTimingCommand(d) => &mut d.err_span,
+                AnyDiagnostic::ReorderedTimingCommand(d) => &mut d.err_span,
+                AnyDiagnostic::MessageOverflow(d) => &mut d.err_span,
+                AnyDiagnostic::ChoiceTooWide(d) => &mut d.err_span,
+                AnyDiagnostic::ChoiceOverflow(d) => &mut d.err_span,
+            })
+        }
+    }
+
+    impl LineReport {
+        /// Removes the first `len` bytes of the string from the report, moving the spans to point into the rest of it
+        ///
+        /// Used to report the issues in the message without the speaker name joined to it. The issues in the name itself are reported at the start of the message.
+        pub fn strip_prefix(mut self, len: usize) -> Self {
+            if len == 0 {
+                return self;
+            }
+
+            self.src.drain(..len);
+            for diagnostic in &mut self.diagnostics {
+                let Some(span) = diagnostic.err_span_mut() else {
+                    continue;
+                };
+                let start = span.offset().saturating_sub(len);
+                let end = (span.offset() + span.len()).saturating_sub(len);
+                *span = SourceSpan::new(SourceOffset::from(start), end - start);
+            }
+            self
+        }
+
+        /// Checks whether the report contains any diagnostics that are not just warnings
+        pub fn has_errors(&self) -> bool {
+            self.diagnostics.iter().any(|d| {
+                d.severity()
+                    .is_none_or(|severity| severity == miette::Severity::Error)
+            })
+        }
+    }
 }
 
 fn diagnose(
@@ -132,3 +345,609 @@ pub fn lint_string<'bump>(
 
     Err(report)
 }
+
+/// Whether the command affects voice playback or timing
+fn is_timing_command(command: MessageCommand) -> bool {
+    matches!(
+        command,
+        MessageCommand::Voice
+            | MessageCommand::Wait
+            | MessageCommand::VoiceSync
+            | MessageCommand::Sync
+            | MessageCommand::EnableLipsync
+            | MessageCommand::DisableLipsync
+            | MessageCommand::ClickWait
+    )
+}
+
+fn format_command(style: MessageCommandStyle, command: CommandToken) -> String {
+    let prefix = match style {
+        MessageCommandStyle::Escaped => "@",
+        MessageCommandStyle::Unescaped => "",
+    };
+    match command.argument {
+        Some(argument) => format!("{}{}{}.", prefix, command.command, argument),
+        None => format!("{}{}", prefix, command.command),
+    }
+}
+
+/// Reports voice and timing commands that were dropped, added or reordered in the translation, compared to the original
+pub fn lint_command_parity<'bump>(
+    bump: &'bump Bump,
+    sink: &mut std::vec::Vec<diagnostics::AnyDiagnostic>,
+    original: &'bump str,
+    translated: &'bump str,
+    style: MessageCommandStyle,
+    source: AnyStringSource,
+) {
+    if !source.contains_commands() {
+        return;
+    }
+
+    let timing_commands = |tokens: Vec<'bump, super::SpannedMessageToken<'bump>>| {
+        tokens
+            .into_iter()
+            .filter_map(
+                |super::SpannedMessageToken { token, start, end }| match token {
+                    MessageToken::Command(command)
+                        if MessageCommand::parse(command.command)
+                            .is_some_and(is_timing_command) =>
+                    {
+                        Some((
+                            command,
+                            SourceSpan::new(SourceOffset::from(start), end - start),
+                        ))
+                    }
+                    _ => None,
+                },
+            )
+            .collect::<std::vec::Vec<_>>()
+    };
+    let same =
+        |a: CommandToken, b: CommandToken| a.command == b.command && a.argument == b.argument;
+
+    let mut original_tokens = Vec::new_in(bump);
+    super::parse(style, original, &mut original_tokens);
+    let original_commands = timing_commands(original_tokens);
+
+    let mut translated_tokens = Vec::new_in(bump);
+    super::parse(style, translated, &mut translated_tokens);
+    let translated_commands = timing_commands(translated_tokens);
+
+    let mut matched = vec![false; translated_commands.len()];
+    let mut consistent = true;
+    for &(command, _) in &original_commands {
+        let found = translated_commands
+            .iter()
+            .zip(matched.iter_mut())
+            .find(|((translated, _), matched)| !**matched && same(command, *translated));
+        match found {
+            Some((_, matched)) => *matched = true,
+            None => {
+                consistent = false;
+                sink.push(
+                    diagnostics::MissingTimingCommand {
+                        err_span: SourceSpan::new(SourceOffset::from(0), translated.len()),
+                        command: format_command(style, command),
+                    }
+                    .into(),
+                );
+            }
+        }
+    }
+    for (&(command, span), matched) in translated_commands.iter().zip(&matched) {
+        if !matched {
+            consistent = false;
+            sink.push(
+                diagnostics::UnexpectedTimingCommand {
+                    err_span: span,
+                    command: format_command(style, command),
+                }
+                .into(),
+            );
+        }
+    }
+
+    // the same commands are present, but they might be in a different order
+    if consistent
+        && let Some(&(command, span)) = original_commands
+            .iter()
+            .zip(&translated_commands)
+            .find(|((original, _), (translated, _))| !same(*original, *translated))
+            .map(|(_, translated)| translated)
+    {
+        sink.push(
+            diagnostics::ReorderedTimingCommand {
+                err_span: span,
+                command: format_command(style, command),
+            }
+            .into(),
+        );
+    }
+}
+
+/// Reports all the characters in the string that can't be encoded, suggesting replacements from the table
+pub fn lint_encoding(
+    sink: &mut std::vec::Vec<diagnostics::AnyDiagnostic>,
+    decoded: &str,
+    encoding: StringEncoding,
+    substitutions: &SubstitutionTable,
+    char_remap: &CharRemap,
+) {
+    for (start, c) in decoded.char_indices() {
+        if substitution::is_encodable(c, encoding, char_remap) {
+            continue;
+        }
+
+        let advice = match substitutions.suggest(c) {
+            Some(replacement) => format!(
+                "replace it with `{}`, or pass --auto-substitute to do it automatically",
+                replacement
+            ),
+            None => "replace it with a similar character, add it to the --substitution-table or assign it a codepoint with --char-remap"
+                .to_string(),
+        };
+
+        sink.push(
+            diagnostics::UnencodableCharacter {
+                err_span: SourceSpan::new(SourceOffset::from(start), c.len_utf8()),
+                character: c,
+                code: c as u32,
+                advice: Some(advice),
+            }
+            .into(),
+        );
+    }
+}
+
+/// Reports the characters (including the furigana) that the font doesn't have, as the layouter can't measure them
+pub fn lint_missing_glyphs<'bump>(
+    bump: &'bump Bump,
+    sink: &mut std::vec::Vec<diagnostics::AnyDiagnostic>,
+    decoded: &'bump str,
+    style: MessageCommandStyle,
+    source: AnyStringSource,
+    font_metrics: &FontMetrics,
+) {
+    if !source.contains_commands() {
+        return;
+    }
+
+    let mut tokens = Vec::new_in(bump);
+    super::parse(style, decoded, &mut tokens);
+    for super::SpannedMessageToken { token, start, end } in tokens {
+        if let Some(c) = find_missing_glyph(font_metrics, &[token]) {
+            sink.push(
+                diagnostics::MissingGlyph {
+                    err_span: SourceSpan::new(SourceOffset::from(start), end - start),
+                    character: c,
+                    code: c as u32,
+                }
+                .into(),
+            );
+        }
+    }
+}
+
+/// Calculates the size of the string as it will be written to the SNR file, including the zero terminator.
+///
+/// The messages are reflowed the same way the rewrite does it, so the inserted line breaks are counted too. With [`MessageReflowMode::GreedySplit`], each page is written as a separate message, so the size of the longest page is returned.
+///
+/// Returns `None` if the string can't be encoded.
+pub fn measure_encoded(
+    bump: &Bump,
+    decoded: &str,
+    style: MessageCommandStyle,
+    version: ShinVersion,
+    source: AnyStringSource,
+    reflow: MessageReflowMode,
+    char_remap: &CharRemap,
+) -> Option<usize> {
+    let measure = |decoded, reflow| match version.string_policy() {
+        StringPolicy::ShiftJis(policy) => {
+            // the original string is not known here, so assume it was fixed up like most of them are
+            let (transformed, fixup) = super::transform_reflow_and_infer_fixup_policy(
+                bump,
+                decoded,
+                style,
+                reflow,
+                version.message_command_style(),
+                // we are not interested in the sanity check here
+                true,
+                policy,
+                FixupDetectResult::FixedUp,
+                source,
+            );
+            shin_text::measure_sjis_zstring_with_remap(transformed, fixup, char_remap).ok()
+        }
+        StringPolicy::Utf8 => {
+            let transformed = super::transform_reflow(
+                bump,
+                decoded,
+                style,
+                reflow,
+                version.message_command_style(),
+                true,
+                source,
+            );
+            Some(transformed.len() + 1)
+        }
+    };
+
+    let decoded = bump.alloc_str(decoded);
+    match reflow {
+        MessageReflowMode::GreedySplit(params) if source.is_for_messagebox() => {
+            super::reflow_split_pages(bump, decoded, style, params)
+                .into_iter()
+                .map(|page| measure(page, MessageReflowMode::NoReflow))
+                .try_fold(0, |longest, length| Some(longest.max(length?)))
+        }
+        reflow => measure(decoded, reflow),
+    }
+}
+
+/// Settings for checking that the translations fit into the message and choice boxes
+#[derive(Clone, Copy)]
+pub struct OverflowCheck<'a> {
+    pub params: ReflowParams<'a>,
+    /// How the messages are reflowed before counting their lines
+    pub algorithm: ReflowAlgorithm,
+    /// Whether the messages that don't fit are split into pages (see [`MessageReflowMode::GreedySplit`]), so only the voiced messages, which are not split, and the choices need to be checked
+    pub split_pages: bool,
+    /// The size of the choice box and how the choices are made to fit before checking them. Without it, the choices are not checked
+    pub choices: Option<(ChoiceBoxInfo, ChoiceFitPolicy)>,
+}
+
+/// Lays out the string and reports the messages that have more lines than the message box can fit, and the choices that don't fit into the choice box.
+///
+/// The messages are reflowed and the choices are fitted first, the same way the rewrite would do it.
+pub fn lint_overflow(
+    bump: &Bump,
+    sink: &mut std::vec::Vec<diagnostics::AnyDiagnostic>,
+    decoded: &str,
+    style: MessageCommandStyle,
+    source: AnyStringSource,
+    check: &OverflowCheck,
+) {
+    let err_span = SourceSpan::new(SourceOffset::from(0), decoded.len());
+    let mut tokens = Vec::new_in(bump);
+    super::parse(style, decoded, &mut tokens);
+    // such strings can't be laid out, they are reported by `lint_missing_glyphs`
+    if find_missing_glyph(check.params.metrics, &tokens).is_some() {
+        return;
+    }
+
+    match source {
+        AnyStringSource::Singular(StringSource::Msgset(_))
+            if !check.split_pages || reflow::is_voiced(&tokens) =>
+        {
+            let lines = reflow::render_message(bump, check.params, check.algorithm, &tokens);
+            if lines.len() > check.params.layout.max_lines {
+                let breakdown = lines
+                    .iter()
+                    .enumerate()
+                    .map(|(index, line)| {
+                        format!("{:>3}: {} ({:.0})", index + 1, line.text, line.width)
+                    })
+                    .collect::<std::vec::Vec<_>>()
+                    .join("\n");
+                sink.push(
+                    diagnostics::MessageOverflow {
+                        err_span,
+                        lines: lines.len(),
+                        limit: check.params.layout.max_lines,
+                        breakdown,
+                    }
+                    .into(),
+                );
+            }
+        }
+        AnyStringSource::Array(StringArraySource::Select, _) => {
+            let Some((choice_box, choice_fit)) = check.choices else {
+                return;
+            };
+            let mut fitted = Vec::new_in(bump);
+            reflow::fit_choice(
+                bump,
+                check.params,
+                choice_box,
+                choice_fit,
+                &tokens,
+                &mut fitted,
+            );
+
+            let lines = 1 + fitted
+                .iter()
+                .filter(|token| {
+                    matches!(
+                        token,
+                        MessageToken::Command(CommandToken { command, .. })
+                            if matches!(MessageCommand::parse(*command), Some(MessageCommand::Newline))
+                    )
+                })
+                .count();
+            if lines > choice_box.max_lines {
+                sink.push(
+                    diagnostics::ChoiceOverflow {
+                        err_span,
+                        lines,
+                        limit: choice_box.max_lines,
+                    }
+                    .into(),
+                );
+            }
+
+            let width =
+                reflow::measure_width(bump, check.params.metrics, check.params.layout, &fitted);
+            if width > choice_box.width {
+                sink.push(
+                    diagnostics::ChoiceTooWide {
+                        err_span,
+                        width,
+                        limit: choice_box.width,
+                    }
+                    .into(),
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the maximum encoded size of the string (or the whole string array) of this kind
+pub fn length_limit(version: ShinVersion, kind: AnyStringKind) -> usize {
+    let style = match kind {
+        AnyStringKind::Singular(kind) => version.string_style(kind),
+        AnyStringKind::Array(kind) => version.string_array_style(kind),
+    };
+
+    match style.size_kind {
+        LengthKind::U8Length => u8::MAX as usize,
+        LengthKind::U16Length => u16::MAX as usize,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+    use shin_font::test_util::TestFont;
+    use shin_text::CharRemap;
+    use shin_versions::{MessageCommandStyle, ShinVersion};
+
+    use super::{
+        OverflowCheck, diagnostics::AnyDiagnostic, lint_command_parity, lint_missing_glyphs,
+        lint_overflow, measure_encoded,
+    };
+    use crate::{
+        layout::{
+            layouter::{ChoiceBoxInfo, GameLayoutInfo},
+            message_parser::MessageReflowMode,
+            reflow::{ChoiceFitPolicy, ReflowAlgorithm, ReflowParams},
+        },
+        reactor::{AnyStringSource, StringArraySource, StringSource},
+    };
+
+    fn parity(original: &str, translated: &str) -> Vec<AnyDiagnostic> {
+        let bump = Bump::new();
+        let mut sink = Vec::new();
+        lint_command_parity(
+            &bump,
+            &mut sink,
+            bump.alloc_str(original),
+            bump.alloc_str(translated),
+            MessageCommandStyle::Escaped,
+            AnyStringSource::Singular(StringSource::Msgset(0)),
+        );
+        sink
+    }
+
+    #[test]
+    fn command_parity() {
+        // layout-only commands like `@r` and `@c` are not compared
+        assert!(parity("@v001.「はい」@k@w10.", "@v001.@c999.\"Yes\"@k@w10.").is_empty());
+
+        let diagnostics = parity("@v001.「はい」@k@w10.", "\"Yes\"@w10.@y");
+        let [
+            AnyDiagnostic::MissingTimingCommand(voice),
+            AnyDiagnostic::MissingTimingCommand(click_wait),
+            AnyDiagnostic::UnexpectedTimingCommand(sync),
+        ] = diagnostics.as_slice()
+        else {
+            panic!("unexpected diagnostics: {:?}", diagnostics);
+        };
+        assert_eq!(voice.command, "@v001.");
+        assert_eq!(click_wait.command, "@k");
+        assert_eq!(sync.command, "@y");
+
+        let diagnostics = parity("@v001.「はい」@k@w10.", "@v001.\"Yes\"@w10.@k");
+        let [AnyDiagnostic::ReorderedTimingCommand(reordered)] = diagnostics.as_slice() else {
+            panic!("unexpected diagnostics: {:?}", diagnostics);
+        };
+        assert_eq!(reordered.command, "@w10.");
+    }
+
+    #[test]
+    fn measure_reflowed() {
+        let font = TestFont::monospace().metrics();
+        let version = ShinVersion::HigurashiHou;
+        let params = ReflowParams {
+            metrics: &font,
+            layout: GameLayoutInfo {
+                width: 100.0,
+                max_lines: 1,
+                default_scale: 1.0,
+                overall_scale_factor: 1.0,
+                auto_close_furigana: false,
+            },
+            hyphenator: None,
+        };
+
+        let measure = |reflow| {
+            let bump = Bump::new();
+            measure_encoded(
+                &bump,
+                "aaaa bbbb cccc dddd",
+                MessageCommandStyle::Escaped,
+                version,
+                AnyStringSource::Singular(StringSource::Msgset(0)),
+                reflow,
+                &CharRemap::new(),
+            )
+        };
+
+        assert_eq!(measure(MessageReflowMode::NoReflow), Some(20));
+        // the inserted `@r` is counted
+        assert_eq!(measure(MessageReflowMode::Greedy(params)), Some(22));
+        // each page is a separate message
+        assert_eq!(measure(MessageReflowMode::GreedySplit(params)), Some(11));
+    }
+
+    #[test]
+    fn measure_fixed_up() {
+        let measure = |s| {
+            let bump = Bump::new();
+            measure_encoded(
+                &bump,
+                s,
+                MessageCommandStyle::Escaped,
+                ShinVersion::HigurashiHou,
+                AnyStringSource::Singular(StringSource::Msgset(0)),
+                MessageReflowMode::NoReflow,
+                &CharRemap::new(),
+            )
+        };
+
+        // the hiragana after the first line are encoded as single bytes
+        assert_eq!(measure("あい@rあい"), Some(4 + 2 + 2 + 1));
+    }
+
+    #[test]
+    fn overflow() {
+        let font = TestFont::monospace().metrics();
+        let check = OverflowCheck {
+            params: ReflowParams {
+                metrics: &font,
+                layout: GameLayoutInfo {
+                    width: 100.0,
+                    max_lines: 2,
+                    default_scale: 1.0,
+                    overall_scale_factor: 1.0,
+                    auto_close_furigana: false,
+                },
+                hyphenator: None,
+            },
+            algorithm: ReflowAlgorithm::Greedy,
+            split_pages: false,
+            choices: Some((
+                ChoiceBoxInfo {
+                    width: 100.0,
+                    max_lines: 1,
+                },
+                ChoiceFitPolicy::Keep,
+            )),
+        };
+        let lint = |decoded: &str, source| {
+            let bump = Bump::new();
+            let mut sink = Vec::new();
+            lint_overflow(
+                &bump,
+                &mut sink,
+                decoded,
+                MessageCommandStyle::Escaped,
+                source,
+                &check,
+            );
+            sink
+        };
+        let message = |decoded| lint(decoded, AnyStringSource::Singular(StringSource::Msgset(0)));
+
+        assert!(message("aaaa bbbb cccc").is_empty());
+        // neither the speaker name nor an empty one take a line
+        assert!(message("[Name]@raaaa bbbb cccc").is_empty());
+        assert!(message("@raaaa@rbbbb").is_empty());
+
+        let diagnostics = message("[Name]@raaaa bbbb cccc dddd eeee");
+        let [AnyDiagnostic::MessageOverflow(overflow)] = diagnostics.as_slice() else {
+            panic!("unexpected diagnostics: {:?}", diagnostics);
+        };
+        assert_eq!(overflow.lines, 3);
+        assert_eq!(overflow.limit, 2);
+        assert_eq!(
+            overflow.breakdown,
+            "  1: aaaa bbbb  (100)\n  2: cccc dddd  (100)\n  3: eeee (40)"
+        );
+
+        let choice = |decoded| {
+            lint(
+                decoded,
+                AnyStringSource::Array(StringArraySource::Select, 0),
+            )
+        };
+        assert!(choice("aaaa bbbb").is_empty());
+        let diagnostics = choice("aaaa bbbb cccc");
+        let [AnyDiagnostic::ChoiceTooWide(too_wide)] = diagnostics.as_slice() else {
+            panic!("unexpected diagnostics: {:?}", diagnostics);
+        };
+        assert_eq!(too_wide.width, 140.0);
+
+        // the split pages fit, but the voiced messages are not split
+        let split = OverflowCheck {
+            split_pages: true,
+            ..check
+        };
+        let lint_split = |decoded| {
+            let bump = Bump::new();
+            let mut sink = Vec::new();
+            lint_overflow(
+                &bump,
+                &mut sink,
+                decoded,
+                MessageCommandStyle::Escaped,
+                AnyStringSource::Singular(StringSource::Msgset(0)),
+                &split,
+            );
+            sink
+        };
+        assert!(lint_split("aaaa bbbb cccc dddd eeee").is_empty());
+        let diagnostics = lint_split("@vvoice.aaaa bbbb cccc dddd eeee");
+        let [AnyDiagnostic::MessageOverflow(overflow)] = diagnostics.as_slice() else {
+            panic!("unexpected diagnostics: {:?}", diagnostics);
+        };
+        assert_eq!(overflow.lines, 3);
+
+        // the strings the font can't display are reported by `lint_missing_glyphs` instead
+        assert!(message("aaaa bbbb cccc dddd 😀").is_empty());
+        assert!(choice("aaaa bbbb 😀").is_empty());
+    }
+
+    #[test]
+    fn missing_glyphs() {
+        let font = TestFont::monospace().metrics();
+        let bump = Bump::new();
+        let mut sink = Vec::new();
+        lint_missing_glyphs(
+            &bump,
+            &mut sink,
+            "a😀b@b🍣.@<c@>",
+            MessageCommandStyle::Escaped,
+            AnyStringSource::Singular(StringSource::Msgset(0)),
+            &font,
+        );
+
+        let [
+            AnyDiagnostic::MissingGlyph(literal),
+            AnyDiagnostic::MissingGlyph(furigana),
+        ] = sink.as_slice()
+        else {
+            panic!("unexpected diagnostics: {:?}", sink);
+        };
+        assert_eq!(literal.character, '😀');
+        assert_eq!((literal.err_span.offset(), literal.err_span.len()), (1, 4));
+        // the furigana is reported on its command
+        assert_eq!(furigana.character, '🍣');
+        assert_eq!(
+            (furigana.err_span.offset(), furigana.err_span.len()),
+            (6, 7)
+        );
+    }
+}
@@ -8,19 +8,24 @@ use bumpalo::{
     collections::{String, Vec},
     vec,
 };
-use shin_font::FontMetrics;
 use shin_text::FixupDetectResult;
 use shin_versions::{MessageCommandStyle, SjisMessageFixupPolicy};
 use sink::{CountingStringSink, FullStringSink, StringSink, TokenSink};
 
-use crate::{layout::layouter::GameLayoutInfo, reactor::AnyStringSource};
+use crate::{
+    layout::{
+        layouter::ChoiceBoxInfo,
+        reflow::{ChoiceFitPolicy, ReflowAlgorithm, ReflowParams},
+    },
+    reactor::AnyStringSource,
+};
 
 #[derive(Copy, Clone, Debug)]
 pub enum ParseIntArgError {
     NoArgument,
     InvalidArgument,
 }
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct CommandToken<'b> {
     pub command: char,
     pub argument: Option<&'b str>,
@@ -42,7 +47,7 @@ impl<'b> CommandToken<'b> {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum MessageToken<'b> {
     Literal(char),
     Command(CommandToken<'b>),
@@ -417,6 +422,45 @@ pub fn parse<'bump, S: TokenSink<'bump>>(
     }
 }
 
+/// Splits the speaker name off the start of a message.
+///
+/// Messages often start with the name of the speaking character, followed by a newline command (`語り手@r...`).
+/// If the message has one, returns the name and the rest of the message (starting with the newline command).
+/// See [`super::reflow::speaker_name_len`] for what is considered to be a name. An empty name is not split off.
+pub fn split_speaker_name(style: MessageCommandStyle, message: &str) -> Option<(&str, &str)> {
+    let mut spanned_tokens = std::vec::Vec::<SpannedMessageToken>::new();
+    parse(style, message, &mut spanned_tokens);
+    let tokens = spanned_tokens
+        .iter()
+        .map(|token| token.token)
+        .collect::<std::vec::Vec<_>>();
+
+    match super::reflow::speaker_name_len(&tokens) {
+        // no name or an empty one
+        0 | 1 => None,
+        name_len => Some(message.split_at(spanned_tokens[name_len - 1].start)),
+    }
+}
+
+/// Removes all the layout commands from the message, leaving only the text
+pub fn strip_commands<'bump>(
+    bump: &'bump Bump,
+    style: MessageCommandStyle,
+    message: &'bump str,
+) -> &'bump str {
+    let mut tokens = Vec::new_in(bump);
+    parse(style, message, &mut tokens);
+
+    let mut result = String::with_capacity_in(message.len(), bump);
+    for token in tokens {
+        if let MessageToken::Literal(c) = token {
+            result.push(c);
+        }
+    }
+
+    result.into_bump_str()
+}
+
 pub fn infer_string_fixup_policy<'bump>(
     bump: &'bump Bump,
     decoded: &str,
@@ -455,11 +499,85 @@ pub enum MessageReflowMode<'a> {
     NoReflow,
     /// Use a greedy algorithm to insert hardbreaks (@r) to correctly word-wrap western text.
     ///
-    /// This uses UAX#14 to identify possible line break points.
-    Greedy {
-        metrics: &'a FontMetrics,
-        layout: GameLayoutInfo,
-    },
+    /// This uses UAX#14 to identify possible line break points, and the hyphenation patterns, if enabled.
+    Greedy(ReflowParams<'a>),
+    /// Like [`MessageReflowMode::Greedy`], but messages that don't fit into the message box are split into several pages.
+    ///
+    /// The voiced messages are not split, see [`super::reflow::split_pages`].
+    ///
+    /// The splitting itself is done by the rewriter (see [`reflow_split_pages`]), as it needs to emit additional MSGSET operations.
+    GreedySplit(ReflowParams<'a>),
+    /// Like [`MessageReflowMode::Greedy`], but chooses the line breaks across the whole paragraph to make the lines as even as possible.
+    ///
+    /// See [`super::reflow::reflow_paragraph_optimal`] for details.
+    Optimal(ReflowParams<'a>),
+}
+
+impl<'a> MessageReflowMode<'a> {
+    /// Returns the reflow parameters and the line breaking algorithm if the messages are reflowed
+    pub fn reflow_params(self) -> Option<(ReflowParams<'a>, ReflowAlgorithm)> {
+        match self {
+            MessageReflowMode::NoReflow => None,
+            MessageReflowMode::Greedy(params) | MessageReflowMode::GreedySplit(params) => {
+                Some((params, ReflowAlgorithm::Greedy))
+            }
+            MessageReflowMode::Optimal(params) => Some((params, ReflowAlgorithm::Optimal)),
+        }
+    }
+}
+
+/// Reflows the message and splits it into pages fitting into the message box (see [`super::reflow::split_pages`]).
+///
+/// The pages are serialized back in the same style, ready to be passed to [`transform_reflow_and_infer_fixup_policy`] or [`transform_reflow`] with [`MessageReflowMode::NoReflow`].
+pub fn reflow_split_pages<'bump>(
+    bump: &'bump Bump,
+    decoded: &'bump str,
+    style: MessageCommandStyle,
+    params: ReflowParams,
+) -> Vec<'bump, &'bump str> {
+    let mut tokens = Vec::new_in(bump);
+    parse(style, decoded, &mut tokens);
+
+    // the name is kept as-is, so that the reflow doesn't affect where it ends
+    let name_len = super::reflow::speaker_name_len(&tokens);
+    let mut tokens_out = Vec::from_iter_in(tokens[..name_len].iter().copied(), bump);
+    super::reflow::reflow_message(
+        bump,
+        params,
+        ReflowAlgorithm::Greedy,
+        &tokens[name_len..],
+        &mut tokens_out,
+    );
+
+    let pages = super::reflow::split_pages(bump, params.layout.max_lines, name_len, &tokens_out);
+    let mut result = Vec::with_capacity_in(pages.len(), bump);
+    for page in pages {
+        result.push(serialize_string(bump, style, &page));
+    }
+    result
+}
+
+/// Makes the choice fit into the choice box (see [`super::reflow::fit_choice`]), serializing it back in the same style.
+///
+/// The result is then converted to the style of the SNR file by [`transform_reflow`], like any other string. The choices that fit already are returned as-is.
+pub fn fit_choice<'bump>(
+    bump: &'bump Bump,
+    decoded: &'bump str,
+    style: MessageCommandStyle,
+    params: ReflowParams,
+    choice_box: ChoiceBoxInfo,
+    policy: ChoiceFitPolicy,
+) -> &'bump str {
+    let mut tokens = Vec::new_in(bump);
+    parse(style, decoded, &mut tokens);
+
+    let mut tokens_out = Vec::new_in(bump);
+    super::reflow::fit_choice(bump, params, choice_box, policy, &tokens, &mut tokens_out);
+    if tokens_out == tokens {
+        return decoded;
+    }
+
+    serialize_string(bump, style, &tokens_out)
 }
 
 /// Combines [`transform_reflow`] and [`infer_string_fixup_policy`] into a single pass.
@@ -481,9 +599,9 @@ pub fn transform_reflow_and_infer_fixup_policy<'bump>(
         parse(in_style, decoded, &mut tokens);
 
         if source.is_for_messagebox() {
-            if let MessageReflowMode::Greedy { metrics, layout } = reflow {
+            if let Some((params, algorithm)) = reflow.reflow_params() {
                 let mut tokens_out = Vec::new_in(bump);
-                super::reflow::reflow_message(bump, metrics, layout, &tokens, &mut tokens_out);
+                super::reflow::reflow_message(bump, params, algorithm, &tokens, &mut tokens_out);
                 tokens = tokens_out;
             }
         }
@@ -539,16 +657,18 @@ pub fn transform_reflow<'bump>(
         parse(in_style, decoded, &mut tokens);
 
         if source.is_for_messagebox() {
-            if let MessageReflowMode::Greedy { metrics, layout } = reflow {
+            if let Some((params, algorithm)) = reflow.reflow_params() {
                 let mut tokens_out = Vec::new_in(bump);
-                super::reflow::reflow_message(bump, metrics, layout, &tokens, &mut tokens_out);
+                super::reflow::reflow_message(bump, params, algorithm, &tokens, &mut tokens_out);
                 tokens = tokens_out;
             }
         }
 
         let serialized_string = serialize_string(bump, out_style, &tokens);
 
-        if in_style == out_style {
+        if in_style == out_style
+            && let MessageReflowMode::NoReflow = reflow
+        {
             // WorldRe has a string with needless escapes, triggering this assert
             // This is the problematic string: `r　両目を見開き、こんな感じの顔をした→!(!？Д!？!)`
             if !has_useless_escapes {
@@ -562,3 +682,108 @@ pub fn transform_reflow<'bump>(
         decoded
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+    use shin_font::test_util::TestFont;
+    use shin_versions::{MessageCommandStyle, ShinVersion};
+
+    use super::{MessageReflowMode, fit_choice, split_speaker_name, transform_reflow};
+    use crate::{
+        layout::{
+            layouter::{ChoiceBoxInfo, GameLayoutInfo},
+            reflow::{ChoiceFitPolicy, ReflowParams},
+        },
+        reactor::{AnyStringSource, StringArraySource},
+    };
+
+    #[test]
+    fn speaker_name() {
+        assert_eq!(
+            split_speaker_name(MessageCommandStyle::Escaped, "語り手@r@vhello.Hello."),
+            Some(("語り手", "@r@vhello.Hello."))
+        );
+        assert_eq!(
+            split_speaker_name(MessageCommandStyle::Unescaped, "語り手rvhello.!H!e!l!l!o."),
+            Some(("語り手", "rvhello.!H!e!l!l!o."))
+        );
+        assert_eq!(
+            split_speaker_name(
+                MessageCommandStyle::Escaped,
+                "@rここは自由に駆け回れる庭だった。"
+            ),
+            None
+        );
+        assert_eq!(
+            split_speaker_name(MessageCommandStyle::Escaped, "@c900.語り手@c.@rHello."),
+            None
+        );
+        assert_eq!(
+            split_speaker_name(MessageCommandStyle::Escaped, "Hello."),
+            None
+        );
+
+        // the spoken lines start with a quote
+        assert_eq!(
+            split_speaker_name(MessageCommandStyle::Escaped, "語り手@r「はい」"),
+            Some(("語り手", "@r「はい」"))
+        );
+        assert_eq!(
+            split_speaker_name(MessageCommandStyle::Escaped, "【語り手】@rはい"),
+            Some(("【語り手】", "@rはい"))
+        );
+        // two lines of narration are not a name and a message
+        assert_eq!(
+            split_speaker_name(
+                MessageCommandStyle::Escaped,
+                "雨が降っていた@r僕は傘を差した。"
+            ),
+            None
+        );
+        assert_eq!(
+            split_speaker_name(MessageCommandStyle::Escaped, "彼は言った。@r「はい」"),
+            None
+        );
+    }
+
+    #[test]
+    fn fit_choice_style() {
+        let font = TestFont::monospace().metrics();
+        let params = ReflowParams {
+            metrics: &font,
+            layout: GameLayoutInfo {
+                default_scale: 1.0,
+                overall_scale_factor: 1.0,
+                ..GameLayoutInfo::for_version(ShinVersion::HigurashiSui).unwrap()
+            },
+            hyphenator: None,
+        };
+        let choice_box = ChoiceBoxInfo {
+            width: 100.0,
+            max_lines: 1,
+        };
+        let bump = Bump::new();
+
+        // the choice stays in the user's style, so that the rewriter can convert it like any other string
+        let fitted = fit_choice(
+            &bump,
+            bump.alloc_str("aaaa bbbb cccc"),
+            MessageCommandStyle::Escaped,
+            params,
+            choice_box,
+            ChoiceFitPolicy::Shrink,
+        );
+        assert_eq!(fitted, "@z71.aaaa bbbb cccc");
+        let transformed = transform_reflow(
+            &bump,
+            fitted,
+            MessageCommandStyle::Escaped,
+            MessageReflowMode::NoReflow,
+            MessageCommandStyle::Unescaped,
+            false,
+            AnyStringSource::Array(StringArraySource::Select, 0),
+        );
+        assert_eq!(transformed, "z71.!a!a!a!a! !b!b!b!b! !c!c!c!c");
+    }
+}
@@ -96,6 +96,12 @@ impl<'bump> TokenSink<'bump> for bumpalo::collections::Vec<'bump, MessageToken<'
     }
 }
 
+impl<'bump> TokenSink<'bump> for Vec<SpannedMessageToken<'bump>> {
+    fn push(&mut self, start: usize, end: usize, token: MessageToken<'bump>) {
+        self.push(SpannedMessageToken { start, end, token });
+    }
+}
+
 impl<'bump> TokenSink<'bump> for bumpalo::collections::Vec<'bump, SpannedMessageToken<'bump>> {
     fn push(&mut self, start: usize, end: usize, token: MessageToken<'bump>) {
         self.push(SpannedMessageToken { start, end, token });
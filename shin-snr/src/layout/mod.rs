@@ -1,5 +1,7 @@
+pub mod hyphenate;
 pub mod layout_dump;
 pub mod layouter;
 mod linebreak;
 pub mod message_parser;
-mod reflow;
+pub mod preview;
+pub mod reflow;
@@ -0,0 +1,253 @@
+//! Placing the characters of a message the way the game would draw them, for rendering previews of the translations.
+
+use bumpalo::Bump;
+use shin_font::FontMetrics;
+
+use crate::layout::{
+    layouter::{self, GameLayoutInfo, LightLayouter, PushResult},
+    message_parser::{MessageCommand, MessageToken},
+};
+
+/// Scale of the furigana, the same as the layouter uses to measure it
+pub const FURIGANA_SCALE: f32 = 0.45;
+
+/// Color of the text when no `@c` command is in effect
+pub const DEFAULT_COLOR: [u8; 3] = [0xff, 0xff, 0xff];
+
+/// Parses the argument of the `@c` command: three decimal digits for red, green and blue. Anything else resets the color to the default.
+pub fn parse_color(argument: Option<&str>) -> [u8; 3] {
+    let Some(argument) = argument.filter(|argument| argument.len() == 3) else {
+        return DEFAULT_COLOR;
+    };
+
+    let mut color = [0; 3];
+    for (component, digit) in color.iter_mut().zip(argument.chars()) {
+        let Some(digit) = digit.to_digit(10) else {
+            return DEFAULT_COLOR;
+        };
+        *component = (digit * 255 / 9) as u8;
+    }
+    color
+}
+
+/// A character placed in the message box
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PlacedChar {
+    pub codepoint: char,
+    /// Position of the origin of the glyph, at the left end of its baseline
+    pub x: f32,
+    pub baseline_y: f32,
+    pub scale: f32,
+    pub color: [u8; 3],
+}
+
+/// A message laid out for drawing, in the same units as [`GameLayoutInfo::width`]
+#[derive(Debug, Clone)]
+pub struct MessagePreview {
+    pub chars: Vec<PlacedChar>,
+    pub lines: usize,
+    /// Distance between the tops of the consecutive lines, including the space for furigana above the text
+    pub line_height: f32,
+}
+
+struct Placer<'a> {
+    font_metrics: &'a FontMetrics,
+    line_height: f32,
+    /// Offsets of the furigana and the text baselines from the top of the line
+    furigana_baseline: f32,
+    text_baseline: f32,
+    color: [u8; 3],
+    furigana: Option<&'a str>,
+    /// Horizontal extent of the text the furigana is placed over
+    furigana_base: Option<(f32, f32)>,
+    chars: Vec<PlacedChar>,
+    lines: usize,
+}
+
+impl<'a> Placer<'a> {
+    fn place_furigana(&mut self, top: f32) {
+        let (Some(furigana), Some((left, right))) = (self.furigana, self.furigana_base.take())
+        else {
+            return;
+        };
+
+        let advances = furigana
+            .chars()
+            .map(|c| {
+                let glyph_metrics = self
+                    .font_metrics
+                    .get_glyph_metrics(c)
+                    .expect("could not get glyph metrics");
+                (c, glyph_metrics.advance_width as f32 * FURIGANA_SCALE)
+            })
+            .collect::<Vec<_>>();
+        let width = advances.iter().map(|&(_, advance)| advance).sum::<f32>();
+
+        // the furigana is centered over its base text
+        let mut x = (left + right - width) / 2.0;
+        for (codepoint, advance) in advances {
+            self.chars.push(PlacedChar {
+                codepoint,
+                x,
+                baseline_y: top + self.furigana_baseline,
+                scale: FURIGANA_SCALE,
+                color: self.color,
+            });
+            x += advance;
+        }
+    }
+
+    fn place_line(&mut self, commands: &[layouter::Command<'a>]) {
+        let top = self.lines as f32 * self.line_height;
+
+        for command in commands {
+            match *command {
+                layouter::Command::Char(c) => {
+                    if c.has_furigana {
+                        let (left, right) = self
+                            .furigana_base
+                            .unwrap_or((f32::INFINITY, f32::NEG_INFINITY));
+                        self.furigana_base =
+                            Some((left.min(c.pos_x), right.max(c.pos_x + c.width)));
+                    }
+                    self.chars.push(PlacedChar {
+                        codepoint: c.codepoint,
+                        x: c.pos_x,
+                        baseline_y: top + self.text_baseline,
+                        scale: c.scale,
+                        color: self.color,
+                    });
+                }
+                layouter::Command::Unparsed(c) => match MessageCommand::parse(c.command) {
+                    Some(MessageCommand::SetColor) => self.color = parse_color(c.argument),
+                    Some(MessageCommand::RubiContent) => {
+                        // the furigana left open is closed before the next one starts
+                        self.place_furigana(top);
+                        self.furigana = c.argument;
+                    }
+                    Some(MessageCommand::RubiBaseStart | MessageCommand::RubiBaseEnd) => {
+                        self.place_furigana(top)
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        // furigana can't span several lines, so it's placed over the part of the base on this line
+        self.place_furigana(top);
+        self.lines += 1;
+    }
+}
+
+/// Lays out the message and places its characters the way the game draws them, without reflowing it.
+///
+/// The tokens must not include the speaker name (see [`super::reflow::speaker_name_len`]), as it's not drawn in the message box.
+///
+/// The text color set by `@c` commands is tracked, and the furigana is placed centered over its base text.
+pub fn place_message<'a>(
+    bump: &Bump,
+    font_metrics: &'a FontMetrics,
+    layout_info: GameLayoutInfo,
+    tokens: &[MessageToken<'a>],
+) -> MessagePreview {
+    let font_info = font_metrics.get_info();
+    let font_height = (font_info.ascent + font_info.descent) as f32;
+    let default_scale = layout_info.default_scale * layout_info.overall_scale_factor;
+    let furigana_height = font_height * FURIGANA_SCALE;
+
+    let mut placer = Placer {
+        font_metrics,
+        line_height: furigana_height + font_height * default_scale,
+        furigana_baseline: font_info.ascent as f32 * FURIGANA_SCALE,
+        text_baseline: furigana_height + font_info.ascent as f32 * default_scale,
+        color: DEFAULT_COLOR,
+        furigana: None,
+        furigana_base: None,
+        chars: Vec::new(),
+        lines: 0,
+    };
+
+    let mut layouter = LightLayouter::new(bump, font_metrics, layout_info);
+    for &token in tokens {
+        if let PushResult::ParagraphComplete = layouter.push(token) {
+            placer.place_line(layouter.peek_buffer());
+            layouter.reset_buffer();
+        }
+    }
+    placer.place_line(layouter.peek_buffer());
+
+    MessagePreview {
+        chars: placer.chars,
+        lines: placer.lines,
+        line_height: placer.line_height,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+    use shin_font::test_util::TestFont;
+    use shin_versions::{MessageCommandStyle, ShinVersion};
+
+    use super::{DEFAULT_COLOR, FURIGANA_SCALE, parse_color, place_message};
+    use crate::layout::{layouter::GameLayoutInfo, message_parser};
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color(Some("900")), [0xff, 0, 0]);
+        assert_eq!(parse_color(Some("369")), [85, 170, 255]);
+        assert_eq!(parse_color(None), DEFAULT_COLOR);
+        assert_eq!(parse_color(Some("red")), DEFAULT_COLOR);
+    }
+
+    #[test]
+    fn place() {
+        let font = TestFont::monospace().metrics();
+        let layout = GameLayoutInfo {
+            default_scale: 1.0,
+            overall_scale_factor: 1.0,
+            ..GameLayoutInfo::for_version(ShinVersion::HigurashiSui).unwrap()
+        };
+
+        let mut tokens = Vec::new();
+        message_parser::parse(
+            MessageCommandStyle::Escaped,
+            "@c900.a@c.b@rc@bxyz.@<d@>",
+            &mut tokens,
+        );
+        let bump = Bump::new();
+        let preview = place_message(&bump, &font, layout, &tokens);
+
+        assert_eq!(preview.lines, 2);
+        let line_height = 24.0 * FURIGANA_SCALE + 24.0;
+        assert_eq!(preview.line_height, line_height);
+
+        let chars = preview
+            .chars
+            .iter()
+            .map(|c| (c.codepoint, c.x, c.color))
+            .collect::<Vec<_>>();
+        // the furigana is wider than its base, so the base is spread out, and the furigana starts with it
+        assert_eq!(
+            chars,
+            [
+                ('a', 0.0, [0xff, 0, 0]),
+                ('b', 10.0, DEFAULT_COLOR),
+                ('c', 0.0, DEFAULT_COLOR),
+                ('d', 11.75, DEFAULT_COLOR),
+                ('x', 10.0, DEFAULT_COLOR),
+                ('y', 14.5, DEFAULT_COLOR),
+                ('z', 19.0, DEFAULT_COLOR),
+            ]
+        );
+
+        let text_baseline = 24.0 * FURIGANA_SCALE + 20.0;
+        assert_eq!(preview.chars[0].baseline_y, text_baseline);
+        assert_eq!(preview.chars[2].baseline_y, line_height + text_baseline);
+        assert_eq!(
+            preview.chars[4].baseline_y,
+            line_height + 20.0 * FURIGANA_SCALE
+        );
+        assert_eq!(preview.chars[4].scale, FURIGANA_SCALE);
+    }
+}
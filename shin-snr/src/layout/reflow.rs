@@ -2,25 +2,134 @@ use bumpalo::{collections::Vec, Bump};
 use shin_font::FontMetrics;
 
 use crate::layout::{
-    layouter::{self, GameLayoutInfo, LightLayouter, PushResult},
-    message_parser::{CommandToken, MessageToken},
+    hyphenate::Hyphenator,
+    layouter::{
+        self, ChoiceBoxInfo, GameLayoutInfo, LightLayouter, PushResult, find_missing_glyph,
+        parse_font_scale,
+    },
+    message_parser::{CommandToken, MessageCommand, MessageToken},
 };
 
+/// Everything needed to reflow the messages
+#[derive(Clone, Copy)]
+pub struct ReflowParams<'a> {
+    pub metrics: &'a FontMetrics,
+    pub layout: GameLayoutInfo,
+    /// Allows breaking the lines inside the words, adding a hyphen
+    pub hyphenator: Option<&'a Hyphenator>,
+}
+
+/// A place where the line can be broken, before the command at `index`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BreakCandidate {
+    pub index: usize,
+    /// For the breaks inside the words, the width of the hyphen added at the end of the line
+    pub hyphen_width: Option<f32>,
+}
+
+/// A line break chosen by the reflow, before the command at `index`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BreakPoint {
+    pub index: usize,
+    /// Whether the word is broken here, so a hyphen has to be added at the end of the line
+    pub hyphen: bool,
+}
+
+/// Finds the places where the paragraph can be broken: the UAX#14 break opportunities and, if hyphenation is enabled, the places inside the words where a hyphen can be inserted
+///
+/// The hyphenation needs the advance width of the hyphen glyph, it's scaled the same way as the chars are.
+pub fn break_candidates(
+    commands: &[layouter::Command],
+    hyphenation: Option<(&Hyphenator, f32)>,
+) -> std::vec::Vec<BreakCandidate> {
+    let mut candidates = super::linebreak::LineBreakIterator::new(commands)
+        .map(|(index, _is_hard)| BreakCandidate {
+            index,
+            hyphen_width: None,
+        })
+        .collect::<std::vec::Vec<_>>();
+
+    let Some((hyphenator, hyphen_advance)) = hyphenation else {
+        return candidates;
+    };
+
+    let is_letter = |command: &layouter::Command| match command {
+        layouter::Command::Char(c) => c.codepoint.is_alphabetic() && !c.has_furigana,
+        _ => false,
+    };
+    let mut start = 0;
+    while start < commands.len() {
+        // words are runs of letters, not interrupted by any commands
+        let len = commands[start..]
+            .iter()
+            .position(|command| !is_letter(command))
+            .unwrap_or(commands.len() - start);
+        if len == 0 {
+            start += 1;
+            continue;
+        }
+        // a run touching a command is only a part of the word, don't guess how to hyphenate it
+        let is_unparsed = |index: Option<usize>| {
+            index
+                .and_then(|index| commands.get(index))
+                .is_some_and(|command| matches!(command, layouter::Command::Unparsed(_)))
+        };
+        if is_unparsed(start.checked_sub(1)) || is_unparsed(Some(start + len)) {
+            start += len;
+            continue;
+        }
+
+        let chars = commands[start..start + len]
+            .iter()
+            .filter_map(|command| match command {
+                layouter::Command::Char(c) => Some(*c),
+                layouter::Command::Unparsed(_) => None,
+            })
+            .collect::<std::vec::Vec<_>>();
+        let word = chars
+            .iter()
+            .map(|c| c.codepoint)
+            .collect::<std::string::String>();
+        for offset in hyphenator.hyphenate_word(&word) {
+            let char_index = word[..offset].chars().count();
+            let index = start + char_index;
+            if candidates.iter().all(|candidate| candidate.index != index) {
+                candidates.push(BreakCandidate {
+                    index,
+                    hyphen_width: Some(hyphen_advance * chars[char_index - 1].scale),
+                });
+            }
+        }
+
+        start += len;
+    }
+    candidates.sort_by_key(|candidate| candidate.index);
+
+    candidates
+}
+
 pub fn reflow_paragraph(
     layout_info: GameLayoutInfo,
     base_index: usize,
-    break_points: &mut Vec<usize>,
+    break_points: &mut Vec<BreakPoint>,
     commands: &[layouter::Command],
+    candidates: &[BreakCandidate],
 ) {
-    let mut break_iter = super::linebreak::LineBreakIterator::new(commands).peekable();
+    let mut break_iter = candidates.iter().copied().peekable();
     let mut base_x = 0.0;
-    let mut last_seen_candidate_breakpoint = None;
+    // right edge of the last char seen
+    let mut line_end = 0.0;
+    let mut last_seen_candidate_breakpoint: Option<BreakCandidate> = None;
 
     for (index, command) in (0..).zip(commands) {
-        if let Some((break_index, _is_hard)) =
-            break_iter.next_if(|&(break_index, _)| index >= break_index)
-        {
-            last_seen_candidate_breakpoint = Some(break_index);
+        if let Some(candidate) = break_iter.next_if(|candidate| index >= candidate.index) {
+            // a hyphen sticking out of the line is no good
+            let fits = candidate
+                .hyphen_width
+                .is_none_or(|hyphen_width| line_end + hyphen_width - base_x <= layout_info.width);
+            if fits {
+                last_seen_candidate_breakpoint = Some(candidate);
+            }
         }
 
         if let layouter::Command::Char(c) = command {
@@ -28,49 +137,187 @@ pub fn reflow_paragraph(
                 // need a line break
                 if let Some(breakpoint) = last_seen_candidate_breakpoint {
                     last_seen_candidate_breakpoint = None; // do not re-use the same breakpoint!
-                    break_points.push(base_index + breakpoint);
-                    let layouter::Command::Char(c) = &commands[breakpoint] else {
+                    break_points.push(BreakPoint {
+                        index: base_index + breakpoint.index,
+                        hyphen: breakpoint.hyphen_width.is_some(),
+                    });
+                    let layouter::Command::Char(c) = &commands[breakpoint.index] else {
                         unreachable!("break points should only happen at chars")
                     };
                     base_x = c.pos_x + c.width;
                 } else {
                     // no valid breakpoint, force a break right here
-                    break_points.push(base_index + index);
+                    break_points.push(BreakPoint {
+                        index: base_index + index,
+                        hyphen: false,
+                    });
                     base_x = c.pos_x + c.width;
                 }
             }
+            line_end = c.pos_x + c.width;
         }
     }
 }
 
+/// Chooses the line breaks in a paragraph to minimize the raggedness: the sum of the squared slack (unused width) of all its lines, including the last one.
+///
+/// Unlike [`reflow_paragraph`], this looks at the whole paragraph at once, so it avoids leaving a word or two on the last line.
+/// The lines are measured the same way [`reflow_paragraph`] does it, and the paragraph never takes more lines than it would with [`reflow_paragraph`], so it doesn't overflow the message box where the greedy reflow wouldn't.
+/// If some word doesn't fit into a line by itself, the paragraph is reflowed with [`reflow_paragraph`], which breaks such words.
+pub fn reflow_paragraph_optimal(
+    layout_info: GameLayoutInfo,
+    base_index: usize,
+    break_points: &mut Vec<BreakPoint>,
+    commands: &[layouter::Command],
+    candidates: &[BreakCandidate],
+) {
+    let positions = std::iter::once(BreakCandidate {
+        index: 0,
+        hyphen_width: None,
+    })
+    .chain(candidates.iter().copied())
+    .chain(std::iter::once(BreakCandidate {
+        index: commands.len(),
+        hyphen_width: None,
+    }))
+    .collect::<std::vec::Vec<_>>();
+
+    // the right edge of the last char before the index
+    let right_edge = |end: usize| {
+        commands[..end]
+            .iter()
+            .rev()
+            .find_map(|command| match command {
+                layouter::Command::Char(c) => Some(c.pos_x + c.width),
+                layouter::Command::Unparsed(_) => None,
+            })
+            .unwrap_or(0.0)
+    };
+    // where the line starting at the position is measured from, see `base_x` in `reflow_paragraph`
+    let base_x = positions
+        .iter()
+        .map(|position| match commands.get(position.index) {
+            Some(layouter::Command::Char(c)) if position.index > 0 => c.pos_x + c.width,
+            _ => 0.0,
+        })
+        .collect::<std::vec::Vec<_>>();
+    let end_x = positions
+        .iter()
+        .map(|position| right_edge(position.index) + position.hyphen_width.unwrap_or(0.0))
+        .collect::<std::vec::Vec<_>>();
+    // prefer breaking between the words when it doesn't make much difference
+    let hyphen_penalty = (layout_info.width / 10.0).powi(2);
+
+    // best[j] is the number of lines and the raggedness of the best way to lay out the paragraph up to position j
+    // previous[j] is where its last line starts
+    let mut best = vec![(usize::MAX, f32::INFINITY); positions.len()];
+    let mut previous = vec![0; positions.len()];
+    best[0] = (0, 0.0);
+    for end in 1..positions.len() {
+        for start in (0..end).rev() {
+            let width = end_x[end] - base_x[start];
+            if width > layout_info.width {
+                // the lines starting earlier are even wider
+                break;
+            }
+            let (lines, raggedness) = best[start];
+            if lines == usize::MAX {
+                continue;
+            }
+            let slack = layout_info.width - width;
+            let penalty = if positions[end].hyphen_width.is_some() {
+                hyphen_penalty
+            } else {
+                0.0
+            };
+            let candidate = (lines + 1, raggedness + slack * slack + penalty);
+            if candidate.0 < best[end].0 || candidate.0 == best[end].0 && candidate.1 < best[end].1
+            {
+                best[end] = candidate;
+                previous[end] = start;
+            }
+        }
+    }
+
+    let last = positions.len() - 1;
+    if best[last].0 == usize::MAX {
+        reflow_paragraph(layout_info, base_index, break_points, commands, candidates);
+        return;
+    }
+
+    let first_break = break_points.len();
+    let mut current = last;
+    while current != 0 {
+        current = previous[current];
+        if current != 0 {
+            break_points.push(BreakPoint {
+                index: base_index + positions[current].index,
+                hyphen: positions[current].hyphen_width.is_some(),
+            });
+        }
+    }
+    break_points[first_break..].reverse();
+}
+
+/// How [`reflow_message`] chooses where to break the lines
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ReflowAlgorithm {
+    /// Break each line as late as possible (see [`reflow_paragraph`])
+    #[default]
+    Greedy,
+    /// Choose the breaks across the whole paragraph (see [`reflow_paragraph_optimal`])
+    Optimal,
+}
+
 pub fn reflow_message<'s>(
     bump: &Bump,
-    font_metrics: &FontMetrics,
-    layout_info: GameLayoutInfo,
+    params: ReflowParams,
+    algorithm: ReflowAlgorithm,
     tokens_in: &[MessageToken<'s>],
     tokens_out: &mut Vec<MessageToken<'s>>,
 ) {
+    // the layouter can't measure the characters the font doesn't have, such messages are left as-is (the lint reports them)
+    if find_missing_glyph(params.metrics, tokens_in).is_some() {
+        tokens_out.extend_from_slice(tokens_in);
+        return;
+    }
+
     tokens_out.reserve(tokens_in.len() + 8);
-    let mut layouter = LightLayouter::new(bump, font_metrics, layout_info);
+    let mut layouter = LightLayouter::new(bump, params.metrics, params.layout);
 
     let mut break_points = Vec::with_capacity_in(16, bump);
 
     fn flush_paragraph<'s>(
-        break_points: &mut Vec<usize>,
-        layout_info: GameLayoutInfo,
+        break_points: &mut Vec<BreakPoint>,
+        params: ReflowParams,
+        algorithm: ReflowAlgorithm,
         para_commands: &[layouter::Command<'s>],
         commands_out: &mut Vec<MessageToken<'s>>,
     ) {
         break_points.clear();
-        reflow_paragraph(layout_info, 0, break_points, para_commands);
+        // the words can't be hyphenated if the font doesn't have a hyphen
+        let hyphen_advance = params
+            .metrics
+            .get_glyph_metrics('-')
+            .map(|hyphen| hyphen.advance_width as f32);
+        let candidates = break_candidates(para_commands, params.hyphenator.zip(hyphen_advance));
+        match algorithm {
+            ReflowAlgorithm::Greedy => {
+                reflow_paragraph(params.layout, 0, break_points, para_commands, &candidates)
+            }
+            ReflowAlgorithm::Optimal => {
+                reflow_paragraph_optimal(params.layout, 0, break_points, para_commands, &candidates)
+            }
+        }
 
         let mut break_iter = break_points.iter().copied().peekable();
 
         for (index, command) in (0..).zip(para_commands) {
-            if break_iter
-                .next_if(|&break_index| index >= break_index)
-                .is_some()
+            if let Some(break_point) = break_iter.next_if(|break_point| index >= break_point.index)
             {
+                if break_point.hyphen {
+                    commands_out.push(MessageToken::Literal('-'));
+                }
                 commands_out.push(MessageToken::Command(CommandToken {
                     command: 'r',
                     argument: None,
@@ -85,7 +332,8 @@ pub fn reflow_message<'s>(
         if let PushResult::ParagraphComplete = layouter.push(command) {
             flush_paragraph(
                 &mut break_points,
-                layout_info,
+                params,
+                algorithm,
                 layouter.peek_buffer(),
                 tokens_out,
             );
@@ -94,21 +342,423 @@ pub fn reflow_message<'s>(
     }
     flush_paragraph(
         &mut break_points,
-        layout_info,
+        params,
+        algorithm,
         layouter.peek_buffer(),
         tokens_out,
     );
 }
 
+/// How the choices wider than the choice box are made to fit into it
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum ChoiceFitPolicy {
+    /// Leave the choices as they are. The ones that are too wide can be found with `--check-overflow`.
+    #[default]
+    Keep,
+    /// Break the choices into several lines (@r), the same way the `greedy` message reflow does it.
+    Reflow,
+    /// Scale the font of the choices down (@z), so that they fit on a single line.
+    Shrink,
+}
+
+/// Makes the choice fit into the choice box, according to the policy. The choices that fit already are left as they are.
+///
+/// The result may still not fit: a reflowed choice may take more lines than the choice box has, and the font can't be scaled down below 10%.
+pub fn fit_choice<'s>(
+    bump: &'s Bump,
+    params: ReflowParams,
+    choice_box: ChoiceBoxInfo,
+    policy: ChoiceFitPolicy,
+    tokens_in: &[MessageToken<'s>],
+    tokens_out: &mut Vec<MessageToken<'s>>,
+) {
+    if find_missing_glyph(params.metrics, tokens_in).is_some() {
+        tokens_out.extend_from_slice(tokens_in);
+        return;
+    }
+
+    let width = measure_width(bump, params.metrics, params.layout, tokens_in);
+    if width <= choice_box.width {
+        tokens_out.extend_from_slice(tokens_in);
+        return;
+    }
+
+    match policy {
+        ChoiceFitPolicy::Keep => tokens_out.extend_from_slice(tokens_in),
+        ChoiceFitPolicy::Reflow => {
+            let params = ReflowParams {
+                layout: GameLayoutInfo {
+                    width: choice_box.width,
+                    ..params.layout
+                },
+                ..params
+            };
+            reflow_message(bump, params, ReflowAlgorithm::Greedy, tokens_in, tokens_out);
+        }
+        ChoiceFitPolicy::Shrink => {
+            // the width is proportional to the font scale, which is set in percents
+            let factor = choice_box.width / width;
+            let font_scale = |scale: f32| {
+                let percent = ((scale * factor * 100.0).floor() as u32).max(10);
+                MessageToken::Command(CommandToken {
+                    command: 'z',
+                    argument: Some(bump.alloc_str(&percent.to_string())),
+                })
+            };
+
+            tokens_out.push(font_scale(params.layout.default_scale));
+            // the scales set inside the choice override the one added at the start, so they are scaled down too
+            for &token in tokens_in {
+                match token {
+                    MessageToken::Command(c)
+                        if matches!(
+                            MessageCommand::parse(c.command),
+                            Some(MessageCommand::FontScale)
+                        ) =>
+                    {
+                        match c.parse_int_arg() {
+                            Ok(scale) => tokens_out.push(font_scale(parse_font_scale(scale))),
+                            Err(_) => tokens_out.push(token),
+                        }
+                    }
+                    token => tokens_out.push(token),
+                }
+            }
+        }
+    }
+}
+
+/// The longest speaker name, in characters. The longer texts before the first newline are ordinary lines
+const MAX_SPEAKER_NAME_LEN: usize = 16;
+/// The characters a speaker name can't contain
+const SENTENCE_PUNCTUATION: &[char] = &['。', '、', '！', '？', '…', '「', '」', '!', '?', ','];
+/// The characters a line spoken by a named character can start with
+const OPENING_QUOTES: &[char] = &['「', '『', '（', '“', '"', '('];
+
+/// Returns the number of tokens taken by the speaker name (the literals before the first newline), including the newline
+///
+/// A message with two lines of narration looks the same as a named one, so only these are treated as names:
+/// - a leading newline, which is an empty name, as it doesn't start a line in the message box either;
+/// - a bracketed name (`【語り手】@r...` or `[Narrator]@r...`);
+/// - a short text without sentence punctuation, followed by a voice command or an opening quote (`語り手@r@vhello....` or `語り手@r「...`).
+///
+/// This is the token counterpart of [`super::message_parser::split_speaker_name`].
+/// It has to be called before reflowing the message, as the inserted line breaks would make the first line look like a name.
+pub fn speaker_name_len(tokens: &[MessageToken]) -> usize {
+    let parse_command = |token: Option<&MessageToken>| match token {
+        Some(MessageToken::Command(c)) => MessageCommand::parse(c.command),
+        _ => None,
+    };
+
+    let Some(newline) = tokens
+        .iter()
+        .position(|token| !matches!(token, MessageToken::Literal(_)))
+    else {
+        return 0;
+    };
+    if !matches!(
+        parse_command(tokens.get(newline)),
+        Some(MessageCommand::Newline)
+    ) {
+        return 0;
+    }
+
+    let name = || {
+        tokens[..newline].iter().filter_map(|token| match *token {
+            MessageToken::Literal(c) => Some(c),
+            MessageToken::Command(_) => None,
+        })
+    };
+    let bracketed = matches!(
+        (name().next(), name().next_back()),
+        (Some('【'), Some('】')) | (Some('['), Some(']')) | (Some('［'), Some('］'))
+    ) && newline > 1;
+    let named_line = name().count() <= MAX_SPEAKER_NAME_LEN
+        && !name().any(|c| SENTENCE_PUNCTUATION.contains(&c))
+        && match tokens.get(newline + 1) {
+            Some(MessageToken::Literal(c)) => OPENING_QUOTES.contains(c),
+            token => matches!(parse_command(token), Some(MessageCommand::Voice)),
+        };
+
+    if newline == 0 || bracketed || named_line {
+        newline + 1
+    } else {
+        0
+    }
+}
+
+/// A line of a message as the game would display it
+#[derive(Debug, Clone)]
+pub struct RenderedLine {
+    pub text: std::string::String,
+    pub width: f32,
+}
+
+fn render_paragraph(commands: &[layouter::Command]) -> RenderedLine {
+    let mut text = std::string::String::new();
+    let mut width = 0.0f32;
+    for command in commands {
+        if let layouter::Command::Char(c) = command {
+            text.push(c.codepoint);
+            width = width.max(c.pos_x + c.width);
+        }
+    }
+    RenderedLine { text, width }
+}
+
+/// Reflows the message the same way [`reflow_message`] does and lays out the result, returning its lines. The speaker name is not included.
+pub fn render_message(
+    bump: &Bump,
+    params: ReflowParams,
+    algorithm: ReflowAlgorithm,
+    tokens: &[MessageToken],
+) -> std::vec::Vec<RenderedLine> {
+    let body = &tokens[speaker_name_len(tokens)..];
+    let mut reflowed = Vec::new_in(bump);
+    reflow_message(bump, params, algorithm, body, &mut reflowed);
+
+    let mut layouter = LightLayouter::new(bump, params.metrics, params.layout);
+    let mut lines = std::vec::Vec::new();
+    for &token in &reflowed {
+        if let PushResult::ParagraphComplete = layouter.push(token) {
+            lines.push(render_paragraph(layouter.peek_buffer()));
+            layouter.reset_buffer();
+        }
+    }
+    lines.push(render_paragraph(layouter.peek_buffer()));
+
+    lines
+}
+
+/// Lays out a string without reflowing it, returning the width of its widest line
+pub fn measure_width(
+    bump: &Bump,
+    font_metrics: &FontMetrics,
+    layout_info: GameLayoutInfo,
+    tokens: &[MessageToken],
+) -> f32 {
+    let mut layouter = LightLayouter::new(bump, font_metrics, layout_info);
+    let mut width = 0.0f32;
+    for &token in tokens {
+        if let PushResult::ParagraphComplete = layouter.push(token) {
+            width = width.max(render_paragraph(layouter.peek_buffer()).width);
+            layouter.reset_buffer();
+        }
+    }
+    width.max(render_paragraph(layouter.peek_buffer()).width)
+}
+
+/// Whether the message plays a voice, see [`split_pages`]
+pub fn is_voiced(tokens: &[MessageToken]) -> bool {
+    tokens.iter().any(|token| {
+        matches!(
+            token,
+            MessageToken::Command(CommandToken { command, .. })
+                if matches!(MessageCommand::parse(*command), Some(MessageCommand::Voice))
+        )
+    })
+}
+
+/// Splits an already reflowed message into pages of at most `max_lines` lines.
+///
+/// The speaker name (the first `name_len` tokens, see [`speaker_name_len`]) is repeated on each page and isn't counted as a line.
+/// The styling commands (color, font scale, text speed, bold and instant text) in effect at the end of a page are repeated at the start of the next one. The other commands, like the waits and the sync commands, are left in place, so they end up on the page with the text around them.
+/// The `@e` command is moved to the last page, so that the game waits for a click on all the others.
+///
+/// The pages are written as copies of the same MSGSET, sharing its message id, so the voiced messages (see [`is_voiced`]) are not split: the game finds the voice of the message by its id.
+pub fn split_pages<'bump, 's>(
+    bump: &'bump Bump,
+    max_lines: usize,
+    name_len: usize,
+    tokens: &[MessageToken<'s>],
+) -> Vec<'bump, Vec<'bump, MessageToken<'s>>> {
+    let parse_command = |token: &MessageToken| match token {
+        MessageToken::Command(c) => MessageCommand::parse(c.command),
+        MessageToken::Literal(_) => None,
+    };
+
+    if is_voiced(tokens) {
+        let mut pages = Vec::new_in(bump);
+        pages.push(Vec::from_iter_in(tokens.iter().copied(), bump));
+        return pages;
+    }
+
+    let (name, body) = tokens.split_at(name_len);
+
+    let mut pages = Vec::new_in(bump);
+    let mut page = Vec::from_iter_in(name.iter().copied(), bump);
+    let mut lines = 1;
+    // the newline that ended the last page, if anything else follows it
+    let mut page_break = None;
+    // the commands setting the current text style
+    let mut style = [None; 5];
+    let mut deferred = Vec::new_in(bump);
+
+    for &token in body {
+        let command = parse_command(&token);
+        if let Some(MessageCommand::NoFinalClickWait) = command {
+            deferred.push(token);
+            continue;
+        }
+
+        if page_break.take().is_some() {
+            pages.push(std::mem::replace(&mut page, Vec::new_in(bump)));
+            page.extend_from_slice(name);
+            page.extend(style.iter().flatten().copied());
+            lines = 1;
+        }
+
+        match command {
+            Some(MessageCommand::Newline) if lines >= max_lines => {
+                page_break = Some(token);
+                continue;
+            }
+            Some(MessageCommand::Newline) => lines += 1,
+            Some(MessageCommand::SetColor) => style[0] = Some(token),
+            Some(MessageCommand::FontScale) => style[1] = Some(token),
+            Some(MessageCommand::TextSpeed) => style[2] = Some(token),
+            Some(MessageCommand::BoldTextStart) => style[3] = Some(token),
+            Some(MessageCommand::BoldTextEnd) => style[3] = None,
+            Some(MessageCommand::InstantTextStart) => style[4] = Some(token),
+            Some(MessageCommand::InstantTextEnd) => style[4] = None,
+            _ => {}
+        }
+        page.push(token);
+    }
+
+    // a newline at the very end doesn't need a new page
+    page.extend(page_break);
+    page.extend(deferred);
+    pages.push(page);
+
+    pages
+}
+
 #[cfg(test)]
 mod test {
     use bumpalo::{collections::Vec, Bump};
+    use shin_font::test_util::TestFont;
     use shin_versions::{MessageCommandStyle, ShinVersion, SjisMessageFixupPolicy};
 
+    use super::{ChoiceFitPolicy, ReflowAlgorithm, ReflowParams};
     use crate::layout::{
-        layouter, layouter::GameLayoutInfo, message_parser, reflow::reflow_paragraph,
+        hyphenate::Hyphenator,
+        layouter,
+        layouter::{ChoiceBoxInfo, GameLayoutInfo},
+        message_parser,
+        reflow::{break_candidates, reflow_paragraph, reflow_paragraph_optimal},
     };
 
+    /// Breaks the monospace text into lines, adding the hyphens where the words are broken
+    fn break_lines(
+        algorithm: ReflowAlgorithm,
+        width: f32,
+        hyphenator: Option<&Hyphenator>,
+        text: &str,
+    ) -> std::vec::Vec<std::string::String> {
+        let params = GameLayoutInfo {
+            width,
+            ..GameLayoutInfo::for_version(ShinVersion::HigurashiSui).unwrap()
+        };
+        // lay out the text unscaled, with all characters being 10 units wide
+        let font = TestFont::monospace().metrics();
+        let bump = Bump::new();
+        let mut tokens = Vec::new_in(&bump);
+        message_parser::parse(MessageCommandStyle::Escaped, text, &mut tokens);
+        let mut layouter = layouter::LightLayouter::new(
+            &bump,
+            &font,
+            layouter::LightLayouterParams {
+                default_scale: 1.0,
+                overall_scale_factor: 1.0,
+                auto_close_furigana: false,
+            },
+        );
+        for token in tokens {
+            layouter.push(token);
+        }
+        let commands = layouter.take_buffer();
+        let candidates = break_candidates(&commands, hyphenator.map(|h| (h, 10.0)));
+
+        let mut break_points = Vec::new_in(&bump);
+        match algorithm {
+            ReflowAlgorithm::Greedy => {
+                reflow_paragraph(params, 0, &mut break_points, &commands, &candidates)
+            }
+            ReflowAlgorithm::Optimal => {
+                reflow_paragraph_optimal(params, 0, &mut break_points, &commands, &candidates)
+            }
+        }
+
+        let chars = text.chars().collect::<std::vec::Vec<_>>();
+        let mut lines = std::vec::Vec::new();
+        let mut start = 0;
+        for break_point in &break_points {
+            let mut line = chars[start..break_point.index]
+                .iter()
+                .collect::<std::string::String>();
+            if break_point.hyphen {
+                line.push('-');
+            }
+            lines.push(line);
+            start = break_point.index;
+        }
+        lines.push(chars[start..].iter().collect());
+        lines
+    }
+
+    #[test]
+    fn optimal() {
+        let text = "aa bb cc dd ee ff gg hh ii";
+
+        // greedy packs the first lines tightly, leaving a single word on the last one
+        assert_eq!(
+            break_lines(ReflowAlgorithm::Greedy, 120.0, None, text),
+            ["aa bb cc dd ", "ee ff gg hh ", "ii"]
+        );
+        // while the optimal reflow evens them out, without taking more lines
+        assert_eq!(
+            break_lines(ReflowAlgorithm::Optimal, 120.0, None, text),
+            ["aa bb cc ", "dd ee ff ", "gg hh ii"]
+        );
+        assert_eq!(
+            break_lines(ReflowAlgorithm::Optimal, 80.0, None, text),
+            ["aa bb ", "cc dd ", "ee ff ", "gg hh ii"]
+        );
+
+        // a word too long for a line is broken the same way greedy does it
+        assert_eq!(
+            break_lines(ReflowAlgorithm::Optimal, 50.0, None, "aaaaaaa b"),
+            break_lines(ReflowAlgorithm::Greedy, 50.0, None, "aaaaaaa b"),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hyphenation")]
+    fn hyphenate() {
+        let hyphenator = Hyphenator::for_language("en-us").unwrap();
+        let text = "an extraordinary performance";
+
+        assert_eq!(
+            break_lines(ReflowAlgorithm::Greedy, 80.0, None, text),
+            ["an ", "extraordi", "nary ", "performan", "ce"]
+        );
+        assert_eq!(
+            break_lines(ReflowAlgorithm::Greedy, 80.0, Some(&hyphenator), text),
+            ["an ex-", "traordi-", "nary per-", "formance"]
+        );
+        // the hyphens are penalized, so the optimal reflow uses fewer of them when it can
+        assert_eq!(
+            break_lines(ReflowAlgorithm::Greedy, 120.0, Some(&hyphenator), text),
+            ["an extraor-", "dinary per-", "formance"]
+        );
+        assert_eq!(
+            break_lines(ReflowAlgorithm::Optimal, 120.0, Some(&hyphenator), text),
+            ["an extra-", "ordinary ", "performance"]
+        );
+    }
+
     #[test]
     fn smoke() {
         let params = GameLayoutInfo::for_version(ShinVersion::HigurashiSui).unwrap();
@@ -122,11 +772,22 @@ mod test {
         let mut break_points = Vec::new_in(&bump);
         let mut base_index = 0;
         for paragraph in &commands {
-            reflow_paragraph(params, base_index, &mut break_points, paragraph);
+            let candidates = break_candidates(paragraph, None);
+            reflow_paragraph(
+                params,
+                base_index,
+                &mut break_points,
+                paragraph,
+                &candidates,
+            );
             base_index += paragraph.len();
         }
 
-        assert_eq!(break_points, [40, 90, 141, 191, 242, 295, 345, 397,])
+        let break_indices = break_points
+            .iter()
+            .map(|break_point| break_point.index)
+            .collect::<std::vec::Vec<_>>();
+        assert_eq!(break_indices, [40, 90, 141, 191, 242, 295, 345, 397,])
     }
 
     fn reflow_message(message: &str) -> String {
@@ -140,8 +801,12 @@ mod test {
         let mut tokens_out = Vec::new_in(&bump);
         super::reflow_message(
             &bump,
-            &layouter::test::RESOURCES.metrics,
-            params,
+            ReflowParams {
+                metrics: &layouter::test::RESOURCES.metrics,
+                layout: params,
+                hyphenator: None,
+            },
+            ReflowAlgorithm::Greedy,
             &tokens,
             &mut tokens_out,
         );
@@ -161,6 +826,153 @@ mod test {
         s
     }
 
+    fn fit_choice(policy: ChoiceFitPolicy, choice: &str) -> String {
+        let font = TestFont::monospace().metrics();
+        let layout = GameLayoutInfo {
+            default_scale: 1.0,
+            overall_scale_factor: 1.0,
+            ..GameLayoutInfo::for_version(ShinVersion::HigurashiSui).unwrap()
+        };
+
+        let bump = Bump::new();
+        let mut tokens = Vec::new_in(&bump);
+        message_parser::parse(MessageCommandStyle::Escaped, choice, &mut tokens);
+
+        let mut tokens_out = Vec::new_in(&bump);
+        super::fit_choice(
+            &bump,
+            ReflowParams {
+                metrics: &font,
+                layout,
+                hyphenator: None,
+            },
+            ChoiceBoxInfo {
+                width: 100.0,
+                max_lines: 2,
+            },
+            policy,
+            &tokens,
+            &mut tokens_out,
+        );
+
+        message_parser::serialize_string(&bump, MessageCommandStyle::Escaped, &tokens_out)
+            .to_string()
+    }
+
+    #[test]
+    fn choices() {
+        let long = "aaaa bbbb cccc";
+
+        assert_eq!(fit_choice(ChoiceFitPolicy::Keep, long), long);
+        assert_eq!(
+            fit_choice(ChoiceFitPolicy::Reflow, long),
+            "aaaa bbbb @rcccc"
+        );
+        assert_eq!(
+            fit_choice(ChoiceFitPolicy::Shrink, long),
+            "@z71.aaaa bbbb cccc"
+        );
+        // the choices that fit are left as they are
+        assert_eq!(
+            fit_choice(ChoiceFitPolicy::Shrink, "aaaa bbbb"),
+            "aaaa bbbb"
+        );
+        // the scales set inside the choice are shrunk too
+        assert_eq!(
+            fit_choice(ChoiceFitPolicy::Shrink, "aaaa @z50.bbbb@z100. cccc"),
+            "@z83.aaaa @z41.bbbb@z83. cccc"
+        );
+    }
+
+    fn split_pages(message: &str, max_lines: usize) -> std::vec::Vec<String> {
+        let bump = Bump::new();
+
+        let mut tokens = Vec::new_in(&bump);
+        message_parser::parse(MessageCommandStyle::Escaped, message, &mut tokens);
+
+        let name_len = super::speaker_name_len(&tokens);
+        super::split_pages(&bump, max_lines, name_len, &tokens)
+            .iter()
+            .map(|page| {
+                message_parser::serialize_string(&bump, MessageCommandStyle::Escaped, page)
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split() {
+        // fits
+        assert_eq!(split_pages("[Name]@rone@rtwo", 2), ["[Name]@rone@rtwo"]);
+        // the name is repeated, the sync commands stay with their text
+        assert_eq!(
+            split_pages("[Name]@rone@rtwo@k@rthree@y", 2),
+            ["[Name]@rone@rtwo@k", "[Name]@rthree@y"]
+        );
+        // the voiced messages are not split
+        assert_eq!(
+            split_pages("[Name]@r@vvoice.one@rtwo@rthree", 2),
+            ["[Name]@r@vvoice.one@rtwo@rthree"]
+        );
+        // an empty name is repeated too
+        assert_eq!(
+            split_pages("@rone@rtwo@rthree", 2),
+            ["@rone@rtwo", "@rthree"]
+        );
+        // no name, the style is carried over and @e goes to the end
+        assert_eq!(
+            split_pages("@eone @c900.red@rtwo @{bold@rthree@} four@rfive", 1),
+            [
+                "one @c900.red",
+                "@c900.two @{bold",
+                "@c900.@{three@} four",
+                "@c900.five@e"
+            ]
+        );
+        // a trailing newline doesn't make an empty page
+        assert_eq!(split_pages("one@rtwo@r", 2), ["one@rtwo@r"]);
+        assert_eq!(split_pages("@eone@rtwo@r", 1), ["one", "two@r@e"]);
+    }
+
+    #[test]
+    fn split_reflowed() {
+        let font = TestFont::monospace().metrics();
+        let params = ReflowParams {
+            metrics: &font,
+            layout: GameLayoutInfo {
+                width: 100.0,
+                max_lines: 1,
+                default_scale: 1.0,
+                overall_scale_factor: 1.0,
+                ..GameLayoutInfo::for_version(ShinVersion::HigurashiSui).unwrap()
+            },
+            hyphenator: None,
+        };
+        let split = |message| {
+            let bump = Bump::new();
+            message_parser::reflow_split_pages(
+                &bump,
+                bump.alloc_str(message),
+                MessageCommandStyle::Escaped,
+                params,
+            )
+            .iter()
+            .map(|page| page.to_string())
+            .collect::<std::vec::Vec<_>>()
+        };
+
+        // the line break inserted by the reflow doesn't make the first line a name
+        assert_eq!(split("aaaa bbbb cccc dddd"), ["aaaa bbbb ", "cccc dddd"]);
+        assert_eq!(
+            split("[Name]@raaaa bbbb cccc dddd"),
+            ["[Name]@raaaa bbbb ", "[Name]@rcccc dddd"]
+        );
+        assert_eq!(
+            split("@raaaa bbbb cccc dddd"),
+            ["@raaaa bbbb ", "@rcccc dddd"]
+        );
+    }
+
     #[test]
     fn smoke_message() {
         assert_eq!(reflow_message(
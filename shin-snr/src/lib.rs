@@ -1,7 +1,12 @@
+pub mod detect;
+pub mod glossary;
+pub mod header;
 pub mod layout;
 pub mod location_painter;
 pub mod operation;
 pub mod reactor;
 pub mod reader;
+pub mod substitution;
 mod text;
+pub mod translation_memory;
 pub mod writer;
@@ -4,4 +4,4 @@ mod repr;
 pub mod schema;
 pub mod serialize;
 
-pub use repr::OperationElementRepr;
+pub use repr::{NumberArrayKind, OperationElementRepr};
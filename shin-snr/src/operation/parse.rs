@@ -2,7 +2,7 @@ use shin_versions::{LengthKind, NumberStyle};
 
 use crate::{
     operation::{arena::OperationArena, schema::OperationElement},
-    reader::Reader,
+    reader::{ReadError, Reader},
 };
 
 #[derive(Default)]
@@ -136,14 +136,24 @@ impl<'a, 'snr> InstructionParseContext<'a, 'snr> {
 
     // compounds
     fn take_expression(&mut self) {
+        let position = self.reader.position();
         let mut length = 0u16;
         let mut number_count = 0u16;
 
         loop {
             let code = self.reader.take_u8();
+            if self.reader.error().is_some() {
+                // there is no terminator to find
+                break;
+            }
             // TODO: I am a bit unsure whether it's a good idea to push the 0xff terminator
             self.arena.u8s.push(code);
-            length = length.checked_add(1).expect("expression too long"); // TODO: fallible parsing?
+            let Some(new_length) = length.checked_add(1) else {
+                self.reader
+                    .set_error(ReadError::ExpressionTooLong { position });
+                break;
+            };
+            length = new_length;
             match code {
                 0x00 => {
                     self.take_number();
@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use shin_versions::{AnyStringKind, StringArrayKind, StringKind};
 
 use crate::{
@@ -11,10 +13,14 @@ use crate::{
 
 pub mod dump_bin;
 pub mod offset_validator;
+mod parse_error;
 pub mod rewrite;
+pub mod speaker_names;
 pub mod string_roundrip_validator;
 pub mod trace;
 
+pub use self::parse_error::{ParseError, ParseErrorKind};
+
 pub trait Reactor {
     fn react(
         &mut self,
@@ -27,34 +33,85 @@ pub trait Reactor {
     fn end_of_stream(&mut self) {}
 }
 
-pub fn react_with<R: Reactor>(mut reader: Reader, schema: &EngineSchema, reactor: &mut R) {
+/// A reactor that does nothing, useful to only check that the file parses
+impl Reactor for () {
+    fn react(
+        &mut self,
+        _operation_position: u32,
+        _raw_opcode: u8,
+        _opcode: Opcode,
+        _op_schema: &OperationSchema,
+        _arena: &OperationArena,
+    ) {
+    }
+}
+
+/// Parses the operations, feeding them to the reactor.
+///
+/// If an operation fails to parse, the reactor doesn't see it and [`Reactor::end_of_stream`] is not called.
+pub fn react_with<R: Reactor>(
+    reader: Reader,
+    schema: &EngineSchema,
+    reactor: &mut R,
+) -> Result<(), ParseError> {
+    let start = reader.clone();
+    react_fallible(reader, schema, reactor).map_err(|(kind, position, last_operations)| {
+        ParseError::new(kind, position, last_operations, &start, schema)
+    })
+}
+
+type RawParseError = (ParseErrorKind, u32, VecDeque<(u32, Opcode)>);
+
+/// Does the actual parsing for [`react_with`], without collecting the diagnostics
+pub(crate) fn react_fallible<R: Reactor>(
+    mut reader: Reader,
+    schema: &EngineSchema,
+    reactor: &mut R,
+) -> Result<(), RawParseError> {
     let mut arena = OperationArena::new();
+    let mut last_operations = VecDeque::new();
 
     while reader.has_instr() {
         let operation_position = reader.position();
         let raw_opcode = reader.take_u8();
 
         let Some(opcode) = schema.lookup_opcode(raw_opcode) else {
-            panic!(
-                "Undefined opcode: 0x{:02x} @ 0x{:08x}",
-                raw_opcode, operation_position
-            );
+            return Err((
+                ParseErrorKind::UndefinedOpcode { raw_opcode },
+                operation_position,
+                last_operations,
+            ));
         };
         let Some(op_schema) = schema.lookup_operation(opcode) else {
-            panic!(
-                "Opcode with undefined schema: {:?} @ 0x{:08x}",
-                opcode, operation_position
-            );
+            return Err((
+                ParseErrorKind::UndefinedSchema { opcode },
+                operation_position,
+                last_operations,
+            ));
         };
 
         // eprintln!("{:08x} {:?}", operation_position, opcode);
 
         op_schema.parse(schema.number_style(), &mut reader, &mut arena);
+        if let Some(error) = reader.error() {
+            return Err((
+                ParseErrorKind::Read(error),
+                operation_position,
+                last_operations,
+            ));
+        }
 
         reactor.react(operation_position, raw_opcode, opcode, &op_schema, &arena);
+
+        if last_operations.len() == parse_error::RECENT_OPERATIONS {
+            last_operations.pop_front();
+        }
+        last_operations.push_back((operation_position, opcode));
     }
 
     reactor.end_of_stream();
+
+    Ok(())
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -64,6 +121,17 @@ pub enum AnyStringSource {
 }
 
 impl AnyStringSource {
+    pub fn from_kind(kind: AnyStringKind, subindex: u32) -> Self {
+        match kind {
+            AnyStringKind::Singular(singular) => {
+                AnyStringSource::Singular(StringSource::from_kind(singular, subindex))
+            }
+            AnyStringKind::Array(array) => {
+                AnyStringSource::Array(StringArraySource::from_kind(array), subindex)
+            }
+        }
+    }
+
     pub fn kind(&self) -> AnyStringKind {
         match self {
             AnyStringSource::Singular(s) => AnyStringKind::Singular(s.kind()),
@@ -107,6 +175,11 @@ pub enum StringSource {
     // Alias Carnival
     Named,
     Stageinfo,
+    // Header tables, with the index of the entry
+    BgmName(u32),
+    TipsTitle(u32),
+    TipsContent(u32),
+    CharacterName(u32),
 }
 
 impl StringSource {
@@ -114,6 +187,23 @@ impl StringSource {
         opcode: Opcode,
         op_schema: &OperationSchema,
         arena: &OperationArena,
+    ) -> Option<Self> {
+        Self::for_opcode(opcode, || {
+            let OperationElementRepr::U32(first_argument) = arena
+                .iter(op_schema)
+                .next()
+                .expect("Expected MSGSET to have at least one element")
+            else {
+                panic!("Expected the first MSGSET element to be u32");
+            };
+            first_argument
+        })
+    }
+
+    /// Like [`StringSource::for_operation`], but takes the first argument of MSGSET from the callback instead of the parsed operation
+    pub(crate) fn for_opcode(
+        opcode: Opcode,
+        msgset_argument: impl FnOnce() -> u32,
     ) -> Option<Self> {
         match opcode {
             Opcode::Instruction(_) => None,
@@ -121,16 +211,9 @@ impl StringSource {
                 Command::SAVEINFO => Some(StringSource::Saveinfo),
                 Command::SELECT => Some(StringSource::Select),
                 Command::MSGSET => {
-                    let OperationElementRepr::U32(first_argument) = arena
-                        .iter(op_schema)
-                        .next()
-                        .expect("Expected MSGSET to have at least one element")
-                    else {
-                        panic!("Expected the first MSGSET element to be u32");
-                    };
                     // the lower 24-bit are the message id
                     // the top 8 bits are some other thing
-                    Some(StringSource::Msgset(first_argument & 0xffffff))
+                    Some(StringSource::Msgset(msgset_argument() & 0xffffff))
                 }
                 Command::DEBUGOUT => Some(StringSource::Dbgout),
                 Command::LOGSET => Some(StringSource::Logset),
@@ -155,6 +238,10 @@ impl StringSource {
             StringKind::Chatset => StringSource::Chatset,
             StringKind::Named => StringSource::Named,
             StringKind::Stageinfo => StringSource::Stageinfo,
+            StringKind::BgmName => StringSource::BgmName(subindex),
+            StringKind::TipsTitle => StringSource::TipsTitle(subindex),
+            StringKind::TipsContent => StringSource::TipsContent(subindex),
+            StringKind::CharacterName => StringSource::CharacterName(subindex),
         }
     }
 
@@ -169,6 +256,10 @@ impl StringSource {
             StringSource::Chatset => StringKind::Chatset,
             StringSource::Named => StringKind::Named,
             StringSource::Stageinfo => StringKind::Stageinfo,
+            StringSource::BgmName(_) => StringKind::BgmName,
+            StringSource::TipsTitle(_) => StringKind::TipsTitle,
+            StringSource::TipsContent(_) => StringKind::TipsContent,
+            StringSource::CharacterName(_) => StringKind::CharacterName,
         }
     }
 
@@ -183,6 +274,10 @@ impl StringSource {
             StringSource::Chatset => 0,
             StringSource::Named => 0,
             StringSource::Stageinfo => 0,
+            StringSource::BgmName(i) => i,
+            StringSource::TipsTitle(i) => i,
+            StringSource::TipsContent(i) => i,
+            StringSource::CharacterName(i) => i,
         }
     }
 
@@ -212,6 +307,10 @@ impl StringArraySource {
         _op_schema: &OperationSchema,
         _arena: &OperationArena,
     ) -> Option<Self> {
+        Self::for_opcode(opcode)
+    }
+
+    pub(crate) fn for_opcode(opcode: Opcode) -> Option<Self> {
         match opcode {
             Opcode::Instruction(_) => None,
             Opcode::Command(cmd) => match cmd {
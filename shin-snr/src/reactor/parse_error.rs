@@ -0,0 +1,225 @@
+use std::{collections::VecDeque, fmt};
+
+use shin_versions::ShinVersion;
+
+use crate::{
+    operation::schema::{ENGINE_SCHEMAS, EngineSchema, Opcode},
+    reader::{ReadError, Reader},
+};
+
+/// How many of the last parsed operations to keep for the error report
+pub(super) const RECENT_OPERATIONS: usize = 8;
+/// How many bytes before and after the error position to include in the error report
+const CONTEXT_BEFORE: usize = 32;
+const CONTEXT_AFTER: usize = 32;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    UndefinedOpcode { raw_opcode: u8 },
+    UndefinedSchema { opcode: Opcode },
+    Read(ReadError),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::UndefinedOpcode { raw_opcode } => {
+                write!(f, "undefined opcode 0x{:02x}", raw_opcode)
+            }
+            ParseErrorKind::UndefinedSchema { opcode } => {
+                write!(f, "opcode {:?} has no schema defined", opcode)
+            }
+            ParseErrorKind::Read(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// An error encountered while parsing the SNR bytecode, along with the information needed to diagnose it.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Position of the operation that failed to parse
+    pub position: u32,
+    /// Raw bytes around the failed operation
+    pub context: Vec<u8>,
+    /// Position of the first byte in `context`
+    pub context_start: u32,
+    /// Positions and opcodes of the last successfully parsed operations, oldest first
+    pub last_operations: Vec<(u32, Opcode)>,
+    /// The version the file was parsed as, if the schema is one of [`ENGINE_SCHEMAS`]
+    pub version: Option<ShinVersion>,
+    /// Other versions that can parse the file without errors
+    pub suspected_versions: Vec<ShinVersion>,
+}
+
+impl ParseError {
+    pub(super) fn new(
+        kind: ParseErrorKind,
+        position: u32,
+        last_operations: VecDeque<(u32, Opcode)>,
+        start: &Reader,
+        schema: &EngineSchema,
+    ) -> Self {
+        let data = start.data();
+        // align the hexdump lines to 16 bytes
+        let context_start = (position as usize).saturating_sub(CONTEXT_BEFORE) / 16 * 16;
+        let context_end = (position as usize + CONTEXT_AFTER).min(data.len());
+        let context = data[context_start..context_end].to_vec();
+
+        let version = ENGINE_SCHEMAS
+            .iter()
+            .find(|&(_, other)| std::ptr::eq(other, schema))
+            .map(|(version, _)| version);
+        // a wrong engine version is the most common cause, so try to find the right one
+        let suspected_versions = ENGINE_SCHEMAS
+            .iter()
+            .filter(|&(_, other)| !std::ptr::eq(other, schema))
+            .filter(|&(_, other)| super::react_fallible(start.clone(), other, &mut ()).is_ok())
+            .map(|(version, _)| version)
+            .collect();
+
+        Self {
+            kind,
+            position,
+            context,
+            context_start: context_start as u32,
+            last_operations: last_operations.into(),
+            version,
+            suspected_versions,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to parse the SNR operation at 0x{:08x}: {}",
+            self.position, self.kind
+        )?;
+        if let Some(version) = self.version {
+            write!(f, " (parsing as {})", version.name())?;
+        }
+        writeln!(f)?;
+
+        writeln!(f)?;
+        writeln!(f, "Bytes around the error:")?;
+        for (line_index, line) in self.context.chunks(16).enumerate() {
+            let line_start = self.context_start as usize + line_index * 16;
+            write!(f, "  {:08x}:", line_start)?;
+            for (i, byte) in line.iter().enumerate() {
+                // mark the start of the failed operation
+                let separator = if line_start + i == self.position as usize {
+                    '>'
+                } else {
+                    ' '
+                };
+                write!(f, "{}{:02x}", separator, byte)?;
+            }
+            writeln!(f)?;
+        }
+
+        if !self.last_operations.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Last parsed operations:")?;
+            for (position, opcode) in &self.last_operations {
+                writeln!(f, "  {:08x}: {:?}", position, opcode)?;
+            }
+        }
+
+        writeln!(f)?;
+        if self.suspected_versions.is_empty() {
+            write!(
+                f,
+                "None of the known engine versions can parse this file. It might be corrupted or come from an unsupported game."
+            )?;
+        } else {
+            let names = self
+                .suspected_versions
+                .iter()
+                .map(|version| version.name())
+                .collect::<Vec<_>>()
+                .join(", ");
+            write!(
+                f,
+                "The file can be parsed as: {}. Did you pass the wrong engine version?",
+                names
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod test {
+    use shin_versions::ShinVersion;
+
+    use super::ParseErrorKind;
+    use crate::{
+        operation::schema::ENGINE_SCHEMAS,
+        reactor::react_with,
+        reader::{ReadError, Reader},
+    };
+
+    // SAVEINFO 0, "共通ルート"
+    const SAVEINFO: [u8; 15] = [
+        0xa0, 0x00, 0x00, 0x0b, 0x8b, 0xa4, 0x92, 0xca, 0x83, 0x8b, 0x81, 0x5b, 0x83, 0x67, 0x00,
+    ];
+
+    #[test]
+    fn undefined_opcode() {
+        let mut data = SAVEINFO.to_vec();
+        data.push(0xff);
+        data.resize(64, 0);
+
+        let error = react_with(
+            Reader::new(&data, 0),
+            &ENGINE_SCHEMAS[ShinVersion::HigurashiSui],
+            &mut (),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::UndefinedOpcode { raw_opcode: 0xff }
+        );
+        assert_eq!(error.position, 15);
+        assert_eq!(error.context_start, 0);
+        assert_eq!(&error.context[..16], &data[..16]);
+        assert_eq!(error.version, Some(ShinVersion::HigurashiSui));
+        let [(0, opcode)] = error.last_operations.as_slice() else {
+            panic!("unexpected operations: {:?}", error.last_operations);
+        };
+        assert_eq!(format!("{:?}", opcode), "SAVEINFO");
+    }
+
+    #[test]
+    fn wrong_version() {
+        // the padding at the end of the file
+        let mut data = SAVEINFO.to_vec();
+        data.resize(SAVEINFO.len() + 16, 0);
+
+        let error = react_with(
+            Reader::new(&data, 0),
+            &ENGINE_SCHEMAS[ShinVersion::Umineko],
+            &mut (),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::Read(ReadError::UnexpectedEof {
+                position: 4,
+                size: 0x0b00
+            })
+        );
+        assert!(
+            error
+                .suspected_versions
+                .contains(&ShinVersion::HigurashiSui)
+        );
+    }
+}
@@ -2,11 +2,22 @@ use std::io;
 
 use bumpalo::Bump;
 use serde::{Deserialize, de};
-use shin_versions::{AnyStringKind, MessageCommandStyle};
+use shin_text::CharRemap;
+use shin_versions::{AnyStringKind, MessageCommandStyle, StringEncoding};
 
 use crate::{
     layout::message_parser::lint::diagnostics::LineReport,
-    reactor::{AnyStringSource, StringArraySource, StringSource, rewrite::StringRewriter},
+    reactor::{
+        AnyStringSource,
+        rewrite::{
+            StringReplacementMode, StringRewriter, TranslationStats,
+            entry::{Entries, Entry, LintOptions},
+            stats::collect_stats,
+        },
+        speaker_names::SpeakerNames,
+        trace::TracedString,
+    },
+    substitution::SubstitutionTable,
 };
 
 fn deser_hex<'de, D: serde::Deserializer<'de>>(deser: D) -> Result<u32, D::Error> {
@@ -35,128 +46,164 @@ struct RawEntry {
     offset: u32,
     source: AnyStringKind,
     source_subindex: u32,
+    #[serde(default)]
+    speaker: Option<String>,
     s: String,
     translated: Option<String>,
 }
 
 impl RawEntry {
-    fn into_entry(self) -> Entry {
+    fn into_entry(self, speaker_names: Option<&SpeakerNames>) -> Entry {
+        // merge the speaker name back into the message
+        // the messages without a translation are left as-is, even if their speaker name is translated
+        let (s, translated, speaker_lens) = match self.speaker {
+            Some(speaker) => {
+                let speaker_translated = speaker_names
+                    .and_then(|names| names.translate(&speaker))
+                    .unwrap_or(&speaker);
+                let translated = self
+                    .translated
+                    .map(|translated| format!("{}{}", speaker_translated, translated));
+                (
+                    format!("{}{}", speaker, self.s),
+                    translated,
+                    (speaker.len(), speaker_translated.len()),
+                )
+            }
+            None => (self.s, self.translated, (0, 0)),
+        };
+
         Entry {
             offset: self.offset,
-            source: match self.source {
-                AnyStringKind::Singular(singular) => AnyStringSource::Singular(
-                    StringSource::from_kind(singular, self.source_subindex),
-                ),
-                AnyStringKind::Array(array) => AnyStringSource::Array(
-                    StringArraySource::from_kind(array),
-                    self.source_subindex,
-                ),
-            },
-            s: self.s,
-            translated: self.translated,
+            source: AnyStringSource::from_kind(self.source, self.source_subindex),
+            s,
+            translated,
+            speaker_lens,
         }
     }
 }
 
-#[derive(Default, Debug, Clone, Copy)]
-#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
-pub enum StringReplacementMode {
-    /// Replace strings with the value from `translated` column, leaving those that don't have a translation as-is. The `s` column will be ignored.
-    #[default]
-    TranslatedOnly,
-    /// Replace strings with the value from `translated` column, falling back to `s` column if `translated` is not present. This is the old default behavior.   
-    TranslatedOrOriginal,
-}
-
-#[derive(Clone)]
-struct Entry {
-    offset: u32,
-    source: AnyStringSource,
-    s: String,
-    translated: Option<String>,
-}
-
-impl Entry {
-    pub fn get_effective_string(&self, mode: StringReplacementMode) -> Option<&str> {
-        match (&self.translated, mode) {
-            (Some(translated), _) => Some(translated),
-            (None, StringReplacementMode::TranslatedOnly) => None,
-            (None, StringReplacementMode::TranslatedOrOriginal) => Some(&self.s),
-        }
-    }
-}
+fn read_csv<R: io::Read>(
+    mut reader: csv::Reader<R>,
+    into_entry: impl Fn(RawEntry) -> Entry,
+) -> (Entries, bool) {
+    let has_speakers = reader
+        .headers()
+        .unwrap()
+        .iter()
+        .any(|header| header == "speaker");
 
-fn read_csv<R: io::Read>(reader: csv::Reader<R>) -> Vec<Option<Entry>> {
-    let mut result = vec![None; 64];
+    let mut result = Entries::new();
 
     for (index, entry) in reader
         .into_deserialize()
         .map(|r| r.unwrap())
-        .map(|v: RawEntry| (v.index, v.into_entry()))
+        .map(|v: RawEntry| (v.index, into_entry(v)))
     {
-        if result.len() <= index as usize {
-            result.resize_with(result.len() * 2, || None);
-        }
-
-        result[index as usize] = Some(entry);
+        result.insert(index, entry);
     }
 
-    result
+    (result, has_speakers)
 }
 
 pub struct CsvData {
-    entries: Vec<Option<Entry>>,
+    pub(super) entries: Entries,
+    has_speakers: bool,
 }
 
 impl CsvData {
-    pub fn new<R: io::Read>(reader: csv::Reader<R>) -> Self {
+    /// Reads the translation CSV file.
+    ///
+    /// If the file has a `speaker` column, the speaker names are joined back with the messages, using translations from `speaker_names` when available.
+    pub fn new<R: io::Read>(reader: csv::Reader<R>, speaker_names: Option<&SpeakerNames>) -> Self {
+        let (entries, has_speakers) = read_csv(reader, |entry| entry.into_entry(speaker_names));
+        Self {
+            entries,
+            has_speakers,
+        }
+    }
+
+    /// Reads the translation CSV file, leaving the translations as they are in the file.
+    ///
+    /// The speaker names are still joined with the original strings, so that they match the strings in the SNR file, but not with the translations.
+    /// Used to migrate the translations, which are then written to a CSV file with the `speaker` column again.
+    pub fn new_unmerged<R: io::Read>(reader: csv::Reader<R>) -> Self {
+        let (entries, has_speakers) = read_csv(reader, |entry| Entry {
+            offset: entry.offset,
+            source: AnyStringSource::from_kind(entry.source, entry.source_subindex),
+            s: match &entry.speaker {
+                Some(speaker) => format!("{}{}", speaker, entry.s),
+                None => entry.s,
+            },
+            translated: entry.translated,
+            speaker_lens: (entry.speaker.map_or(0, |speaker| speaker.len()), 0),
+        });
         Self {
-            entries: read_csv(reader),
+            entries,
+            has_speakers,
         }
     }
 
-    pub fn lint(
+    /// Whether the file has a `speaker` column, with the speaker names split off the messages
+    pub fn has_speakers(&self) -> bool {
+        self.has_speakers
+    }
+
+    /// Returns the translation of the string, checking that the translation file corresponds to the SNR
+    pub fn get_translated(
+        &self,
+        instr_index: u32,
+        instr_offset: u32,
+        source: AnyStringSource,
+    ) -> Option<&str> {
+        self.entries
+            .get(instr_index, instr_offset, source)?
+            .translated
+            .as_deref()
+            .filter(|translated| !translated.is_empty())
+    }
+
+    /// Fills in untranslated strings from other strings with the same text. Returns the number of filled strings.
+    pub fn propagate_repeats(&mut self) -> usize {
+        self.entries.propagate_repeats()
+    }
+
+    /// Collects the translation progress statistics, see [`collect_stats`] for details
+    ///
+    /// If the file has the `speaker` column, the speaker names are not counted, as they are translated separately.
+    /// In this case, the file should be read with [`CsvData::new_unmerged`], so that the names are not joined with the translations either.
+    pub fn stats(
         &self,
-        mode: StringReplacementMode,
+        strings: &[TracedString],
         style: MessageCommandStyle,
-    ) -> Result<(), Vec<LineReport>> {
-        let mut reports = Vec::new();
-
-        let mut bump = Bump::new();
-        for (index, line) in (0..).zip(&self.entries) {
-            let Some(line) = line else {
-                continue;
-            };
-            let Some(s) = line.get_effective_string(mode) else {
-                continue;
-            };
-
-            if let Err(report) = crate::layout::message_parser::lint::lint_string(
-                &bump,
-                s,
-                style,
-                line.source,
-                index,
-            ) {
-                reports.push(report);
-                if reports.len() >= 64 {
-                    // too many errors, stop
-                    break;
-                }
-            }
-            bump.reset();
-        }
+        id_range_size: u32,
+    ) -> TranslationStats {
+        collect_stats(
+            strings,
+            &self.entries,
+            self.has_speakers,
+            style,
+            id_range_size,
+        )
+    }
 
-        if reports.is_empty() {
-            Ok(())
-        } else {
-            Err(reports)
-        }
+    /// Replaces the characters that can't be encoded in the translations. Returns the number of changed strings.
+    pub fn substitute(
+        &mut self,
+        substitutions: &SubstitutionTable,
+        encoding: StringEncoding,
+        char_remap: &CharRemap,
+    ) -> usize {
+        self.entries.substitute(substitutions, encoding, char_remap)
+    }
+
+    pub fn lint(&self, options: &LintOptions) -> Result<(), Vec<LineReport>> {
+        self.entries.lint(options)
     }
 }
 
 pub struct CsvRewriter {
-    entries: Vec<Option<Entry>>,
+    entries: Entries,
     mode: StringReplacementMode,
 }
 
@@ -178,17 +225,8 @@ impl StringRewriter for CsvRewriter {
         instr_offset: u32,
         source: AnyStringSource,
     ) -> Option<&'bump str> {
-        // no entry -> no replacement
-        let entry = self.entries[instr_index as usize].as_ref()?;
-        assert_eq!(
-            entry.offset, instr_offset,
-            "Offset mismatch while matching CSV entry. Does the CSV file correspond to the SNR?"
-        );
-        assert_eq!(
-            entry.source, source,
-            "Source mismatch while matching CSV entry. Does the CSV file correspond to the SNR?"
-        );
-
-        entry.get_effective_string(self.mode)
+        self.entries
+            .get(instr_index, instr_offset, source)?
+            .get_effective_string(self.mode)
     }
 }
@@ -0,0 +1,356 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    operation::{
+        arena::{Number, Offset, Register},
+        schema::{EngineSchema, Opcode, OperationElement},
+    },
+    reactor::{AnyStringSource, StringArraySource, StringSource},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum InsertedOperationError {
+    #[error("Opcode {0:?} is not defined in this engine version")]
+    UndefinedOpcode(Opcode),
+    #[error("Expected {expected} elements for {opcode:?}, got {actual}")]
+    ElementCount {
+        opcode: Opcode,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Element #{index} of {opcode:?} should be {expected:?}, got {actual:?}")]
+    ElementMismatch {
+        opcode: Opcode,
+        index: usize,
+        expected: OperationElement,
+        actual: InsertedElement,
+    },
+    #[error("Opcode {0:?} has strings, but the kind of string is unknown")]
+    UnknownStringSource(Opcode),
+}
+
+/// An element of an operation to be inserted into the code.
+///
+/// Offsets refer to the positions in the original file, they are mapped to the output the same way as the ones in the existing operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertedElement {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    Register(Register),
+    RegisterArray(Vec<Register>),
+    Offset(Offset),
+    OffsetArray(Vec<Offset>),
+    /// A number in the same compressed representation as produced by the parser (see [`crate::reader::Reader::take_number`])
+    Number(Number),
+    /// Fits both the padded and the dense number arrays
+    NumberArray(Vec<Number>),
+    /// A string in the user message command style. It is encoded the same way as the translated strings are.
+    String(String),
+    StringArray(Vec<String>),
+}
+
+impl InsertedElement {
+    fn matches(&self, element: OperationElement) -> bool {
+        matches!(
+            (self, element),
+            (InsertedElement::U8(_), OperationElement::U8)
+                | (InsertedElement::U16(_), OperationElement::U16)
+                | (InsertedElement::U32(_), OperationElement::U32)
+                | (InsertedElement::Register(_), OperationElement::Register)
+                | (
+                    InsertedElement::RegisterArray(_),
+                    OperationElement::RegisterArray(_)
+                )
+                | (InsertedElement::Offset(_), OperationElement::Offset)
+                | (
+                    InsertedElement::OffsetArray(_),
+                    OperationElement::OffsetArray(_)
+                )
+                | (InsertedElement::Number(_), OperationElement::Number)
+                | (
+                    InsertedElement::NumberArray(_),
+                    OperationElement::NumberArray(_) | OperationElement::PadNumberArray(_)
+                )
+                | (InsertedElement::String(_), OperationElement::String(_))
+                | (
+                    InsertedElement::StringArray(_),
+                    OperationElement::StringArray(_)
+                )
+        )
+    }
+}
+
+/// A new operation to be inserted into the code, checked against the engine schema
+#[derive(Debug, Clone)]
+pub struct InsertedOperation {
+    pub(super) raw_opcode: u8,
+    pub(super) elements: Vec<(OperationElement, InsertedElement)>,
+    pub(super) string_source: Option<StringSource>,
+    pub(super) string_array_source: Option<StringArraySource>,
+}
+
+impl InsertedOperation {
+    /// Makes an operation with the specified elements, checking that they match the schema of the operation.
+    ///
+    /// Operations with special elements (like expressions or optional numbers) can't be constructed.
+    pub fn new(
+        schema: &EngineSchema,
+        opcode: Opcode,
+        elements: Vec<InsertedElement>,
+    ) -> Result<Self, InsertedOperationError> {
+        let raw_opcode = (0..=u8::MAX)
+            .find(|&raw_opcode| schema.lookup_opcode(raw_opcode) == Some(opcode))
+            .ok_or(InsertedOperationError::UndefinedOpcode(opcode))?;
+        let op_schema = schema
+            .lookup_operation(opcode)
+            .ok_or(InsertedOperationError::UndefinedOpcode(opcode))?;
+
+        if op_schema.elements.len() != elements.len() {
+            return Err(InsertedOperationError::ElementCount {
+                opcode,
+                expected: op_schema.elements.len(),
+                actual: elements.len(),
+            });
+        }
+        for (index, (&expected, actual)) in op_schema.elements.iter().zip(&elements).enumerate() {
+            if !actual.matches(expected) {
+                return Err(InsertedOperationError::ElementMismatch {
+                    opcode,
+                    index,
+                    expected,
+                    actual: actual.clone(),
+                });
+            }
+        }
+
+        let string_source = StringSource::for_opcode(opcode, || match elements.first() {
+            Some(&InsertedElement::U32(value)) => value,
+            _ => unreachable!("the schema was checked above"),
+        });
+        let string_array_source = StringArraySource::for_opcode(opcode);
+        let has_strings = |f: fn(&InsertedElement) -> bool| elements.iter().any(f);
+        if string_source.is_none() && has_strings(|e| matches!(e, InsertedElement::String(_)))
+            || string_array_source.is_none()
+                && has_strings(|e| matches!(e, InsertedElement::StringArray(_)))
+        {
+            return Err(InsertedOperationError::UnknownStringSource(opcode));
+        }
+
+        Ok(Self {
+            raw_opcode,
+            elements: op_schema.elements.iter().copied().zip(elements).collect(),
+            string_source,
+            string_array_source,
+        })
+    }
+
+    pub(super) fn string_source(&self) -> Option<AnyStringSource> {
+        self.string_source.map(AnyStringSource::Singular)
+    }
+}
+
+/// Changes to the sequence of operations, applied during the rewrite.
+///
+/// Operations are referred to by their offsets in the original file. The jumps are fixed up to account for the added and removed code:
+/// - a jump to an operation lands on the operations inserted before it, so they are executed on every path leading to it
+/// - a jump to a removed operation lands on whatever follows it
+///
+/// The strings of the removed operations are still counted when matching the translations by index, so the translation files don't need to be changed.
+#[derive(Debug, Default, Clone)]
+pub struct OperationEdits {
+    before: HashMap<u32, Vec<InsertedOperation>>,
+    after: HashMap<u32, Vec<InsertedOperation>>,
+    removed: HashSet<u32>,
+}
+
+impl OperationEdits {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty() && self.removed.is_empty()
+    }
+
+    /// Inserts an operation before the one at the specified offset, after the ones inserted previously
+    pub fn insert_before(&mut self, offset: u32, operation: InsertedOperation) {
+        self.before.entry(offset).or_default().push(operation);
+    }
+
+    /// Inserts an operation after the one at the specified offset, after the ones inserted previously
+    pub fn insert_after(&mut self, offset: u32, operation: InsertedOperation) {
+        self.after.entry(offset).or_default().push(operation);
+    }
+
+    /// Removes the operation at the specified offset. The operations inserted around it are kept.
+    pub fn remove(&mut self, offset: u32) {
+        self.removed.insert(offset);
+    }
+
+    pub(super) fn before(&self, offset: u32) -> &[InsertedOperation] {
+        self.before.get(&offset).map_or(&[], Vec::as_slice)
+    }
+
+    pub(super) fn after(&self, offset: u32) -> &[InsertedOperation] {
+        self.after.get(&offset).map_or(&[], Vec::as_slice)
+    }
+
+    pub(super) fn is_removed(&self, offset: u32) -> bool {
+        self.removed.contains(&offset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use shin_versions::ShinVersion;
+
+    use super::{InsertedElement, InsertedOperation, InsertedOperationError, OperationEdits};
+    use crate::{
+        layout::message_parser::MessageReflowMode,
+        operation::{
+            OperationElementRepr,
+            arena::OperationArena,
+            schema::{Command, ENGINE_SCHEMAS, Instruction, Opcode, OperationSchema},
+        },
+        reactor::{Reactor, react_with, rewrite::RewriteReactor},
+        reader::Reader,
+    };
+
+    const VERSION: ShinVersion = ShinVersion::HigurashiSui;
+
+    #[derive(Default)]
+    struct Collector {
+        operations: Vec<(u32, Opcode, Vec<u32>)>,
+    }
+
+    impl Reactor for Collector {
+        fn react(
+            &mut self,
+            operation_position: u32,
+            _raw_opcode: u8,
+            opcode: Opcode,
+            op_schema: &OperationSchema,
+            arena: &OperationArena,
+        ) {
+            let offsets = arena
+                .iter(op_schema)
+                .filter_map(|element| match element {
+                    OperationElementRepr::Offset(offset) => Some(offset),
+                    _ => None,
+                })
+                .collect();
+            self.operations.push((operation_position, opcode, offsets));
+        }
+    }
+
+    fn collect(data: &[u8]) -> Vec<(u32, Opcode, Vec<u32>)> {
+        let mut collector = Collector::default();
+        react_with(
+            Reader::new(data, 0),
+            &ENGINE_SCHEMAS[VERSION],
+            &mut collector,
+        )
+        .unwrap();
+        collector.operations
+    }
+
+    fn rewrite(data: &[u8], edits: OperationEdits) -> Vec<u8> {
+        let schema = &ENGINE_SCHEMAS[VERSION];
+        let mut reactor = RewriteReactor::new(
+            VERSION.number_style(),
+            VERSION.message_command_style(),
+            VERSION.message_command_style(),
+            VERSION.has_needless_escapes(),
+            MessageReflowMode::NoReflow,
+            VERSION.string_policy(),
+            (),
+            0,
+        )
+        .with_edits(edits);
+        react_with(Reader::new(data, 0), schema, &mut reactor).unwrap();
+        let mut reactor = reactor.into_emit(Vec::new());
+        react_with(Reader::new(data, 0), schema, &mut reactor).unwrap();
+        reactor.finish()
+    }
+
+    fn jump(target: u32) -> InsertedOperation {
+        InsertedOperation::new(
+            &ENGINE_SCHEMAS[VERSION],
+            Opcode::Instruction(Instruction::j),
+            vec![InsertedElement::Offset(target)],
+        )
+        .unwrap()
+    }
+
+    fn saveinfo(s: &str) -> InsertedOperation {
+        InsertedOperation::new(
+            &ENGINE_SCHEMAS[VERSION],
+            Opcode::Command(Command::SAVEINFO),
+            vec![
+                InsertedElement::Number(0),
+                InsertedElement::String(s.to_string()),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let schema = &ENGINE_SCHEMAS[VERSION];
+        // SAVEINFO 0, "共通ルート"; j 0x14; j 0x0f; j 0x00; padding
+        let mut data = vec![
+            0xa0, 0x00, 0x00, 0x0b, 0x8b, 0xa4, 0x92, 0xca, 0x83, 0x8b, 0x81, 0x5b, 0x83, 0x67,
+            0x00,
+        ];
+        let raw_j = (0..=u8::MAX)
+            .find(|&raw| schema.lookup_opcode(raw) == Some(Opcode::Instruction(Instruction::j)))
+            .unwrap();
+        for target in [0x14u32, 0x0f, 0x00] {
+            data.push(raw_j);
+            data.extend_from_slice(&target.to_le_bytes());
+        }
+        data.resize(data.len().next_multiple_of(16), 0);
+
+        // no edits, no changes
+        assert_eq!(rewrite(&data, OperationEdits::new()), data);
+
+        let mut edits = OperationEdits::new();
+        edits.insert_before(0x0f, saveinfo("abc"));
+        edits.insert_after(0x0f, jump(0));
+        edits.remove(0x14);
+        let output = rewrite(&data, edits);
+
+        let saveinfo = Opcode::Command(Command::SAVEINFO);
+        let j = Opcode::Instruction(Instruction::j);
+        // SAVEINFO "abc" takes 1 + 2 + 1 + 4 bytes
+        assert_eq!(
+            collect(&output),
+            [
+                (0x00, saveinfo, vec![]),
+                (0x0f, saveinfo, vec![]),
+                // the jump to the removed operation goes to whatever follows it
+                (0x17, j, vec![0x21]),
+                (0x1c, j, vec![0x00]),
+                (0x21, j, vec![0x00]),
+            ]
+        );
+        // the inserted string is encoded
+        assert_eq!(&output[0x13..0x17], b"abc\0");
+    }
+
+    #[test]
+    fn schema_mismatch() {
+        let error = InsertedOperation::new(
+            &ENGINE_SCHEMAS[VERSION],
+            Opcode::Instruction(Instruction::j),
+            vec![InsertedElement::U32(0)],
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            error,
+            InsertedOperationError::ElementMismatch { index: 0, .. }
+        ));
+    }
+}
@@ -0,0 +1,544 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use bumpalo::Bump;
+use miette::{SourceOffset, SourceSpan};
+use shin_font::FontMetrics;
+use shin_text::CharRemap;
+use shin_versions::{AnyStringKind, MessageCommandStyle, ShinVersion, StringEncoding};
+
+use crate::{
+    glossary::Glossary,
+    layout::message_parser::{
+        MessageReflowMode,
+        lint::{
+            self, OverflowCheck,
+            diagnostics::{LineReport, MissingGlossaryTerm, StringTooLong},
+        },
+    },
+    reactor::AnyStringSource,
+    substitution::SubstitutionTable,
+};
+
+#[derive(Default, Debug, Clone, Copy)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum StringReplacementMode {
+    /// Replace strings with the value from `translated` column, leaving those that don't have a translation as-is. The `s` column will be ignored.
+    #[default]
+    TranslatedOnly,
+    /// Replace strings with the value from `translated` column, falling back to `s` column if `translated` is not present. This is the old default behavior.
+    TranslatedOrOriginal,
+}
+
+/// Settings for linting the translations
+pub struct LintOptions<'a> {
+    pub mode: StringReplacementMode,
+    pub style: MessageCommandStyle,
+    pub version: ShinVersion,
+    /// Used to suggest replacements for the characters that can't be encoded
+    pub substitutions: &'a SubstitutionTable,
+    /// Characters that are encoded with custom codepoints
+    pub char_remap: &'a CharRemap,
+    /// Terms that must be translated consistently
+    pub glossary: &'a Glossary,
+    /// The font the strings are laid out with, to report the characters it doesn't have
+    pub font: Option<&'a FontMetrics>,
+    /// Check that the strings fit into the message and choice boxes
+    pub overflow: Option<OverflowCheck<'a>>,
+    /// How the messages are reflowed by the rewrite, to count the inserted line breaks in the string lengths
+    pub reflow: MessageReflowMode<'a>,
+}
+
+/// A single string from a translation file, regardless of its format
+#[derive(Clone)]
+pub(super) struct Entry {
+    pub offset: u32,
+    pub source: AnyStringSource,
+    pub s: String,
+    pub translated: Option<String>,
+    /// Lengths of the speaker names joined to the start of `s` and `translated` from a separate column, 0 if there are none
+    ///
+    /// Used to report the lint issues in the text of the cell with the message.
+    pub speaker_lens: (usize, usize),
+}
+
+impl Entry {
+    pub fn get_effective_string(&self, mode: StringReplacementMode) -> Option<&str> {
+        match (&self.translated, mode) {
+            (Some(translated), _) => Some(translated),
+            (None, StringReplacementMode::TranslatedOnly) => None,
+            (None, StringReplacementMode::TranslatedOrOriginal) => Some(&self.s),
+        }
+    }
+
+    /// Length of the speaker name at the start of the string returned by [`Entry::get_effective_string`]
+    fn effective_speaker_len(&self) -> usize {
+        match self.translated {
+            Some(_) => self.speaker_lens.1,
+            None => self.speaker_lens.0,
+        }
+    }
+}
+
+/// How many reports of each severity are collected by [`Entries::lint`]
+const MAX_REPORTS: usize = 64;
+
+/// Translation file entries, indexed by the string index
+pub(super) struct Entries {
+    entries: Vec<Option<Entry>>,
+}
+
+impl Entries {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![None; 64],
+        }
+    }
+
+    pub fn insert(&mut self, index: u32, entry: Entry) {
+        if self.entries.len() <= index as usize {
+            let new_len = std::cmp::max(self.entries.len() * 2, index as usize + 1);
+            self.entries.resize_with(new_len, || None);
+        }
+
+        self.entries[index as usize] = Some(entry);
+    }
+
+    /// Iterates over the present entries along with their indices
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Entry)> {
+        (0..)
+            .zip(&self.entries)
+            .filter_map(|(index, entry)| Some((index, entry.as_ref()?)))
+    }
+
+    /// Gets the entry for the string, checking that the translation file corresponds to the SNR
+    pub fn get(
+        &self,
+        instr_index: u32,
+        instr_offset: u32,
+        source: AnyStringSource,
+    ) -> Option<&Entry> {
+        // no entry -> no replacement
+        let entry = self.entries.get(instr_index as usize)?.as_ref()?;
+        assert_eq!(
+            entry.offset, instr_offset,
+            "Offset mismatch while matching translation entry. Does the translation file correspond to the SNR?"
+        );
+        assert_eq!(
+            entry.source, source,
+            "Source mismatch while matching translation entry. Does the translation file correspond to the SNR?"
+        );
+
+        Some(entry)
+    }
+
+    /// Fills in missing translations from other entries with the same original string and kind.
+    ///
+    /// If the repeats were translated differently, the first translation is used. Returns the number of filled entries.
+    pub fn propagate_repeats(&mut self) -> usize {
+        let mut translations = HashMap::new();
+        for (_, entry) in self.iter() {
+            if let Some(translated) = &entry.translated {
+                translations
+                    .entry((entry.source.kind(), entry.s.clone()))
+                    .or_insert_with(|| (translated.clone(), entry.speaker_lens.1));
+            }
+        }
+
+        let mut filled = 0;
+        for entry in self.entries.iter_mut().flatten() {
+            if entry.translated.is_none()
+                && let Some((translated, speaker_len)) =
+                    translations.get(&(entry.source.kind(), entry.s.clone()))
+            {
+                entry.translated = Some(translated.clone());
+                entry.speaker_lens.1 = *speaker_len;
+                filled += 1;
+            }
+        }
+
+        filled
+    }
+
+    /// Replaces the characters that can't be encoded in the translations. Returns the number of changed entries.
+    pub fn substitute(
+        &mut self,
+        substitutions: &SubstitutionTable,
+        encoding: StringEncoding,
+        char_remap: &CharRemap,
+    ) -> usize {
+        let mut changed = 0;
+        for entry in self.entries.iter_mut().flatten() {
+            let Some(translated) = &mut entry.translated else {
+                continue;
+            };
+            // the name is substituted separately to keep track of its length
+            let (name, message) = translated.split_at(entry.speaker_lens.1);
+            let name = substitutions.apply(name, encoding, char_remap);
+            let message = substitutions.apply(message, encoding, char_remap);
+            if let (Cow::Borrowed(_), Cow::Borrowed(_)) = (&name, &message) {
+                continue;
+            }
+            entry.speaker_lens.1 = name.len();
+            *translated = format!("{}{}", name, message);
+            changed += 1;
+        }
+
+        changed
+    }
+
+    pub fn lint(&self, options: &LintOptions) -> Result<(), Vec<LineReport>> {
+        let &LintOptions {
+            mode,
+            style,
+            version,
+            substitutions,
+            char_remap,
+            glossary,
+            font,
+            overflow,
+            reflow,
+        } = options;
+        let mut reports = Vec::new();
+        // only the errors stop the scan, the warnings are just not collected anymore
+        let mut errors = 0;
+        let mut warnings = 0;
+
+        let mut bump = Bump::new();
+
+        // string arrays have a single length prefix, so measure them as a whole
+        // untranslated elements are kept as-is, but they still take up space
+        let mut array_lengths = HashMap::<u32, Option<usize>>::new();
+        for (_, line) in self.iter() {
+            if let AnyStringSource::Array(..) = line.source {
+                let s = line.get_effective_string(mode).unwrap_or(&line.s);
+                let length = lint::measure_encoded(
+                    &bump,
+                    s,
+                    style,
+                    version,
+                    line.source,
+                    reflow,
+                    char_remap,
+                );
+                let total = array_lengths.entry(line.offset).or_insert(Some(1));
+                *total = total.zip(length).map(|(total, length)| total + length);
+                bump.reset();
+            }
+        }
+
+        for (index, line) in self.iter() {
+            let Some(s) = line.get_effective_string(mode) else {
+                continue;
+            };
+
+            let mut report = lint::lint_string(&bump, s, style, line.source, index).err();
+
+            // diagnostics from the checks not related to layout commands
+            let mut diagnostics = Vec::new();
+            lint::lint_encoding(
+                &mut diagnostics,
+                s,
+                version.string_encoding(),
+                substitutions,
+                char_remap,
+            );
+
+            let length = match line.source {
+                AnyStringSource::Singular(_) => {
+                    lint::measure_encoded(
+                        &bump,
+                        s,
+                        style,
+                        version,
+                        line.source,
+                        reflow,
+                        char_remap,
+                    )
+                    .map(|length| (length, None))
+                }
+                // report the whole array on its first translated element
+                AnyStringSource::Array(..) => array_lengths
+                    .get_mut(&line.offset)
+                    .and_then(|length| length.take())
+                    .map(|length| {
+                        (
+                            length,
+                            Some("All choices of the selection are stored together, so this includes their lengths too"),
+                        )
+                    }),
+            };
+            let limit = lint::length_limit(version, line.source.kind());
+            if let Some((length, advice)) = length
+                && length > limit
+            {
+                diagnostics.push(
+                    StringTooLong {
+                        err_span: SourceSpan::new(SourceOffset::from(0), s.len()),
+                        length,
+                        limit,
+                        advice,
+                    }
+                    .into(),
+                );
+            }
+
+            if let Some(font) = font {
+                lint::lint_missing_glyphs(&bump, &mut diagnostics, s, style, line.source, font);
+            }
+            if let Some(check) = &overflow {
+                lint::lint_overflow(&bump, &mut diagnostics, s, style, line.source, check);
+            }
+
+            // only check the actual translations, the original strings would always fail
+            if let Some(translated) = &line.translated {
+                lint::lint_command_parity(
+                    &bump,
+                    &mut diagnostics,
+                    &line.s,
+                    translated,
+                    style,
+                    line.source,
+                );
+                for (offset, term) in glossary.missing_terms(&line.s, translated) {
+                    diagnostics.push(
+                        MissingGlossaryTerm {
+                            original: line.s.clone(),
+                            err_span: SourceSpan::new(
+                                SourceOffset::from(offset),
+                                term.source.len(),
+                            ),
+                            source_term: term.source.clone(),
+                            translation: term.translation.clone(),
+                        }
+                        .into(),
+                    );
+                }
+            }
+
+            if !diagnostics.is_empty() {
+                report
+                    .get_or_insert_with(|| LineReport {
+                        src: s.to_string(),
+                        index,
+                        diagnostics: Vec::new(),
+                    })
+                    .diagnostics
+                    .extend(diagnostics);
+            }
+
+            if let Some(report) =
+                report.map(|report| report.strip_prefix(line.effective_speaker_len()))
+            {
+                if report.has_errors() {
+                    reports.push(report);
+                    errors += 1;
+                    if errors >= MAX_REPORTS {
+                        // too many errors, stop
+                        break;
+                    }
+                } else if warnings < MAX_REPORTS {
+                    reports.push(report);
+                    warnings += 1;
+                }
+            }
+            bump.reset();
+        }
+
+        if reports.is_empty() {
+            Ok(())
+        } else {
+            Err(reports)
+        }
+    }
+}
+
+/// Parses the string location in form of `index|offset|source|source_subindex`.
+///
+/// Used by translation formats that don't have separate fields for those (PO's `msgctxt` and XLIFF unit names).
+pub(super) fn parse_context(context: &str) -> Option<(u32, u32, AnyStringSource)> {
+    let mut parts = context.split('|');
+    let index = parts.next()?.parse().ok()?;
+    let offset = u32::from_str_radix(parts.next()?.strip_prefix("0x")?, 16).ok()?;
+    let kind = AnyStringKind::from_name(parts.next()?)?;
+    let subindex = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((index, offset, AnyStringSource::from_kind(kind, subindex)))
+}
+
+#[cfg(test)]
+mod test {
+    use shin_text::CharRemap;
+    use shin_versions::{MessageCommandStyle, ShinVersion};
+
+    use super::{Entries, Entry, LintOptions, StringReplacementMode};
+    use crate::{
+        glossary::Glossary,
+        layout::message_parser::{MessageReflowMode, lint::diagnostics::AnyDiagnostic},
+        reactor::{
+            AnyStringSource, StringArraySource, StringSource, rewrite::csv_rewriter::CsvData,
+            speaker_names::SpeakerNames,
+        },
+        substitution::SubstitutionTable,
+    };
+
+    #[test]
+    fn lint_array_length() {
+        let mut entries = Entries::new();
+        for i in 0..3 {
+            entries.insert(
+                i,
+                Entry {
+                    offset: 0x10,
+                    source: AnyStringSource::Array(StringArraySource::Select, i),
+                    s: "はい".to_string(),
+                    translated: (i > 0).then(|| "ａ".repeat(70)),
+                    speaker_lens: (0, 0),
+                },
+            );
+        }
+
+        let reports = entries
+            .lint(&LintOptions {
+                mode: StringReplacementMode::TranslatedOnly,
+                style: MessageCommandStyle::Unescaped,
+                version: ShinVersion::HigurashiSui,
+                substitutions: &SubstitutionTable::new(),
+                char_remap: &CharRemap::new(),
+                glossary: &Glossary::new(),
+                font: None,
+                overflow: None,
+                reflow: MessageReflowMode::NoReflow,
+            })
+            .unwrap_err();
+
+        // the whole array is reported once, on the first translated element
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].index, 1);
+        let [AnyDiagnostic::StringTooLong(diagnostic)] = reports[0].diagnostics.as_slice() else {
+            panic!("unexpected diagnostics: {:?}", reports[0].diagnostics);
+        };
+        // the untranslated hiragana are fixed up to single bytes
+        assert_eq!(diagnostic.length, 3 + 141 + 141 + 1);
+        assert_eq!(diagnostic.limit, 255);
+    }
+
+    #[test]
+    fn propagate_repeats() {
+        let mut entries = Entries::new();
+        let sources = [
+            AnyStringSource::Singular(StringSource::Msgset(1)),
+            AnyStringSource::Singular(StringSource::Msgset(2)),
+            AnyStringSource::Array(StringArraySource::Select, 0),
+        ];
+        for (i, source) in (0..).zip(sources) {
+            entries.insert(
+                i,
+                Entry {
+                    offset: 0x10 * i,
+                    source,
+                    s: "はい".to_string(),
+                    translated: (i == 0).then(|| "Yes...".to_string()),
+                    speaker_lens: (0, 0),
+                },
+            );
+        }
+
+        assert_eq!(entries.propagate_repeats(), 1);
+        let translations = entries
+            .iter()
+            .map(|(_, entry)| entry.translated.as_deref())
+            .collect::<Vec<_>>();
+        // the choice is not a repeat of the messages
+        assert_eq!(translations, [Some("Yes..."), Some("Yes..."), None]);
+    }
+
+    #[test]
+    fn lint_report_limit() {
+        let mut entries = Entries::new();
+        for i in 0..100 {
+            entries.insert(
+                i,
+                Entry {
+                    offset: 0x10 * i,
+                    source: AnyStringSource::Singular(StringSource::Msgset(i)),
+                    s: "はい@k".to_string(),
+                    // the missing `@k` is only a warning, unlike the character that can't be encoded
+                    translated: Some(if i < 99 { "Yes" } else { "Yes 😀@k" }.to_string()),
+                    speaker_lens: (0, 0),
+                },
+            );
+        }
+
+        let reports = entries
+            .lint(&LintOptions {
+                mode: StringReplacementMode::TranslatedOnly,
+                style: MessageCommandStyle::Escaped,
+                version: ShinVersion::HigurashiSui,
+                substitutions: &SubstitutionTable::new(),
+                char_remap: &CharRemap::new(),
+                glossary: &Glossary::new(),
+                font: None,
+                overflow: None,
+                reflow: MessageReflowMode::NoReflow,
+            })
+            .unwrap_err();
+
+        // the warnings don't push the error out
+        assert_eq!(reports.len(), 65);
+        assert!(reports[..64].iter().all(|report| !report.has_errors()));
+        assert_eq!(reports[64].index, 99);
+        assert!(reports[64].has_errors());
+    }
+
+    #[test]
+    fn lint_speaker_names() {
+        let names = SpeakerNames::read(csv::Reader::from_reader(
+            "name,translated\n圭一,Keiichi\n".as_bytes(),
+        ));
+        let data = CsvData::new(
+            csv::Reader::from_reader(
+                "index,offset,source,source_subindex,speaker,s,translated
+0,0x00000010,msgset,1,圭一,@r「はう」,@r“Hau 😀”
+1,0x00000020,msgset,2,圭一,@r「はい」,
+"
+                .as_bytes(),
+            ),
+            Some(&names),
+        );
+
+        // the translated name alone doesn't make the message translated
+        let translations = data
+            .entries
+            .iter()
+            .map(|(_, entry)| entry.translated.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(translations, [Some("Keiichi@r“Hau 😀”"), None]);
+
+        let reports = data
+            .entries
+            .lint(&LintOptions {
+                mode: StringReplacementMode::TranslatedOnly,
+                style: MessageCommandStyle::Escaped,
+                version: ShinVersion::HigurashiSui,
+                substitutions: &SubstitutionTable::new(),
+                char_remap: &CharRemap::new(),
+                glossary: &Glossary::new(),
+                font: None,
+                overflow: None,
+                reflow: MessageReflowMode::NoReflow,
+            })
+            .unwrap_err();
+
+        // the report points into the translated cell, without the name
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].src, "@r“Hau 😀”");
+        let [AnyDiagnostic::UnencodableCharacter(diagnostic)] = reports[0].diagnostics.as_slice()
+        else {
+            panic!("unexpected diagnostics: {:?}", reports[0].diagnostics);
+        };
+        assert_eq!(diagnostic.err_span.offset(), "@r“Hau ".len());
+    }
+}
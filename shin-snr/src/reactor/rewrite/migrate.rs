@@ -0,0 +1,329 @@
+//! Carries translations over to a new version of the SNR file, where string indices and offsets might have changed.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::reactor::{
+    AnyStringSource, StringSource,
+    rewrite::{CsvData, entry::Entry},
+    trace::TracedString,
+};
+
+/// Describes how a string in the new SNR file was matched to an entry in the old translation file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MigrationMatch {
+    /// The string was matched by its text (and, if needed, message id and neighboring strings)
+    Exact { old_index: u32 },
+    /// The string text has changed, but it was matched by the message id. The translation needs to be reviewed.
+    Changed { old_index: u32 },
+    /// There are multiple equally good candidates with different translations
+    Ambiguous,
+    /// The string has no counterpart in the old translation file
+    Unmatched,
+}
+
+pub struct MigrationResult {
+    /// One entry per string in the new SNR file
+    pub matches: Vec<MigrationMatch>,
+    /// One entry per string in the new SNR file
+    pub translations: Vec<Option<String>>,
+    /// Indices of translated entries in the old translation file that were not carried over
+    pub lost: Vec<u32>,
+}
+
+impl MigrationResult {
+    pub fn report(&self) -> MigrationReport<'_> {
+        MigrationReport { result: self }
+    }
+}
+
+/// A human-readable summary of the migration
+pub struct MigrationReport<'a> {
+    result: &'a MigrationResult,
+}
+
+impl fmt::Display for MigrationReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut exact = 0;
+        let mut unmatched = 0;
+        for (new_index, m) in self.result.matches.iter().enumerate() {
+            match *m {
+                MigrationMatch::Exact { .. } => exact += 1,
+                MigrationMatch::Changed { old_index } => writeln!(
+                    f,
+                    "line #{}: text has changed, translation taken from old line #{} (needs review)",
+                    new_index, old_index
+                )?,
+                MigrationMatch::Ambiguous => {
+                    writeln!(f, "line #{}: ambiguous match, left untranslated", new_index)?
+                }
+                MigrationMatch::Unmatched => unmatched += 1,
+            }
+        }
+        for old_index in &self.result.lost {
+            writeln!(
+                f,
+                "old line #{}: translation was not carried over",
+                old_index
+            )?;
+        }
+
+        write!(
+            f,
+            "{} strings matched, {} new strings, {} translations lost",
+            exact,
+            unmatched,
+            self.result.lost.len()
+        )
+    }
+}
+
+fn message_id(source: AnyStringSource) -> Option<u32> {
+    match source {
+        AnyStringSource::Singular(StringSource::Msgset(id)) => Some(id),
+        _ => None,
+    }
+}
+
+struct Migrator<'a> {
+    old: Vec<(u32, &'a Entry)>,
+    new: &'a [TracedString],
+    used: Vec<bool>,
+}
+
+impl Migrator<'_> {
+    /// How many of the neighboring strings are the same in the old and the new file
+    fn context_score(&self, old_pos: usize, new_pos: usize) -> u32 {
+        let same = |old_pos: Option<usize>, new_pos: Option<usize>| match (
+            old_pos.and_then(|p| self.old.get(p)),
+            new_pos.and_then(|p| self.new.get(p)),
+        ) {
+            (Some((_, old)), Some(new)) => old.source.kind() == new.source.kind() && old.s == new.s,
+            _ => false,
+        };
+
+        same(old_pos.checked_sub(1), new_pos.checked_sub(1)) as u32
+            + same(Some(old_pos + 1), Some(new_pos + 1)) as u32
+    }
+
+    /// Picks the best candidate from the old entries with the same text
+    fn pick(&self, candidates: &[usize], new_pos: usize) -> Option<usize> {
+        let new = &self.new[new_pos];
+
+        let rank = |old_pos: usize| {
+            let (_, old) = self.old[old_pos];
+            (
+                old.source == new.source,
+                self.context_score(old_pos, new_pos),
+            )
+        };
+
+        let candidates = candidates
+            .iter()
+            .copied()
+            .filter(|&pos| !self.used[pos])
+            .collect::<Vec<_>>();
+        let best_rank = candidates.iter().map(|&pos| rank(pos)).max()?;
+        let mut best = candidates.into_iter().filter(|&pos| rank(pos) == best_rank);
+
+        let first = best.next()?;
+        // if all the best candidates have the same translation, it doesn't matter which one we pick
+        if best.all(|pos| self.old[pos].1.translated == self.old[first].1.translated) {
+            Some(first)
+        } else {
+            None
+        }
+    }
+}
+
+/// Matches the strings from the new SNR file with the entries from the old translation file.
+///
+/// The strings are matched by their text and kind. If there are multiple candidates, the message id and the neighboring strings are used to pick one.
+/// Strings with changed text are matched by their message id, if it's unique.
+pub fn migrate(old: &CsvData, new: &[TracedString]) -> MigrationResult {
+    let mut migrator = Migrator {
+        old: old.entries.iter().collect(),
+        new,
+        used: Vec::new(),
+    };
+    migrator.used = vec![false; migrator.old.len()];
+
+    let mut by_text = HashMap::<(&str, &str), Vec<usize>>::new();
+    let mut by_message_id = HashMap::<u32, Vec<usize>>::new();
+    for (pos, (_, entry)) in migrator.old.iter().enumerate() {
+        by_text
+            .entry((entry.source.kind().name(), entry.s.as_str()))
+            .or_default()
+            .push(pos);
+        if let Some(id) = message_id(entry.source) {
+            by_message_id.entry(id).or_default().push(pos);
+        }
+    }
+
+    // message ids are not always unique, so a string matched by its message id must not have an exact match too
+    let new_texts = new
+        .iter()
+        .map(|string| (string.source.kind(), string.s.as_str()))
+        .collect::<HashSet<_>>();
+
+    let mut matches = Vec::with_capacity(new.len());
+    let mut translations = Vec::with_capacity(new.len());
+
+    for (new_pos, string) in new.iter().enumerate() {
+        let (m, pos) = if let Some(candidates) =
+            by_text.get(&(string.source.kind().name(), string.s.as_str()))
+            && candidates.iter().any(|&pos| !migrator.used[pos])
+        {
+            match migrator.pick(candidates, new_pos) {
+                Some(pos) => (
+                    MigrationMatch::Exact {
+                        old_index: migrator.old[pos].0,
+                    },
+                    Some(pos),
+                ),
+                None => (MigrationMatch::Ambiguous, None),
+            }
+        } else if let Some(id) = message_id(string.source)
+            && let Some(candidates) = by_message_id.get(&id)
+            && let [pos] = candidates[..]
+            && !migrator.used[pos]
+            && !new_texts.contains(&(
+                migrator.old[pos].1.source.kind(),
+                migrator.old[pos].1.s.as_str(),
+            ))
+        {
+            (
+                MigrationMatch::Changed {
+                    old_index: migrator.old[pos].0,
+                },
+                Some(pos),
+            )
+        } else {
+            (MigrationMatch::Unmatched, None)
+        };
+
+        if let Some(pos) = pos {
+            migrator.used[pos] = true;
+        }
+        matches.push(m);
+        translations.push(pos.and_then(|pos| migrator.old[pos].1.translated.clone()));
+    }
+
+    let lost = migrator
+        .old
+        .iter()
+        .zip(&migrator.used)
+        .filter(|&(&(_, entry), &used)| !used && entry.translated.is_some())
+        .map(|(&(index, _), _)| index)
+        .collect();
+
+    MigrationResult {
+        matches,
+        translations,
+        lost,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MigrationMatch, migrate};
+    use crate::reactor::{AnyStringSource, StringSource, rewrite::CsvData, trace::TracedString};
+
+    fn msgset(offset: u32, id: u32, s: &str) -> TracedString {
+        TracedString {
+            offset,
+            source: AnyStringSource::Singular(StringSource::Msgset(id)),
+            s: s.to_string(),
+            voice: None,
+        }
+    }
+
+    #[test]
+    fn migrate_shifted() {
+        let old = CsvData::new(
+            csv::Reader::from_reader(
+                "index,offset,source,source_subindex,s,translated
+0,0x00000010,msgset,1,はい,Yes
+1,0x00000020,msgset,2,ここは庭だった。,This was a garden.
+2,0x00000030,msgset,3,はい,Yeah
+3,0x00000040,msgset,4,「はう」,Hau
+4,0x00000050,msgset,5,さようなら,Goodbye
+"
+                .as_bytes(),
+            ),
+            None,
+        );
+
+        let new = [
+            msgset(0x10, 7, "新しい台詞"),
+            msgset(0x20, 1, "はい"),
+            msgset(0x30, 2, "ここは庭だった。"),
+            msgset(0x40, 8, "はい"),
+            msgset(0x50, 4, "「はうう」"),
+            msgset(0x60, 9, "はい"),
+        ];
+
+        let result = migrate(&old, &new);
+
+        assert_eq!(
+            result.matches,
+            [
+                MigrationMatch::Unmatched,
+                MigrationMatch::Exact { old_index: 0 },
+                MigrationMatch::Exact { old_index: 1 },
+                MigrationMatch::Exact { old_index: 2 },
+                MigrationMatch::Changed { old_index: 3 },
+                MigrationMatch::Unmatched,
+            ]
+        );
+        assert_eq!(
+            result.translations,
+            [
+                None,
+                Some("Yes".to_string()),
+                Some("This was a garden.".to_string()),
+                Some("Yeah".to_string()),
+                Some("Hau".to_string()),
+                None,
+            ]
+        );
+        assert_eq!(result.lost, [4]);
+    }
+
+    #[test]
+    fn migrate_speakers() {
+        let old = CsvData::new_unmerged(csv::Reader::from_reader(
+            "index,offset,source,source_subindex,speaker,s,translated
+0,0x00000010,msgset,1,圭一,@r「はう」,@r“Hau”
+1,0x00000020,msgset,2,,@rここは庭だった。,@rThis was a garden.
+"
+            .as_bytes(),
+        ));
+        assert!(old.has_speakers());
+
+        let new = [
+            msgset(0x10, 2, "@rここは庭だった。"),
+            msgset(0x20, 1, "圭一@r「はう」"),
+        ];
+
+        let result = migrate(&old, &new);
+
+        assert_eq!(
+            result.matches,
+            [
+                MigrationMatch::Exact { old_index: 1 },
+                MigrationMatch::Exact { old_index: 0 },
+            ]
+        );
+        // the translations don't get the untranslated speaker names merged into them
+        assert_eq!(
+            result.translations,
+            [
+                Some("@rThis was a garden.".to_string()),
+                Some("@r“Hau”".to_string()),
+            ]
+        );
+    }
+}
@@ -1,24 +1,41 @@
 mod csv_rewriter;
+mod edits;
+mod entry;
+mod migrate;
 mod noop_rewriter;
+mod po_rewriter;
+mod stats;
 mod x_rewriter;
+mod xliff_rewriter;
 
 use std::collections::HashMap;
 
 use bumpalo::Bump;
-use shin_text::{FixupDetectResult, StringArrayIter, encode_sjis_zstring};
+use shin_text::{CharRemap, FixupDetectResult, StringArrayIter, encode_sjis_zstring_with_remap};
 use shin_versions::{MessageCommandStyle, NumberStyle, StringPolicy};
 
 pub use self::{
-    csv_rewriter::{CsvData, CsvRewriter, StringReplacementMode},
+    csv_rewriter::{CsvData, CsvRewriter},
+    edits::{InsertedElement, InsertedOperation, InsertedOperationError, OperationEdits},
+    entry::{LintOptions, StringReplacementMode},
+    migrate::{MigrationMatch, MigrationReport, MigrationResult, migrate},
     noop_rewriter::NoopRewriter,
+    po_rewriter::{PoData, PoParseError, PoRewriter},
+    stats::{MessageIdRangeStats, SceneStats, StatsCounts, TranslationStats},
     x_rewriter::XRewriter,
+    xliff_rewriter::{XliffData, XliffParseError, XliffRewriter},
 };
 use crate::{
-    layout::message_parser::MessageReflowMode,
+    header::SnrHeader,
+    layout::{
+        layouter::ChoiceBoxInfo,
+        message_parser::MessageReflowMode,
+        reflow::{ChoiceFitPolicy, ReflowParams},
+    },
     operation::{
-        OperationElementRepr,
+        NumberArrayKind, OperationElementRepr,
         arena::OperationArena,
-        schema::{Opcode, OperationSchema},
+        schema::{Opcode, OperationElement, OperationSchema},
         serialize::InstructionSerializeContext,
     },
     reactor::{AnyStringSource, Reactor, StringArraySource, StringSource},
@@ -115,7 +132,9 @@ pub trait RewriteMode {
 }
 
 pub trait RewriteMode2 {
-    fn instr_start(&mut self, in_offset: u32, raw_opcode: u8);
+    /// Called for each operation of the original file, before anything is written for it (including the inserted operations)
+    fn instr_start(&mut self, in_offset: u32);
+    fn put_opcode(&mut self, raw_opcode: u8);
     fn put_element(&mut self, element: &OperationElementRepr);
     fn end_of_stream(&mut self);
 }
@@ -127,18 +146,20 @@ pub struct BuildOffsetMapMode {
 }
 
 impl RewriteMode2 for BuildOffsetMapMode {
-    fn instr_start(&mut self, in_offset: u32, raw_opcode: u8) {
+    fn instr_start(&mut self, in_offset: u32) {
         let idx = self.instr_index;
         self.builder.orig_to_idx.insert(in_offset, idx);
         self.builder
             .idx_to_out
             .insert(idx, self.serializing.writer_mut().position());
 
-        self.serializing.put_u8(raw_opcode);
-
         self.instr_index += 1;
     }
 
+    fn put_opcode(&mut self, raw_opcode: u8) {
+        self.serializing.put_u8(raw_opcode);
+    }
+
     fn put_element(&mut self, element: &OperationElementRepr) {
         self.serializing.put_element(element);
     }
@@ -154,7 +175,9 @@ pub struct EmitMode {
 }
 
 impl RewriteMode2 for EmitMode {
-    fn instr_start(&mut self, _in_offset: u32, raw_opcode: u8) {
+    fn instr_start(&mut self, _in_offset: u32) {}
+
+    fn put_opcode(&mut self, raw_opcode: u8) {
         self.serializing.put_u8(raw_opcode);
     }
 
@@ -187,7 +210,9 @@ struct Stringer<'a, R> {
     user_style: MessageCommandStyle,
     has_useless_escapes: bool,
     reflow_mode: MessageReflowMode<'a>,
+    choice_fit: Option<(ReflowParams<'a>, ChoiceBoxInfo, ChoiceFitPolicy)>,
     policy: StringPolicy,
+    char_remap: CharRemap,
     rewriter: R,
 }
 
@@ -206,7 +231,9 @@ impl<'a, R> Stringer<'a, R> {
             user_style,
             has_useless_escapes,
             reflow_mode,
+            choice_fit: None,
             policy,
+            char_remap: CharRemap::new(),
             rewriter,
         }
     }
@@ -229,6 +256,62 @@ impl<'a, R: StringRewriter> Stringer<'a, R> {
         original: &'s [u8],
         source: AnyStringSource,
     ) -> &'s [u8] {
+        match self.replacement(position, original, source) {
+            Some((replacement, fixup_detect_result)) => {
+                self.encode(replacement, fixup_detect_result, source, self.reflow_mode)
+            }
+            None => original,
+        }
+    }
+
+    /// Like [`Stringer::rewrite_string`], but splits the translated messages that don't fit into the message box into several pages when using [`MessageReflowMode::GreedySplit`].
+    fn rewrite_string_pages<'s>(
+        &'s self,
+        position: &mut Position,
+        original: &'s [u8],
+        source: AnyStringSource,
+    ) -> &'s [&'s [u8]] {
+        let Some((replacement, fixup_detect_result)) = self.replacement(position, original, source)
+        else {
+            return self.bump.alloc([original]);
+        };
+
+        match self.reflow_mode {
+            MessageReflowMode::GreedySplit(params)
+                if matches!(source, AnyStringSource::Singular(StringSource::Msgset(_))) =>
+            {
+                let pages = crate::layout::message_parser::reflow_split_pages(
+                    &self.bump,
+                    replacement,
+                    self.user_style,
+                    params,
+                );
+                self.bump
+                    .alloc_slice_fill_iter(pages.into_iter().map(|page| {
+                        self.encode(
+                            page,
+                            fixup_detect_result,
+                            source,
+                            MessageReflowMode::NoReflow,
+                        )
+                    }))
+            }
+            _ => self.bump.alloc([self.encode(
+                replacement,
+                fixup_detect_result,
+                source,
+                self.reflow_mode,
+            )]),
+        }
+    }
+
+    /// Decodes the string and asks the rewriter for a replacement, advancing to the next string
+    fn replacement<'s>(
+        &'s self,
+        position: &mut Position,
+        original: &'s [u8],
+        source: AnyStringSource,
+    ) -> Option<(&'s str, FixupDetectResult)> {
         assert_eq!(
             original.last().copied(),
             Some(0),
@@ -242,52 +325,96 @@ impl<'a, R: StringRewriter> Stringer<'a, R> {
         )
         .unwrap();
 
-        let result = if let Some(replacement) = self.rewriter.rewrite_string(
+        let replacement = self.rewriter.rewrite_string(
             &self.bump,
             original_decoded,
             position.current_str_index,
             position.current_instr_offset,
             source,
-        ) {
-            match self.policy {
-                StringPolicy::ShiftJis(policy) => {
-                    let mut fixup_detect_result = FixupDetectResult::NoFixupCharacters;
-                    shin_text::detect_fixup(original, &mut fixup_detect_result).unwrap();
-
-                    let (transformed, fixup_policy) =
-                        crate::layout::message_parser::transform_reflow_and_infer_fixup_policy(
-                            &self.bump,
-                            replacement,
-                            self.user_style,
-                            self.reflow_mode,
-                            self.snr_style,
-                            self.has_useless_escapes,
-                            policy,
-                            fixup_detect_result,
-                            source,
-                        );
-                    encode_sjis_zstring(&self.bump, transformed, fixup_policy).unwrap()
-                }
-                StringPolicy::Utf8 => {
-                    let transformed = crate::layout::message_parser::transform_reflow(
+        );
+
+        position.current_str_index += 1;
+
+        let replacement = replacement?;
+        let mut fixup_detect_result = FixupDetectResult::NoFixupCharacters;
+        if let StringPolicy::ShiftJis(_) = self.policy {
+            shin_text::detect_fixup(original, &mut fixup_detect_result).unwrap();
+        }
+
+        Some((replacement, fixup_detect_result))
+    }
+}
+
+impl<'a, R> Stringer<'a, R> {
+    /// Encodes a string for an inserted operation. It goes through the same transformations as the translated strings.
+    fn encode_new<'s>(&'s self, s: &str, source: AnyStringSource) -> &'s [u8] {
+        let s = self.bump.alloc_str(s);
+        self.encode(
+            s,
+            FixupDetectResult::NoFixupCharacters,
+            source,
+            self.reflow_mode,
+        )
+    }
+
+    fn encode<'s>(
+        &'s self,
+        replacement: &'s str,
+        fixup_detect_result: FixupDetectResult,
+        source: AnyStringSource,
+        reflow_mode: MessageReflowMode,
+    ) -> &'s [u8] {
+        let replacement = match self.choice_fit {
+            Some((params, choice_box, policy))
+                if matches!(source, AnyStringSource::Array(StringArraySource::Select, _)) =>
+            {
+                crate::layout::message_parser::fit_choice(
+                    &self.bump,
+                    replacement,
+                    self.user_style,
+                    params,
+                    choice_box,
+                    policy,
+                )
+            }
+            _ => replacement,
+        };
+
+        match self.policy {
+            StringPolicy::ShiftJis(policy) => {
+                let (transformed, fixup_policy) =
+                    crate::layout::message_parser::transform_reflow_and_infer_fixup_policy(
                         &self.bump,
                         replacement,
                         self.user_style,
-                        self.reflow_mode,
+                        reflow_mode,
                         self.snr_style,
-                        true,
+                        self.has_useless_escapes,
+                        policy,
+                        fixup_detect_result,
                         source,
                     );
-                    encode_utf8_zstring(&self.bump, transformed)
-                }
+                encode_sjis_zstring_with_remap(
+                    &self.bump,
+                    transformed,
+                    fixup_policy,
+                    &self.char_remap,
+                )
+                .unwrap()
             }
-        } else {
-            original
-        };
-
-        position.current_str_index += 1;
-
-        result
+            StringPolicy::Utf8 => {
+                let transformed = crate::layout::message_parser::transform_reflow(
+                    &self.bump,
+                    replacement,
+                    self.user_style,
+                    reflow_mode,
+                    self.snr_style,
+                    true,
+                    source,
+                );
+                encode_utf8_zstring(&self.bump, transformed)
+            }
+        }
     }
 }
 
@@ -319,6 +446,49 @@ impl<'a, R> RewriteReactor<'a, R, BuildOffsetMapMode> {
                 policy,
                 rewriter,
             ),
+            edits: OperationEdits::new(),
+            mode: BuildOffsetMapMode {
+                builder: OffsetMapBuilder::new(),
+                serializing: InstructionSerializeContext::new(
+                    number_style,
+                    CountingWriter::new(initial_out_position),
+                ),
+                instr_index: 0,
+            },
+        }
+    }
+
+    /// Encodes the characters from the table with their assigned Shift-JIS codepoints
+    pub fn with_char_remap(mut self, char_remap: CharRemap) -> Self {
+        self.stringer.char_remap = char_remap;
+        self
+    }
+
+    /// Makes the translated choices fit into the choice box, see [`crate::layout::reflow::fit_choice`]
+    pub fn with_choice_fit(
+        mut self,
+        params: ReflowParams<'a>,
+        choice_box: ChoiceBoxInfo,
+        policy: ChoiceFitPolicy,
+    ) -> Self {
+        self.stringer.choice_fit = Some((params, choice_box, policy));
+        self
+    }
+
+    /// Inserts and removes operations while rewriting, see [`OperationEdits`]
+    pub fn with_edits(mut self, edits: OperationEdits) -> Self {
+        self.edits = edits;
+        self
+    }
+
+    /// Starts over, placing the code at a different position in the output. Used when the header changes its size.
+    pub fn restart_at(self, initial_out_position: u32) -> Self {
+        let (number_style, _) = self.mode.serializing.into_parts();
+
+        Self {
+            position: Default::default(),
+            stringer: self.stringer.reset(),
+            edits: self.edits,
             mode: BuildOffsetMapMode {
                 builder: OffsetMapBuilder::new(),
                 serializing: InstructionSerializeContext::new(
@@ -341,6 +511,7 @@ impl<'a, R> RewriteReactor<'a, R, BuildOffsetMapMode> {
         RewriteReactor {
             position: Default::default(),
             stringer: self.stringer.reset(),
+            edits: self.edits,
             mode: EmitMode {
                 map: self.mode.builder.build(),
                 serializing: InstructionSerializeContext::new(
@@ -363,9 +534,83 @@ impl<'a, R> RewriteReactor<'a, R, EmitMode> {
 pub struct RewriteReactor<'a, R, M> {
     position: Position,
     stringer: Stringer<'a, R>,
+    edits: OperationEdits,
     mode: M,
 }
 
+impl<'a, R, M: RewriteMode2> RewriteReactor<'a, R, M> {
+    fn put_inserted(mode: &mut M, stringer: &Stringer<'a, R>, operation: &InsertedOperation) {
+        mode.put_opcode(operation.raw_opcode);
+
+        for (schema_element, element) in &operation.elements {
+            let element = match (*schema_element, element) {
+                (_, &InsertedElement::U8(value)) => OperationElementRepr::U8(value),
+                (_, &InsertedElement::U16(value)) => OperationElementRepr::U16(value),
+                (_, &InsertedElement::U32(value)) => OperationElementRepr::U32(value),
+                (_, &InsertedElement::Register(value)) => OperationElementRepr::Register(value),
+                (OperationElement::RegisterArray(kind), InsertedElement::RegisterArray(values)) => {
+                    OperationElementRepr::RegisterArray(kind, values)
+                }
+                (_, &InsertedElement::Offset(value)) => OperationElementRepr::Offset(value),
+                (OperationElement::OffsetArray(kind), InsertedElement::OffsetArray(values)) => {
+                    OperationElementRepr::OffsetArray(kind, values)
+                }
+                (_, &InsertedElement::Number(value)) => OperationElementRepr::Number(value),
+                (OperationElement::NumberArray(kind), InsertedElement::NumberArray(values)) => {
+                    OperationElementRepr::NumberArray(kind, NumberArrayKind::Dense, values)
+                }
+                (OperationElement::PadNumberArray(kind), InsertedElement::NumberArray(values)) => {
+                    OperationElementRepr::NumberArray(kind, NumberArrayKind::Padded, values)
+                }
+                (OperationElement::String(kind), InsertedElement::String(s)) => {
+                    let source = operation
+                        .string_source()
+                        .expect("InsertedOperation::new checks that the string source is known");
+                    OperationElementRepr::String(kind, stringer.encode_new(s, source))
+                }
+                (OperationElement::StringArray(kind), InsertedElement::StringArray(strings)) => {
+                    let source = operation
+                        .string_array_source
+                        .expect("InsertedOperation::new checks that the string source is known");
+                    let mut encoded = bumpalo::collections::Vec::new_in(&stringer.bump);
+                    for (i, s) in (0..).zip(strings) {
+                        encoded.extend_from_slice(
+                            stringer.encode_new(s, AnyStringSource::Array(source, i)),
+                        );
+                    }
+                    encoded.push(0);
+                    OperationElementRepr::StringArray(kind, encoded.into_bump_slice())
+                }
+                (schema_element, element) => unreachable!(
+                    "InsertedOperation::new checks the elements against the schema, but {:?} doesn't match {:?}",
+                    element, schema_element
+                ),
+            };
+            mode.put_element(&element);
+        }
+    }
+}
+
+impl<'a, R: StringRewriter, M> RewriteReactor<'a, R, M> {
+    /// Rewrites the translatable strings of the header tables.
+    ///
+    /// Must be called after all the code was processed, as the header strings are numbered after the ones from the code.
+    pub fn rewrite_header(&self, header: &mut SnrHeader) {
+        let bump = Bump::new();
+        let mut index = self.position.current_str_index;
+        header.rewrite_strings(|offset, source, s| {
+            let s = bump.alloc_str(s);
+            let result = self
+                .stringer
+                .rewriter
+                .rewrite_string(&bump, s, index, offset, source)
+                .map(|s| s.to_string());
+            index += 1;
+            result
+        });
+    }
+}
+
 impl<'a, R: StringRewriter, M: RewriteMode2> Reactor for RewriteReactor<'a, R, M> {
     fn react(
         &mut self,
@@ -377,7 +622,52 @@ impl<'a, R: StringRewriter, M: RewriteMode2> Reactor for RewriteReactor<'a, R, M
     ) {
         self.position.current_instr_offset = operation_position;
 
-        self.mode.instr_start(operation_position, raw_opcode);
+        self.mode.instr_start(operation_position);
+
+        for operation in self.edits.before(operation_position) {
+            Self::put_inserted(&mut self.mode, &self.stringer, operation);
+        }
+
+        if self.edits.is_removed(operation_position) {
+            // keep the numbering of the strings, so that the translations still match
+            for element in arena.iter(op_schema) {
+                match element {
+                    OperationElementRepr::String(..) => self.position.current_str_index += 1,
+                    OperationElementRepr::StringArray(_, string_array) => {
+                        self.position.current_str_index +=
+                            StringArrayIter::new(string_array).count() as u32
+                    }
+                    _ => {}
+                }
+            }
+        } else {
+            self.put_operation(opcode, raw_opcode, op_schema, arena);
+        }
+
+        for operation in self.edits.after(operation_position) {
+            Self::put_inserted(&mut self.mode, &self.stringer, operation);
+        }
+
+        self.position.current_instr_index += 1;
+    }
+
+    fn end_of_stream(&mut self) {
+        self.mode.end_of_stream()
+    }
+}
+
+impl<'a, R: StringRewriter, M: RewriteMode2> RewriteReactor<'a, R, M> {
+    fn put_operation(
+        &mut self,
+        opcode: Opcode,
+        raw_opcode: u8,
+        op_schema: &OperationSchema,
+        arena: &OperationArena,
+    ) {
+        self.mode.put_opcode(raw_opcode);
+
+        // a message split into several pages is continued by copies of the operation
+        let mut extra_pages: &[&[u8]] = &[];
 
         for element in arena.iter(&op_schema) {
             match element {
@@ -386,14 +676,18 @@ impl<'a, R: StringRewriter, M: RewriteMode2> Reactor for RewriteReactor<'a, R, M
                         panic!("Could not determine StringSource for opcode {:?}", opcode)
                     };
 
-                    let rewritten = self.stringer.rewrite_string(
+                    let pages = self.stringer.rewrite_string_pages(
                         &mut self.position,
                         string,
                         AnyStringSource::Singular(source),
                     );
+                    let (first_page, rest) = pages
+                        .split_first()
+                        .expect("A string should have at least one page");
+                    extra_pages = rest;
 
                     self.mode
-                        .put_element(&OperationElementRepr::String(kind, rewritten))
+                        .put_element(&OperationElementRepr::String(kind, first_page))
                 }
                 OperationElementRepr::StringArray(kind, string_array) => {
                     let Some(source) = StringArraySource::for_operation(opcode, op_schema, arena)
@@ -422,10 +716,16 @@ impl<'a, R: StringRewriter, M: RewriteMode2> Reactor for RewriteReactor<'a, R, M
             }
         }
 
-        self.position.current_instr_index += 1;
-    }
-
-    fn end_of_stream(&mut self) {
-        self.mode.end_of_stream()
+        for page in extra_pages {
+            self.mode.put_opcode(raw_opcode);
+            for element in arena.iter(op_schema) {
+                match element {
+                    OperationElementRepr::String(kind, _) => self
+                        .mode
+                        .put_element(&OperationElementRepr::String(kind, page)),
+                    element => self.mode.put_element(&element),
+                }
+            }
+        }
     }
 }
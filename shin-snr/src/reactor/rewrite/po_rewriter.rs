@@ -0,0 +1,331 @@
+use bumpalo::Bump;
+use shin_text::CharRemap;
+use shin_versions::{MessageCommandStyle, StringEncoding};
+
+use crate::{
+    layout::message_parser::lint::diagnostics::LineReport,
+    reactor::{
+        AnyStringSource,
+        rewrite::{
+            StringReplacementMode, StringRewriter, TranslationStats,
+            entry::{Entries, Entry, LintOptions, parse_context},
+            stats::collect_stats,
+        },
+        trace::TracedString,
+    },
+    substitution::SubstitutionTable,
+};
+
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to parse the PO file at line {line}: {message}")]
+pub struct PoParseError {
+    pub line: usize,
+    pub message: &'static str,
+}
+
+#[derive(Default)]
+struct PoEntry {
+    msgctxt: Option<String>,
+    msgid: String,
+    msgstr: String,
+    fuzzy: bool,
+    obsolete: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum PoField {
+    Msgctxt,
+    Msgid,
+    MsgidPlural,
+    Msgstr,
+    /// `msgstr[n]` with `n != 0`, we are not interested in those
+    MsgstrOther,
+}
+
+fn unescape(line: usize, s: &str, out: &mut String) -> Result<(), PoParseError> {
+    let err = |message| PoParseError { line, message };
+
+    let s = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or(err("expected a quoted string"))?;
+
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            _ => return Err(err("unsupported escape sequence")),
+        }
+    }
+
+    Ok(())
+}
+
+/// A minimal parser for gettext PO files, only handling the features we use
+fn parse_po(source: &str) -> Result<Vec<PoEntry>, PoParseError> {
+    let mut entries = Vec::new();
+    let mut current = PoEntry::default();
+    let mut field = None;
+
+    fn finish(entries: &mut Vec<PoEntry>, current: &mut PoEntry, field: &mut Option<PoField>) {
+        if field.is_some() {
+            entries.push(std::mem::take(current));
+        } else {
+            // only comments so far, drop them
+            *current = PoEntry::default();
+        }
+        *field = None;
+    }
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.trim();
+
+        if line.is_empty() {
+            finish(&mut entries, &mut current, &mut field);
+            continue;
+        }
+
+        // a comment or a keyword after msgstr means that a new entry has started
+        if !line.starts_with('"')
+            && matches!(field, Some(PoField::Msgstr | PoField::MsgstrOther))
+            && !line.starts_with("msgstr[")
+        {
+            finish(&mut entries, &mut current, &mut field);
+        }
+
+        let line = if let Some(rest) = line.strip_prefix("#~") {
+            // obsolete entry, still parse it to keep track of entry boundaries
+            current.obsolete = true;
+            let rest = rest.trim_start();
+            if rest.is_empty() {
+                continue;
+            }
+            rest
+        } else if let Some(flags) = line.strip_prefix("#,") {
+            if flags.split(',').any(|flag| flag.trim() == "fuzzy") {
+                current.fuzzy = true;
+            }
+            continue;
+        } else if line.starts_with('#') {
+            // translator comments, extracted comments, references, previous strings
+            continue;
+        } else {
+            line
+        };
+
+        let (new_field, value) = if let Some(rest) = line.strip_prefix("msgctxt ") {
+            (PoField::Msgctxt, rest)
+        } else if let Some(rest) = line.strip_prefix("msgid_plural ") {
+            (PoField::MsgidPlural, rest)
+        } else if let Some(rest) = line.strip_prefix("msgid ") {
+            (PoField::Msgid, rest)
+        } else if let Some(rest) = line.strip_prefix("msgstr[0] ") {
+            (PoField::Msgstr, rest)
+        } else if line.starts_with("msgstr[") {
+            let (_, rest) = line.split_once(' ').ok_or(PoParseError {
+                line: line_number,
+                message: "expected a string after `msgstr[n]`",
+            })?;
+            (PoField::MsgstrOther, rest)
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            (PoField::Msgstr, rest)
+        } else if line.starts_with('"') {
+            let Some(field) = field else {
+                return Err(PoParseError {
+                    line: line_number,
+                    message: "string continuation without a keyword",
+                });
+            };
+            (field, line)
+        } else {
+            return Err(PoParseError {
+                line: line_number,
+                message: "unknown keyword",
+            });
+        };
+
+        field = Some(new_field);
+        let target = match new_field {
+            PoField::Msgctxt => current.msgctxt.get_or_insert_with(String::new),
+            PoField::Msgid => &mut current.msgid,
+            PoField::Msgstr => &mut current.msgstr,
+            PoField::MsgidPlural | PoField::MsgstrOther => continue,
+        };
+        unescape(line_number, value.trim(), target)?;
+    }
+    finish(&mut entries, &mut current, &mut field);
+
+    Ok(entries)
+}
+
+pub struct PoData {
+    entries: Entries,
+}
+
+impl PoData {
+    /// Parses a PO file created by `PoTraceListener` (and filled in by the translators).
+    ///
+    /// Fuzzy translations are treated as missing.
+    pub fn parse(source: &str) -> Result<Self, PoParseError> {
+        let mut entries = Entries::new();
+
+        for entry in parse_po(source)? {
+            if entry.obsolete {
+                continue;
+            }
+            let Some(msgctxt) = entry.msgctxt else {
+                // the header entry doesn't have a context; ignore it and anything else that is not ours
+                continue;
+            };
+            let Some((index, offset, source)) = parse_context(&msgctxt) else {
+                continue;
+            };
+
+            let translated = (!entry.fuzzy && !entry.msgstr.is_empty()).then_some(entry.msgstr);
+
+            entries.insert(
+                index,
+                Entry {
+                    offset,
+                    source,
+                    s: entry.msgid,
+                    translated,
+                    speaker_lens: (0, 0),
+                },
+            );
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Fills in untranslated strings from other strings with the same text. Returns the number of filled strings.
+    pub fn propagate_repeats(&mut self) -> usize {
+        self.entries.propagate_repeats()
+    }
+
+    /// Collects the translation progress statistics, see [`collect_stats`] for details
+    pub fn stats(
+        &self,
+        strings: &[TracedString],
+        style: MessageCommandStyle,
+        id_range_size: u32,
+    ) -> TranslationStats {
+        collect_stats(strings, &self.entries, false, style, id_range_size)
+    }
+
+    /// Replaces the characters that can't be encoded in the translations. Returns the number of changed strings.
+    pub fn substitute(
+        &mut self,
+        substitutions: &SubstitutionTable,
+        encoding: StringEncoding,
+        char_remap: &CharRemap,
+    ) -> usize {
+        self.entries.substitute(substitutions, encoding, char_remap)
+    }
+
+    pub fn lint(&self, options: &LintOptions) -> Result<(), Vec<LineReport>> {
+        self.entries.lint(options)
+    }
+}
+
+pub struct PoRewriter {
+    entries: Entries,
+    mode: StringReplacementMode,
+}
+
+impl PoRewriter {
+    pub fn new(data: PoData, mode: StringReplacementMode) -> Self {
+        Self {
+            entries: data.entries,
+            mode,
+        }
+    }
+}
+
+impl StringRewriter for PoRewriter {
+    fn rewrite_string<'bump>(
+        &'bump self,
+        _bump: &'bump Bump,
+        _decoded: &'bump str,
+        instr_index: u32,
+        instr_offset: u32,
+        source: AnyStringSource,
+    ) -> Option<&'bump str> {
+        self.entries
+            .get(instr_index, instr_offset, source)?
+            .get_effective_string(self.mode)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PoData;
+    use crate::reactor::{AnyStringSource, StringSource};
+
+    #[test]
+    fn parse() {
+        let data = PoData::parse(
+            r#"msgid ""
+msgstr ""
+"Content-Type: text/plain; charset=UTF-8\n"
+
+# source: msgset
+# message id: 1
+msgctxt "0|0x00000010|msgset|1"
+msgid "@rここは自由に駆け回れる庭だった。"
+msgstr ""
+"@rThis was a garden "
+"where I could run \"freely\"."
+
+#, fuzzy
+msgctxt "1|0x00000020|msgset|2"
+msgid "@r好きに生きることを許された世界だった。"
+msgstr "@rIt was a world."
+
+msgctxt "3|0x00000030|select_choice|1"
+msgid "いいえ"
+msgstr "No"
+
+#~ msgctxt "4|0x00000040|saveinfo|0"
+#~ msgid "プロローグ"
+#~ msgstr "Prologue"
+"#,
+        )
+        .unwrap();
+
+        let entry = data
+            .entries
+            .get(0, 0x10, AnyStringSource::Singular(StringSource::Msgset(1)))
+            .unwrap();
+        assert_eq!(
+            entry.translated.as_deref(),
+            Some("@rThis was a garden where I could run \"freely\".")
+        );
+
+        let entry = data
+            .entries
+            .get(1, 0x20, AnyStringSource::Singular(StringSource::Msgset(2)))
+            .unwrap();
+        assert_eq!(entry.translated, None);
+
+        assert!(
+            data.entries
+                .get(2, 0, AnyStringSource::Singular(StringSource::Saveinfo))
+                .is_none()
+        );
+        assert!(
+            data.entries
+                .get(4, 0x40, AnyStringSource::Singular(StringSource::Saveinfo))
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,358 @@
+//! Translation progress statistics.
+
+use std::{collections::BTreeMap, fmt};
+
+use bumpalo::Bump;
+use serde::Serialize;
+use shin_versions::MessageCommandStyle;
+
+use crate::{
+    layout::message_parser::{self, split_speaker_name},
+    reactor::{AnyStringSource, StringSource, rewrite::entry::Entries, trace::TracedString},
+};
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct StatsCounts {
+    pub strings: u32,
+    pub translated: u32,
+    pub untranslated: u32,
+    /// Number of characters in the original strings, not counting layout commands
+    pub source_characters: u32,
+    /// Number of characters in the original strings that are not translated yet
+    pub untranslated_source_characters: u32,
+    /// Number of characters in the translations, not counting layout commands
+    pub translated_characters: u32,
+    /// Number of whitespace-separated words in the translations
+    pub translated_words: u32,
+}
+
+impl StatsCounts {
+    fn add(&mut self, source_characters: u32, translation: Option<(u32, u32)>) {
+        self.strings += 1;
+        self.source_characters += source_characters;
+        match translation {
+            Some((characters, words)) => {
+                self.translated += 1;
+                self.translated_characters += characters;
+                self.translated_words += words;
+            }
+            None => {
+                self.untranslated += 1;
+                self.untranslated_source_characters += source_characters;
+            }
+        }
+    }
+
+    /// Share of translated strings, in percent
+    pub fn progress(&self) -> f64 {
+        if self.strings == 0 {
+            100.0
+        } else {
+            self.translated as f64 * 100.0 / self.strings as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageIdRangeStats {
+    /// First message id in the range
+    pub start: u32,
+    /// Last message id in the range (inclusive)
+    pub end: u32,
+    #[serde(flatten)]
+    pub counts: StatsCounts,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SceneStats {
+    /// Offset of the `SAVEINFO` instruction starting the scene, `None` for the strings before the first one
+    ///
+    /// Different scenes may have the same title, so this is what tells them apart.
+    pub offset: Option<u32>,
+    /// Scene title (as set by `SAVEINFO`), `None` for the strings before the first one
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub counts: StatsCounts,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TranslationStats {
+    pub total: StatsCounts,
+    /// Keyed by the string kind (the `source` column)
+    pub by_kind: BTreeMap<&'static str, StatsCounts>,
+    /// Only includes messages (`msgset` strings), ordered by the message ids
+    pub by_message_id: Vec<MessageIdRangeStats>,
+    /// In order of appearance, each `SAVEINFO` starting a new scene
+    pub by_scene: Vec<SceneStats>,
+}
+
+/// Counts the characters and words of the text, skipping layout commands
+fn count_text(
+    bump: &Bump,
+    style: MessageCommandStyle,
+    source: AnyStringSource,
+    s: &str,
+) -> (u32, u32) {
+    let text = if source.contains_commands() {
+        message_parser::strip_commands(bump, style, bump.alloc_str(s))
+    } else {
+        s
+    };
+
+    let characters = text.chars().filter(|c| !c.is_whitespace()).count() as u32;
+    let words = text.split_whitespace().count() as u32;
+    (characters, words)
+}
+
+/// Collects the translation progress statistics.
+///
+/// Messages are grouped into ranges of `id_range_size` message ids, and strings are grouped into scenes by the last `SAVEINFO` string before them.
+///
+/// If `has_speakers` is set, the speaker names are not counted, as they are translated separately.
+pub(super) fn collect_stats(
+    strings: &[TracedString],
+    entries: &Entries,
+    has_speakers: bool,
+    style: MessageCommandStyle,
+    id_range_size: u32,
+) -> TranslationStats {
+    assert!(id_range_size > 0, "message id range size must be positive");
+
+    let mut bump = Bump::new();
+
+    let mut total = StatsCounts::default();
+    let mut by_kind = BTreeMap::<&'static str, StatsCounts>::new();
+    let mut by_message_id = BTreeMap::<u32, StatsCounts>::new();
+    let mut by_scene = vec![SceneStats {
+        offset: None,
+        title: None,
+        counts: StatsCounts::default(),
+    }];
+
+    for (index, string) in (0..).zip(strings) {
+        if let AnyStringSource::Singular(StringSource::Saveinfo) = string.source {
+            by_scene.push(SceneStats {
+                offset: Some(string.offset),
+                title: Some(string.s.clone()),
+                counts: StatsCounts::default(),
+            });
+        }
+
+        let source_text = match split_speaker_name(style, &string.s) {
+            Some((_, message)) if has_speakers && string.source.is_for_messagebox() => message,
+            _ => &string.s,
+        };
+        let (source_characters, _) = count_text(&bump, style, string.source, source_text);
+        let translation = entries
+            .get(index, string.offset, string.source)
+            .and_then(|entry| entry.translated.as_deref())
+            .filter(|translated| !translated.is_empty())
+            .map(|translated| count_text(&bump, style, string.source, translated));
+        bump.reset();
+
+        total.add(source_characters, translation);
+        by_kind
+            .entry(string.source.kind().name())
+            .or_default()
+            .add(source_characters, translation);
+        if let AnyStringSource::Singular(StringSource::Msgset(id)) = string.source {
+            by_message_id
+                .entry(id / id_range_size)
+                .or_default()
+                .add(source_characters, translation);
+        }
+        by_scene
+            .last_mut()
+            .unwrap()
+            .counts
+            .add(source_characters, translation);
+    }
+
+    // don't report an empty pseudo-scene if the script starts with a `SAVEINFO`
+    if by_scene[0].counts.strings == 0 {
+        by_scene.remove(0);
+    }
+
+    TranslationStats {
+        total,
+        by_kind,
+        by_message_id: by_message_id
+            .into_iter()
+            .map(|(range, counts)| MessageIdRangeStats {
+                start: range * id_range_size,
+                end: range * id_range_size + (id_range_size - 1),
+                counts,
+            })
+            .collect(),
+        by_scene,
+    }
+}
+
+fn write_row(f: &mut fmt::Formatter<'_>, name: &str, counts: &StatsCounts) -> fmt::Result {
+    writeln!(
+        f,
+        "{:<32} {:>8} {:>8} {:>6.1}% {:>10} {:>10} {:>10}",
+        name,
+        counts.strings,
+        counts.translated,
+        counts.progress(),
+        counts.source_characters,
+        counts.untranslated_source_characters,
+        counts.translated_words,
+    )
+}
+
+fn write_header(f: &mut fmt::Formatter<'_>, title: &str) -> fmt::Result {
+    writeln!(
+        f,
+        "{:<32} {:>8} {:>8} {:>7} {:>10} {:>10} {:>10}",
+        title, "strings", "done", "", "src chars", "remaining", "tl words"
+    )
+}
+
+impl fmt::Display for TranslationStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_header(f, "Kind")?;
+        for (kind, counts) in &self.by_kind {
+            write_row(f, kind, counts)?;
+        }
+        write_row(f, "total", &self.total)?;
+
+        if !self.by_message_id.is_empty() {
+            writeln!(f)?;
+            write_header(f, "Message ids")?;
+            for range in &self.by_message_id {
+                write_row(f, &format!("{}-{}", range.start, range.end), &range.counts)?;
+            }
+        }
+
+        if !self.by_scene.is_empty() {
+            writeln!(f)?;
+            write_header(f, "Scene")?;
+            for scene in &self.by_scene {
+                let name = match (scene.offset, &scene.title) {
+                    (Some(offset), Some(title)) => format!("0x{:08x} {}", offset, title),
+                    _ => "(before the first scene)".to_string(),
+                };
+                write_row(f, &name, &scene.counts)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use shin_versions::MessageCommandStyle;
+
+    use crate::reactor::{
+        AnyStringSource, StringSource,
+        rewrite::{CsvData, PoData},
+        trace::TracedString,
+    };
+
+    #[test]
+    fn stats() {
+        let string = |offset, source, s: &str| TracedString {
+            offset,
+            source: AnyStringSource::Singular(source),
+            s: s.to_string(),
+            voice: None,
+        };
+        let strings = [
+            string(0x10, StringSource::Saveinfo, "第一話"),
+            string(0x20, StringSource::Msgset(1), "@r「はい」"),
+            string(0x30, StringSource::Msgset(2), "@rここは庭だった。"),
+            string(0x40, StringSource::Msgset(1001), "@rいいえ"),
+            string(0x50, StringSource::Saveinfo, "第一話"),
+        ];
+        let translations = CsvData::new(
+            csv::Reader::from_reader(
+                "index,offset,source,source_subindex,voice,s,translated
+0,0x00000010,saveinfo,0,,第一話,Chapter 1
+1,0x00000020,msgset,1,,@r「はい」,@r\"Yes\"
+2,0x00000030,msgset,2,,@rここは庭だった。,
+3,0x00000040,msgset,1001,,@rいいえ,@rNo way
+4,0x00000050,saveinfo,0,,第一話,Chapter 1
+"
+                .as_bytes(),
+            ),
+            None,
+        );
+
+        let stats = translations.stats(&strings, MessageCommandStyle::Escaped, 1000);
+
+        assert_eq!(stats.total.strings, 5);
+        assert_eq!(stats.total.translated, 4);
+        // `@r` is not counted
+        assert_eq!(stats.total.source_characters, 3 + 4 + 8 + 3 + 3);
+        assert_eq!(stats.total.untranslated_source_characters, 8);
+        assert_eq!(stats.total.translated_words, 2 + 1 + 2 + 2);
+
+        assert_eq!(stats.by_kind["msgset"].strings, 3);
+        assert_eq!(stats.by_kind["saveinfo"].translated, 2);
+
+        assert_eq!(stats.by_message_id.len(), 2);
+        assert_eq!(
+            (stats.by_message_id[1].start, stats.by_message_id[1].end),
+            (1000, 1999)
+        );
+        assert_eq!(stats.by_message_id[0].counts.untranslated, 1);
+
+        // the scenes with the same title are still counted separately
+        assert_eq!(stats.by_scene.len(), 2);
+        assert_eq!(stats.by_scene[0].offset, Some(0x10));
+        assert_eq!(stats.by_scene[0].title.as_deref(), Some("第一話"));
+        assert_eq!(stats.by_scene[0].counts.strings, 4);
+        assert_eq!(stats.by_scene[1].offset, Some(0x50));
+        assert_eq!(stats.by_scene[1].counts.strings, 1);
+    }
+
+    #[test]
+    fn stats_po() {
+        let strings = [TracedString {
+            offset: 0x10,
+            source: AnyStringSource::Singular(StringSource::Msgset(1)),
+            s: "@r「はい」".to_string(),
+            voice: None,
+        }];
+        let translations = PoData::parse(
+            r#"msgctxt "0|0x00000010|msgset|1"
+msgid "@r「はい」"
+msgstr "@r\"Yes\""
+"#,
+        )
+        .unwrap();
+
+        let stats = translations.stats(&strings, MessageCommandStyle::Escaped, 1000);
+
+        assert_eq!(stats.total.translated, 1);
+        assert_eq!(stats.total.translated_characters, 5);
+        // there are no scenes
+        assert_eq!(stats.by_scene[0].offset, None);
+    }
+
+    #[test]
+    fn stats_speakers() {
+        let strings = [TracedString {
+            offset: 0x10,
+            source: AnyStringSource::Singular(StringSource::Msgset(1)),
+            s: "圭一@r「はい」".to_string(),
+            voice: None,
+        }];
+        let translations = CsvData::new_unmerged(csv::Reader::from_reader(
+            "index,offset,source,source_subindex,voice,speaker,s,translated
+0,0x00000010,msgset,1,,圭一,@r「はい」,@rYes sir
+"
+            .as_bytes(),
+        ));
+
+        let stats = translations.stats(&strings, MessageCommandStyle::Escaped, 1000);
+
+        // the name is translated separately, so it's counted neither in the original nor in the translation
+        assert_eq!(stats.total.source_characters, 4);
+        assert_eq!(stats.total.translated_characters, 6);
+        assert_eq!(stats.total.translated_words, 2);
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+
+use bumpalo::Bump;
+use quick_xml::events::{BytesStart, Event};
+use shin_text::CharRemap;
+use shin_versions::{MessageCommandStyle, StringEncoding};
+
+use crate::{
+    layout::message_parser::{self, MessageToken, lint::diagnostics::LineReport},
+    reactor::{
+        AnyStringSource,
+        rewrite::{
+            StringReplacementMode, StringRewriter, TranslationStats,
+            entry::{Entries, Entry, LintOptions, parse_context},
+            stats::collect_stats,
+        },
+        trace::TracedString,
+    },
+    substitution::SubstitutionTable,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum XliffParseError {
+    #[error("Failed to parse the XLIFF file: {0}")]
+    Xml(#[from] quick_xml::Error),
+    #[error("Failed to parse the XLIFF file: {0}")]
+    Attribute(#[from] quick_xml::events::attributes::AttrError),
+    #[error("Invalid XLIFF unit `{unit}`: {message}")]
+    InvalidUnit { unit: String, message: &'static str },
+}
+
+/// A piece of `<source>` or `<target>` content
+enum Inline {
+    Text(String),
+    /// A placeholder, referring to the `<originalData>` entry
+    Placeholder(String),
+}
+
+#[derive(Default)]
+struct Unit {
+    name: Option<String>,
+    original_data: HashMap<String, String>,
+    source: Vec<Inline>,
+    target: Option<Vec<Inline>>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Location {
+    Outside,
+    Data,
+    Source,
+    Target,
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, XliffParseError> {
+    Ok(match element.try_get_attribute(name)? {
+        Some(attribute) => Some(attribute.unescape_value()?.into_owned()),
+        None => None,
+    })
+}
+
+fn parse_units(source: &str) -> Result<Vec<Unit>, XliffParseError> {
+    let mut reader = quick_xml::Reader::from_str(source);
+
+    let mut units = Vec::new();
+    let mut unit: Option<Unit> = None;
+    let mut location = Location::Outside;
+    let mut data_id = String::new();
+
+    loop {
+        let event = reader.read_event()?;
+
+        let text = match &event {
+            Event::Text(text) => Some(text.unescape()?.into_owned()),
+            Event::CData(cdata) => Some(String::from_utf8_lossy(cdata).into_owned()),
+            _ => None,
+        };
+
+        match event {
+            Event::Eof => break,
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"unit" => {
+                unit = Some(Unit {
+                    name: attribute(&e, "name")?,
+                    ..Default::default()
+                });
+            }
+            Event::End(e) if e.local_name().as_ref() == b"unit" => {
+                units.extend(unit.take());
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"data" => {
+                location = Location::Data;
+                data_id = attribute(&e, "id")?.unwrap_or_default();
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"source" => {
+                location = Location::Source;
+            }
+            Event::Start(e) if e.local_name().as_ref() == b"target" => {
+                location = Location::Target;
+                if let Some(unit) = &mut unit {
+                    unit.target.get_or_insert_with(Vec::new);
+                }
+            }
+            Event::End(e) if matches!(e.local_name().as_ref(), b"data" | b"source" | b"target") => {
+                location = Location::Outside;
+            }
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"ph" => {
+                let Some(unit) = &mut unit else {
+                    continue;
+                };
+                let Some(data_ref) = attribute(&e, "dataRef")? else {
+                    return Err(XliffParseError::InvalidUnit {
+                        unit: unit.name.clone().unwrap_or_default(),
+                        message: "a placeholder without `dataRef`",
+                    });
+                };
+                match location {
+                    Location::Source => unit.source.push(Inline::Placeholder(data_ref)),
+                    Location::Target => {
+                        if let Some(target) = &mut unit.target {
+                            target.push(Inline::Placeholder(data_ref))
+                        }
+                    }
+                    Location::Outside | Location::Data => {}
+                }
+            }
+            Event::Start(e) | Event::Empty(e)
+                if matches!(
+                    e.local_name().as_ref(),
+                    b"pc" | b"sc" | b"ec" | b"mrk" | b"sm" | b"em"
+                ) && matches!(location, Location::Source | Location::Target) =>
+            {
+                // we only produce `<ph>` placeholders, so other inline elements can only come from a CAT tool
+                // annotations (`<mrk>`) are fine to skip, but their contents are still part of the text
+                if !matches!(e.local_name().as_ref(), b"mrk" | b"sm" | b"em") {
+                    return Err(XliffParseError::InvalidUnit {
+                        unit: unit
+                            .as_ref()
+                            .and_then(|u| u.name.clone())
+                            .unwrap_or_default(),
+                        message: "unsupported inline element, only `<ph>` is supported",
+                    });
+                }
+            }
+            _ => {
+                let (Some(text), Some(unit)) = (text, &mut unit) else {
+                    continue;
+                };
+                match location {
+                    Location::Outside => {}
+                    Location::Data => {
+                        unit.original_data
+                            .entry(data_id.clone())
+                            .or_default()
+                            .push_str(&text);
+                    }
+                    Location::Source => unit.source.push(Inline::Text(text)),
+                    Location::Target => {
+                        if let Some(target) = &mut unit.target {
+                            target.push(Inline::Text(text))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(units)
+}
+
+/// Converts the unit content back to a string, restoring the commands from the placeholders
+fn restore_string(
+    bump: &Bump,
+    style: MessageCommandStyle,
+    source: AnyStringSource,
+    unit: &Unit,
+    content: &[Inline],
+) -> Result<String, XliffParseError> {
+    let error = |message| XliffParseError::InvalidUnit {
+        unit: unit.name.clone().unwrap_or_default(),
+        message,
+    };
+
+    if !source.contains_commands() {
+        let mut result = String::new();
+        for inline in content {
+            match inline {
+                Inline::Text(text) => result.push_str(text),
+                Inline::Placeholder(_) => {
+                    return Err(error("a placeholder in a string that can't have commands"));
+                }
+            }
+        }
+        return Ok(result);
+    }
+
+    let mut tokens = bumpalo::collections::Vec::new_in(bump);
+    for inline in content {
+        match inline {
+            Inline::Text(text) => {
+                tokens.extend(text.chars().map(MessageToken::Literal));
+            }
+            Inline::Placeholder(data_ref) => {
+                let Some(command) = unit.original_data.get(data_ref) else {
+                    return Err(error("a placeholder refers to a missing `<data>` element"));
+                };
+                let command = bump.alloc_str(command);
+                message_parser::parse(style, command, &mut tokens);
+            }
+        }
+    }
+
+    Ok(message_parser::serialize_string(bump, style, &tokens).to_string())
+}
+
+pub struct XliffData {
+    entries: Entries,
+}
+
+impl XliffData {
+    /// Parses an XLIFF 2.0 file created by `XliffTraceListener` (and filled in by the translators).
+    ///
+    /// `style` must match the one used when creating the file.
+    pub fn parse(source: &str, style: MessageCommandStyle) -> Result<Self, XliffParseError> {
+        let mut entries = Entries::new();
+        let mut bump = Bump::new();
+
+        for unit in parse_units(source)? {
+            let Some((index, offset, source)) = unit.name.as_deref().and_then(parse_context) else {
+                continue;
+            };
+
+            let s = restore_string(&bump, style, source, &unit, &unit.source)?;
+            let translated = match &unit.target {
+                Some(target) if !target.is_empty() => {
+                    Some(restore_string(&bump, style, source, &unit, target)?)
+                }
+                _ => None,
+            };
+            bump.reset();
+
+            entries.insert(
+                index,
+                Entry {
+                    offset,
+                    source,
+                    s,
+                    translated,
+                    speaker_lens: (0, 0),
+                },
+            );
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Fills in untranslated strings from other strings with the same text. Returns the number of filled strings.
+    pub fn propagate_repeats(&mut self) -> usize {
+        self.entries.propagate_repeats()
+    }
+
+    /// Collects the translation progress statistics, see [`collect_stats`] for details
+    pub fn stats(
+        &self,
+        strings: &[TracedString],
+        style: MessageCommandStyle,
+        id_range_size: u32,
+    ) -> TranslationStats {
+        collect_stats(strings, &self.entries, false, style, id_range_size)
+    }
+
+    /// Replaces the characters that can't be encoded in the translations. Returns the number of changed strings.
+    pub fn substitute(
+        &mut self,
+        substitutions: &SubstitutionTable,
+        encoding: StringEncoding,
+        char_remap: &CharRemap,
+    ) -> usize {
+        self.entries.substitute(substitutions, encoding, char_remap)
+    }
+
+    pub fn lint(&self, options: &LintOptions) -> Result<(), Vec<LineReport>> {
+        self.entries.lint(options)
+    }
+}
+
+pub struct XliffRewriter {
+    entries: Entries,
+    mode: StringReplacementMode,
+}
+
+impl XliffRewriter {
+    pub fn new(data: XliffData, mode: StringReplacementMode) -> Self {
+        Self {
+            entries: data.entries,
+            mode,
+        }
+    }
+}
+
+impl StringRewriter for XliffRewriter {
+    fn rewrite_string<'bump>(
+        &'bump self,
+        _bump: &'bump Bump,
+        _decoded: &'bump str,
+        instr_index: u32,
+        instr_offset: u32,
+        source: AnyStringSource,
+    ) -> Option<&'bump str> {
+        self.entries
+            .get(instr_index, instr_offset, source)?
+            .get_effective_string(self.mode)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+    use shin_versions::MessageCommandStyle;
+
+    use super::{XliffData, XliffRewriter};
+    use crate::reactor::{
+        AnyStringSource, StringArraySource, StringSource,
+        rewrite::{StringReplacementMode, StringRewriter},
+        trace::{StringTraceListener, XliffTraceListener},
+    };
+
+    const STRINGS: [(u32, AnyStringSource, &str); 4] = [
+        (
+            0x10,
+            AnyStringSource::Singular(StringSource::Msgset(1)),
+            "@rここは<庭>&@c900.赤@c.",
+        ),
+        (
+            0x20,
+            AnyStringSource::Singular(StringSource::Msgset(2)),
+            "@r未訳",
+        ),
+        (
+            0x30,
+            AnyStringSource::Array(StringArraySource::Select, 0),
+            "はい",
+        ),
+        (
+            0x40,
+            AnyStringSource::Singular(StringSource::Saveinfo),
+            "プロローグ",
+        ),
+    ];
+
+    /// Traces the strings to XLIFF, and adds the targets to the units, like a CAT tool would
+    fn translate(targets: [Option<&str>; 4]) -> String {
+        let mut listener = XliffTraceListener::new(Vec::new(), MessageCommandStyle::Escaped);
+        for (offset, source, s) in STRINGS {
+            listener.on_string(offset, source, s, None);
+        }
+        let xliff = String::from_utf8(listener.finish()).unwrap();
+
+        assert!(xliff.contains("name=\"0|0x00000010|msgset|1\""));
+        assert!(xliff.contains(
+            "<source><ph id=\"1\" dataRef=\"d1\" disp=\"@r\"/>ここは&lt;庭&gt;&amp;\
+             <ph id=\"2\" dataRef=\"d2\" disp=\"@c900.\"/>赤<ph id=\"3\" dataRef=\"d3\" disp=\"@c.\"/></source>"
+        ));
+
+        let mut parts = xliff.split("</source>\n");
+        let mut translated = parts.next().unwrap().to_string();
+        for (part, target) in parts.zip(targets.into_iter().chain([None])) {
+            translated.push_str("</source>\n");
+            if let Some(target) = target {
+                translated.push_str(&format!("<target>{}</target>\n", target));
+            }
+            translated.push_str(part);
+        }
+        translated
+    }
+
+    #[test]
+    fn roundtrip() {
+        let xliff = translate([
+            Some(
+                "<ph id=\"1\" dataRef=\"d1\"/>A &lt;garden&gt; &amp; \
+                 <ph id=\"2\" dataRef=\"d2\"/>red<ph id=\"3\" dataRef=\"d3\"/>",
+            ),
+            None,
+            Some("Yes, &quot;sure&quot;"),
+            Some("Prologue &amp; epilogue"),
+        ]);
+        let data = XliffData::parse(&xliff, MessageCommandStyle::Escaped).unwrap();
+
+        // the original strings are restored from the placeholders
+        for (index, (offset, source, s)) in (0..).zip(STRINGS) {
+            assert_eq!(data.entries.get(index, offset, source).unwrap().s, s);
+        }
+
+        let bump = Bump::new();
+        let rewrite = |rewriter: &XliffRewriter| {
+            (0..)
+                .zip(STRINGS)
+                .map(|(index, (offset, source, s))| {
+                    rewriter
+                        .rewrite_string(&bump, bump.alloc_str(s), index, offset, source)
+                        .map(|s| s.to_string())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let rewriter = XliffRewriter::new(data, StringReplacementMode::TranslatedOnly);
+        assert_eq!(
+            rewrite(&rewriter),
+            [
+                Some("@rA <garden> & @c900.red@c.".to_string()),
+                None,
+                Some("Yes, \"sure\"".to_string()),
+                Some("Prologue & epilogue".to_string()),
+            ]
+        );
+
+        let data = XliffData::parse(&xliff, MessageCommandStyle::Escaped).unwrap();
+        let rewriter = XliffRewriter::new(data, StringReplacementMode::TranslatedOrOriginal);
+        assert_eq!(rewrite(&rewriter)[1].as_deref(), Some("@r未訳"));
+    }
+}
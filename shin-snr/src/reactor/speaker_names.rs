@@ -0,0 +1,69 @@
+use std::{collections::HashMap, io};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    name: String,
+    translated: Option<String>,
+}
+
+/// A glossary of speaker names, allowing to translate each character name only once.
+///
+/// Stored as a CSV file with `name` and `translated` columns.
+#[derive(Default)]
+pub struct SpeakerNames {
+    records: Vec<Record>,
+    by_name: HashMap<String, usize>,
+}
+
+impl SpeakerNames {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn read<R: io::Read>(reader: csv::Reader<R>) -> Self {
+        let mut result = Self::new();
+
+        for record in reader.into_deserialize() {
+            let record: Record = record.unwrap();
+            match result.by_name.get(&record.name) {
+                Some(&index) => result.records[index] = record,
+                None => {
+                    result
+                        .by_name
+                        .insert(record.name.clone(), result.records.len());
+                    result.records.push(record);
+                }
+            }
+        }
+
+        result
+    }
+
+    pub fn write<W: io::Write>(&self, mut writer: csv::Writer<W>) -> csv::Result<()> {
+        for record in &self.records {
+            writer.serialize(record)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Adds a name to the glossary, if it's not already there
+    pub fn insert(&mut self, name: &str) {
+        if !self.by_name.contains_key(name) {
+            self.by_name.insert(name.to_string(), self.records.len());
+            self.records.push(Record {
+                name: name.to_string(),
+                translated: None,
+            });
+        }
+    }
+
+    /// Returns the translation of the name, if there is one
+    pub fn translate(&self, name: &str) -> Option<&str> {
+        let &index = self.by_name.get(name)?;
+        self.records[index].translated.as_deref()
+    }
+}
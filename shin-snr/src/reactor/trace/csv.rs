@@ -1,14 +1,22 @@
-use std::io;
+use std::{collections::HashMap, io};
 
-use bumpalo::collections::String;
 use serde::{ser::SerializeStruct, Serialize, Serializer};
 use smallvec::SmallVec;
 
-use crate::reactor::{trace::StringTraceListener, AnyStringSource};
+use shin_versions::{AnyStringKind, MessageCommandStyle};
+
+use crate::{
+    layout::message_parser::split_speaker_name,
+    reactor::{AnyStringSource, speaker_names::SpeakerNames, trace::StringTraceListener},
+};
 
 pub struct CsvTraceListener<W: io::Write> {
     writer: csv::Writer<W>,
     record_idx: u32,
+    /// If present, the speaker names are split off messages into a separate column and collected here
+    speakers: Option<(MessageCommandStyle, SpeakerNames)>,
+    /// If present, the strings that were already seen are marked with the index of their first occurrence
+    repeats: Option<HashMap<(AnyStringKind, String), u32>>,
 }
 
 // NOTE: serialization is kind of hard with bumpalo
@@ -17,8 +25,13 @@ struct Record<'bump> {
     index: u32,
     offset: u32,
     source: AnyStringSource,
+    voice: Option<&'bump str>,
+    /// `None` if the speaker column is not emitted at all, `Some(None)` if the message doesn't have a speaker
+    speaker: Option<Option<&'bump str>>,
+    /// `None` if the repeat_of column is not emitted at all, `Some(None)` if this is the first occurrence of the string
+    repeat_of: Option<Option<u32>>,
     s: &'bump str,
-    translated: Option<String<'bump>>,
+    translated: Option<&'bump str>,
 }
 
 impl<'bump> Serialize for Record<'bump> {
@@ -29,7 +42,7 @@ impl<'bump> Serialize for Record<'bump> {
         use std::io::Write as _;
         let mut buf = SmallVec::<u8, 16>::new();
 
-        let mut s = serializer.serialize_struct("Record", 6)?;
+        let mut s = serializer.serialize_struct("Record", 9)?;
         s.serialize_field("index", &self.index)?;
 
         write!(buf, "0x{:08x}", self.offset).unwrap();
@@ -39,8 +52,17 @@ impl<'bump> Serialize for Record<'bump> {
         s.serialize_field("offset", &offset)?;
         s.serialize_field("source", &self.source.kind())?;
         s.serialize_field("source_subindex", &self.source.subindex())?;
+        s.serialize_field("voice", &self.voice)?;
+        match self.speaker {
+            Some(speaker) => s.serialize_field("speaker", &speaker)?,
+            None => s.skip_field("speaker")?,
+        }
+        match self.repeat_of {
+            Some(repeat_of) => s.serialize_field("repeat_of", &repeat_of)?,
+            None => s.skip_field("repeat_of")?,
+        }
         s.serialize_field("s", &self.s)?;
-        s.serialize_field("translated", &self.translated.as_ref().map(|s| s.as_str()))?;
+        s.serialize_field("translated", &self.translated)?;
         s.end()
     }
 }
@@ -50,17 +72,118 @@ impl<W: io::Write> CsvTraceListener<W> {
         Self {
             writer,
             record_idx: 0,
+            speakers: None,
+            repeats: None,
+        }
+    }
+
+    /// Creates a listener that splits speaker names off the messages into a separate `speaker` column.
+    ///
+    /// The encountered names are added to `speaker_names`, which can be retrieved with [`Self::into_speaker_names`].
+    pub fn with_speaker_names(
+        writer: csv::Writer<W>,
+        style: MessageCommandStyle,
+        speaker_names: SpeakerNames,
+    ) -> Self {
+        Self {
+            writer,
+            record_idx: 0,
+            speakers: Some((style, speaker_names)),
+            repeats: None,
+        }
+    }
+
+    /// Adds a `repeat_of` column, containing the index of the first string of the same kind with the same text.
+    ///
+    /// This allows translators to group repeated lines and translate them only once (see `--propagate-repeats` option of the rewriter).
+    pub fn mark_repeats(mut self) -> Self {
+        self.repeats = Some(HashMap::new());
+        self
+    }
+
+    fn repeat_of(&mut self, source: AnyStringSource, s: &str) -> Option<Option<u32>> {
+        let repeats = self.repeats.as_mut()?;
+        match repeats.get(&(source.kind(), s.to_string())) {
+            Some(&first) => Some(Some(first)),
+            None => {
+                repeats.insert((source.kind(), s.to_string()), self.record_idx);
+                Some(None)
+            }
         }
     }
+
+    pub fn into_speaker_names(self) -> Option<SpeakerNames> {
+        self.speakers.map(|(_, names)| names)
+    }
+
+    /// Splits the speaker name off the message, if the speaker names are collected
+    fn split_speaker<'s>(
+        &mut self,
+        source: AnyStringSource,
+        s: &'s str,
+    ) -> (Option<Option<&'s str>>, &'s str) {
+        match &mut self.speakers {
+            Some((style, names)) => {
+                match split_speaker_name(*style, s).filter(|_| source.is_for_messagebox()) {
+                    Some((speaker, s)) => {
+                        names.insert(speaker);
+                        (Some(Some(speaker)), s)
+                    }
+                    None => (Some(None), s),
+                }
+            }
+            None => (None, s),
+        }
+    }
+
+    /// Writes a string together with an already existing translation.
+    ///
+    /// If the speaker names are split off, the translation should not include the speaker name, the same as in the `translated` column of such files.
+    pub fn on_translated_string(
+        &mut self,
+        instr_offset: u32,
+        source: AnyStringSource,
+        s: &str,
+        voice: Option<&str>,
+        translated: Option<&str>,
+    ) {
+        let (speaker, s) = self.split_speaker(source, s);
+        let repeat_of = self.repeat_of(source, s);
+        self.writer
+            .serialize(Record {
+                index: self.record_idx,
+                offset: instr_offset,
+                source,
+                voice,
+                speaker,
+                repeat_of,
+                s,
+                translated,
+            })
+            .unwrap();
+        self.record_idx += 1;
+    }
 }
 
 impl<W: io::Write> StringTraceListener for CsvTraceListener<W> {
-    fn on_string(&mut self, instr_offset: u32, source: AnyStringSource, s: &str) {
+    fn on_string(
+        &mut self,
+        instr_offset: u32,
+        source: AnyStringSource,
+        s: &str,
+        voice: Option<&str>,
+    ) {
+        let (speaker, s) = self.split_speaker(source, s);
+        let repeat_of = self.repeat_of(source, s);
+
         self.writer
             .serialize(Record {
                 index: self.record_idx,
                 offset: instr_offset,
                 source,
+                voice,
+                speaker,
+                repeat_of,
                 s,
                 translated: None,
             })
@@ -1,13 +1,19 @@
 mod console;
 mod csv;
+mod po;
+mod xliff;
 
 use bumpalo::Bump;
 use shin_text::StringArrayIter;
 use shin_versions::{MessageCommandStyle, StringEncoding};
 
-pub use self::{console::ConsoleTraceListener, csv::CsvTraceListener};
+pub use self::{
+    console::ConsoleTraceListener, csv::CsvTraceListener, po::PoTraceListener,
+    xliff::XliffTraceListener,
+};
 use crate::{
-    layout::message_parser::MessageReflowMode,
+    header::SnrHeader,
+    layout::message_parser::{self, MessageCommand, MessageReflowMode, MessageToken},
     operation::{
         OperationElementRepr,
         arena::OperationArena,
@@ -18,7 +24,68 @@ use crate::{
 };
 
 pub trait StringTraceListener {
-    fn on_string(&mut self, instr_offset: u32, source: AnyStringSource, s: &str);
+    /// Called for each string found in the SNR file.
+    ///
+    /// `voice` contains the voice file(s) associated with a message: either the arguments of voice commands (`@v`)
+    /// in the message itself or, if there are none, the file of the most recent `VOICEPLAY` command.
+    fn on_string(
+        &mut self,
+        instr_offset: u32,
+        source: AnyStringSource,
+        s: &str,
+        voice: Option<&str>,
+    );
+}
+
+/// A string collected by the `Vec<TracedString>` listener
+#[derive(Debug, Clone)]
+pub struct TracedString {
+    pub offset: u32,
+    pub source: AnyStringSource,
+    pub s: String,
+    pub voice: Option<String>,
+}
+
+impl StringTraceListener for Vec<TracedString> {
+    fn on_string(
+        &mut self,
+        instr_offset: u32,
+        source: AnyStringSource,
+        s: &str,
+        voice: Option<&str>,
+    ) {
+        self.push(TracedString {
+            offset: instr_offset,
+            source,
+            s: s.to_string(),
+            voice: voice.map(|v| v.to_string()),
+        });
+    }
+}
+
+/// Collects arguments of all voice commands (`@v`) in the message, separated by `;`
+fn inline_voices<'bump>(
+    bump: &'bump Bump,
+    style: MessageCommandStyle,
+    message: &'bump str,
+) -> Option<&'bump str> {
+    let mut tokens = bumpalo::collections::Vec::new_in(bump);
+    message_parser::parse(style, message, &mut tokens);
+
+    let mut voices = bumpalo::collections::String::new_in(bump);
+    for token in tokens {
+        if let MessageToken::Command(command) = token
+            && let Some(MessageCommand::Voice) = MessageCommand::parse(command.command)
+            && let Some(argument) = command.argument
+        {
+            if !voices.is_empty() {
+                voices.push(';');
+            }
+            voices.push_str(argument);
+        }
+    }
+
+    (!voices.is_empty()).then(|| voices.into_bump_str())
 }
 
 pub struct StringTraceReactor<L> {
@@ -27,6 +94,10 @@ pub struct StringTraceReactor<L> {
     user_style: MessageCommandStyle,
     has_useless_escapes: bool,
     listener: L,
+    /// The file of the last `VOICEPLAY` that was not yet associated with a message
+    pending_voice: Option<String>,
+    /// Strings from the header tables, reported after the ones from the code
+    header_strings: Vec<(u32, AnyStringSource, String)>,
     bump: Bump,
 }
 
@@ -44,10 +115,28 @@ impl<L: StringTraceListener> StringTraceReactor<L> {
             user_style,
             has_useless_escapes,
             listener,
+            pending_voice: None,
+            header_strings: Vec::new(),
             bump: Bump::new(),
         }
     }
 
+    /// Also reports the translatable strings from the header tables.
+    ///
+    /// They go after all the strings from the code, so that adding them doesn't change the indices of the other strings.
+    pub fn with_header(mut self, header: &SnrHeader) -> Self {
+        self.header_strings = header
+            .strings()
+            .into_iter()
+            .map(|(offset, source, s)| (offset, source, s.to_string()))
+            .collect();
+        self
+    }
+
+    pub fn into_listener(self) -> L {
+        self.listener
+    }
+
     fn on_string_impl(&mut self, operation_position: u32, source: AnyStringSource, s: &[u8]) {
         let snr_string = decode_zstring(
             &self.bump,
@@ -67,8 +156,21 @@ impl<L: StringTraceListener> StringTraceReactor<L> {
             source,
         );
 
+        let voice = match source {
+            AnyStringSource::Singular(StringSource::Voiceplay) => {
+                self.pending_voice = Some(snr_string.to_string());
+                None
+            }
+            source if source.is_for_messagebox() => {
+                let pending_voice = self.pending_voice.take();
+                inline_voices(&self.bump, self.snr_style, snr_string)
+                    .or_else(|| pending_voice.map(|voice| &*self.bump.alloc_str(&voice)))
+            }
+            _ => None,
+        };
+
         self.listener
-            .on_string(operation_position, source, user_string)
+            .on_string(operation_position, source, user_string, voice)
     }
 }
 
@@ -119,4 +221,59 @@ impl<L: StringTraceListener> Reactor for StringTraceReactor<L> {
 
         self.bump.reset();
     }
+
+    fn end_of_stream(&mut self) {
+        for (offset, source, s) in std::mem::take(&mut self.header_strings) {
+            // the header strings don't contain any commands, so there is nothing to transform
+            self.listener.on_string(offset, source, &s, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use shin_versions::{MessageCommandStyle, StringEncoding};
+
+    use super::{StringTraceReactor, TracedString};
+    use crate::reactor::{AnyStringSource, StringSource};
+
+    #[test]
+    fn voices() {
+        let mut reactor = StringTraceReactor::new(
+            StringEncoding::Utf8,
+            MessageCommandStyle::Escaped,
+            MessageCommandStyle::Escaped,
+            false,
+            Vec::<TracedString>::new(),
+        );
+        let msgset = |id| AnyStringSource::Singular(StringSource::Msgset(id));
+        let voiceplay = AnyStringSource::Singular(StringSource::Voiceplay);
+
+        reactor.on_string_impl(0x10, msgset(1), b"@vvoice/01.Hello@vvoice/02.!\0");
+        reactor.on_string_impl(0x20, voiceplay, b"voice/03\0");
+        reactor.on_string_impl(0x30, msgset(2), b"@rNarration\0");
+        reactor.on_string_impl(0x40, msgset(3), b"No voice\0");
+        // the inline voice wins over the pending one, which is dropped
+        reactor.on_string_impl(0x50, voiceplay, b"voice/04\0");
+        reactor.on_string_impl(0x60, msgset(4), b"@vvoice/05.Inline\0");
+        reactor.on_string_impl(0x70, msgset(5), b"No voice either\0");
+
+        let voices = reactor
+            .into_listener()
+            .into_iter()
+            .map(|s| (s.offset, s.voice))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            voices,
+            [
+                (0x10, Some("voice/01;voice/02".to_string())),
+                (0x20, None),
+                (0x30, Some("voice/03".to_string())),
+                (0x40, None),
+                (0x50, None),
+                (0x60, Some("voice/05".to_string())),
+                (0x70, None),
+            ]
+        );
+    }
 }
@@ -0,0 +1,92 @@
+use std::io;
+
+use crate::reactor::{AnyStringSource, StringSource, trace::StringTraceListener};
+
+/// Writes a string as a PO string literal, escaping it as needed
+fn write_po_string<W: io::Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => writer.write_all(b"\\\"")?,
+            '\\' => writer.write_all(b"\\\\")?,
+            '\n' => writer.write_all(b"\\n")?,
+            '\r' => writer.write_all(b"\\r")?,
+            '\t' => writer.write_all(b"\\t")?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+    writer.write_all(b"\"")
+}
+
+/// Writes strings to a gettext PO file.
+///
+/// Each string gets a `msgctxt` of form `index|offset|source|source_subindex`, mirroring the CSV columns.
+/// It is used to inject the translated strings back into the SNR file.
+pub struct PoTraceListener<W: io::Write> {
+    writer: W,
+    record_idx: u32,
+}
+
+impl<W: io::Write> PoTraceListener<W> {
+    pub fn new(mut writer: W) -> Self {
+        writer
+            .write_all(b"msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n")
+            .unwrap();
+
+        Self {
+            writer,
+            record_idx: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_entry(
+        &mut self,
+        instr_offset: u32,
+        source: AnyStringSource,
+        s: &str,
+        voice: Option<&str>,
+    ) -> io::Result<()> {
+        let kind = source.kind().name();
+
+        writeln!(self.writer)?;
+        writeln!(self.writer, "# source: {}", kind)?;
+        if let AnyStringSource::Singular(StringSource::Msgset(message_id)) = source {
+            writeln!(self.writer, "# message id: {}", message_id)?;
+        }
+        if let Some(voice) = voice {
+            writeln!(self.writer, "# voice: {}", voice)?;
+        }
+
+        write!(
+            self.writer,
+            "msgctxt \"{}|0x{:08x}|{}|{}\"\nmsgid ",
+            self.record_idx,
+            instr_offset,
+            kind,
+            source.subindex()
+        )?;
+        write_po_string(&mut self.writer, s)?;
+        writeln!(self.writer, "\nmsgstr \"\"")
+    }
+}
+
+impl<W: io::Write> StringTraceListener for PoTraceListener<W> {
+    fn on_string(
+        &mut self,
+        instr_offset: u32,
+        source: AnyStringSource,
+        s: &str,
+        voice: Option<&str>,
+    ) {
+        // an empty msgid is reserved for the PO header, and there's nothing to translate anyway
+        if !s.is_empty() {
+            self.write_entry(instr_offset, source, s, voice).unwrap();
+        }
+
+        self.record_idx += 1;
+    }
+}
@@ -0,0 +1,163 @@
+use std::io;
+
+use bumpalo::{Bump, collections::Vec};
+use quick_xml::escape::escape;
+use shin_versions::MessageCommandStyle;
+
+use crate::{
+    layout::message_parser::{self, MessageToken, SpannedMessageToken},
+    reactor::{AnyStringSource, StringSource, trace::StringTraceListener},
+};
+
+/// Writes strings to an XLIFF 2.0 file.
+///
+/// Each string becomes a `<unit>` named `index|offset|source|source_subindex`, mirroring the CSV columns.
+/// Message commands are replaced with `<ph>` placeholders, with the original command text stored in `<originalData>`,
+/// so that CAT tools don't let translators break them.
+pub struct XliffTraceListener<W: io::Write> {
+    writer: W,
+    style: MessageCommandStyle,
+    record_idx: u32,
+    bump: Bump,
+}
+
+impl<W: io::Write> XliffTraceListener<W> {
+    pub fn new(mut writer: W, style: MessageCommandStyle) -> Self {
+        writer
+            .write_all(
+                b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<xliff xmlns=\"urn:oasis:names:tc:xliff:document:2.0\" version=\"2.0\" srcLang=\"ja\">\n\
+<file id=\"snr\" xml:space=\"preserve\">\n",
+            )
+            .unwrap();
+
+        Self {
+            writer,
+            style,
+            record_idx: 0,
+            bump: Bump::new(),
+        }
+    }
+
+    /// Finishes the XLIFF document and returns the underlying writer
+    pub fn finish(mut self) -> W {
+        self.writer.write_all(b"</file>\n</xliff>\n").unwrap();
+        self.writer
+    }
+
+    fn write_unit(
+        &mut self,
+        instr_offset: u32,
+        source: AnyStringSource,
+        s: &str,
+        voice: Option<&str>,
+    ) -> io::Result<()> {
+        let mut tokens = Vec::<SpannedMessageToken>::new_in(&self.bump);
+        if source.contains_commands() {
+            message_parser::parse(self.style, s, &mut tokens);
+        }
+
+        writeln!(
+            self.writer,
+            "<unit id=\"u{}\" name=\"{}|0x{:08x}|{}|{}\">",
+            self.record_idx,
+            self.record_idx,
+            instr_offset,
+            source.kind().name(),
+            source.subindex()
+        )?;
+
+        writeln!(self.writer, "<notes>")?;
+        writeln!(
+            self.writer,
+            "<note category=\"source\">{}</note>",
+            source.kind().name()
+        )?;
+        if let AnyStringSource::Singular(StringSource::Msgset(message_id)) = source {
+            writeln!(
+                self.writer,
+                "<note category=\"message-id\">{}</note>",
+                message_id
+            )?;
+        }
+        if let Some(voice) = voice {
+            writeln!(
+                self.writer,
+                "<note category=\"voice\">{}</note>",
+                escape(voice)
+            )?;
+        }
+        writeln!(self.writer, "</notes>")?;
+
+        let commands = tokens
+            .iter()
+            .filter(|t| matches!(t.token, MessageToken::Command(_)))
+            .map(|t| &s[t.start..t.end]);
+        let mut commands = commands.peekable();
+        if commands.peek().is_some() {
+            writeln!(self.writer, "<originalData>")?;
+            for (id, command) in (1..).zip(commands) {
+                writeln!(
+                    self.writer,
+                    "<data id=\"d{}\">{}</data>",
+                    id,
+                    escape(command)
+                )?;
+            }
+            writeln!(self.writer, "</originalData>")?;
+        }
+
+        write!(self.writer, "<segment>\n<source>")?;
+        if source.contains_commands() {
+            write_tokens(&mut self.writer, s, &tokens)?;
+        } else {
+            write!(self.writer, "{}", escape(s))?;
+        }
+        writeln!(self.writer, "</source>\n</segment>\n</unit>")?;
+
+        Ok(())
+    }
+}
+
+fn write_tokens<W: io::Write>(
+    writer: &mut W,
+    s: &str,
+    tokens: &[SpannedMessageToken],
+) -> io::Result<()> {
+    let mut command_id = 1;
+    for token in tokens {
+        match token.token {
+            MessageToken::Literal(c) => {
+                let mut buf = [0; 4];
+                write!(writer, "{}", escape(&*c.encode_utf8(&mut buf)))?;
+            }
+            MessageToken::Command(_) => {
+                write!(
+                    writer,
+                    "<ph id=\"{}\" dataRef=\"d{}\" disp=\"{}\"/>",
+                    command_id,
+                    command_id,
+                    escape(&s[token.start..token.end])
+                )?;
+                command_id += 1;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl<W: io::Write> StringTraceListener for XliffTraceListener<W> {
+    fn on_string(
+        &mut self,
+        instr_offset: u32,
+        source: AnyStringSource,
+        s: &str,
+        voice: Option<&str>,
+    ) {
+        self.write_unit(instr_offset, source, s, voice).unwrap();
+        self.bump.reset();
+
+        self.record_idx += 1;
+    }
+}
@@ -18,8 +18,34 @@ impl From<VarIntSize> for usize {
     }
 }
 
+/// An error encountered while reading the SNR bytecode.
+///
+/// The [`Reader`] doesn't stop on errors, instead it remembers the first one and returns zeroes from then on, see [`Reader::error`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+pub enum ReadError {
+    #[error("unexpected end of file: tried to read {size} bytes at 0x{position:08x}")]
+    UnexpectedEof { position: u32, size: usize },
+    #[error("unknown number encoding 0x{t:02x} (P={p}) at 0x{position:08x}")]
+    UnknownNumberSpec { position: u32, t: u8, p: u8 },
+    #[error("expression at 0x{position:08x} is too long, its terminator is probably missing")]
+    ExpressionTooLong { position: u32 },
+}
+
 /// Determine the amount of additional bytes needed to parse a variable integer based on the first read byte
+///
+/// Panics on unknown encodings, use [`try_var_int_size`] to handle those.
 pub fn var_int_size(t: u8) -> VarIntSize {
+    try_var_int_size(t).unwrap_or_else(|| {
+        panic!(
+            "Unknown NumberSpec type: t=0x{:02x}, P={}",
+            t,
+            (t & 0x70) >> 4
+        )
+    })
+}
+
+/// Determine the amount of additional bytes needed to parse a variable integer based on the first read byte, returning `None` for unknown encodings
+pub fn try_var_int_size(t: u8) -> Option<VarIntSize> {
     // t=TXXXXXXX
     // T=0 => XXXXXXX is a 7-bit signed constant
     // T=1 => futher processing needed
@@ -34,21 +60,18 @@ pub fn var_int_size(t: u8) -> VarIntSize {
         // P=5 => 4-bit argument register, KKKK is the index
         // P=6 => constant 0x80000000 aka -2147483648 aka MIN_INT (NOTE: this is not implemented in `shin-core`, as it isn't used in umineko)
         match p {
-            0 => VarIntSize::One,
-            1 => VarIntSize::Two,
-            2 => VarIntSize::Three,
-            3 => VarIntSize::Zero,
-            4 => VarIntSize::One,
-            5 => VarIntSize::Zero,
-            6 => VarIntSize::Zero,
-            _ => {
-                // TODO: fallible parsing?
-                panic!("Unknown NumberSpec type: t=0x{:02x}, P={}", t, p)
-            }
+            0 => Some(VarIntSize::One),
+            1 => Some(VarIntSize::Two),
+            2 => Some(VarIntSize::Three),
+            3 => Some(VarIntSize::Zero),
+            4 => Some(VarIntSize::One),
+            5 => Some(VarIntSize::Zero),
+            6 => Some(VarIntSize::Zero),
+            _ => None,
         }
     } else {
         // signed 7-bit integer, nothing more to read
-        VarIntSize::Zero
+        Some(VarIntSize::Zero)
     }
 }
 
@@ -56,11 +79,16 @@ pub fn var_int_size(t: u8) -> VarIntSize {
 pub struct Reader<'a> {
     data: &'a [u8],
     pos: usize,
+    error: Option<ReadError>,
 }
 
 impl<'a> Reader<'a> {
     pub fn new(data: &'a [u8], pos: usize) -> Self {
-        Self { data, pos }
+        Self {
+            data,
+            pos,
+            error: None,
+        }
     }
 }
 
@@ -70,25 +98,48 @@ impl<'a> Reader<'a> {
         Self {
             data: self.data,
             pos: position as usize,
+            error: None,
         }
     }
 
+    /// The first error encountered while reading, if any.
+    ///
+    /// After an error the reader is positioned at the end of the data and all the reads return empty slices or zeroes.
+    pub fn error(&self) -> Option<ReadError> {
+        self.error
+    }
+
+    pub(crate) fn set_error(&mut self, error: ReadError) {
+        self.error.get_or_insert(error);
+        self.pos = self.data.len();
+    }
+
     pub fn take(&mut self, size: usize) -> &'a [u8] {
-        let res = &self.data[self.pos..self.pos + size];
+        let Some(res) = self.data.get(self.pos..self.pos + size) else {
+            self.set_error(ReadError::UnexpectedEof {
+                position: self.pos as u32,
+                size,
+            });
+            return &[];
+        };
         self.pos += size;
         res
     }
 
+    fn take_array<const N: usize>(&mut self) -> [u8; N] {
+        self.take(N).try_into().unwrap_or([0; N])
+    }
+
     pub fn take_u8(&mut self) -> u8 {
-        self.take(1)[0]
+        self.take_array::<1>()[0]
     }
 
     pub fn take_u16(&mut self) -> u16 {
-        u16::from_le_bytes(self.take(2).try_into().unwrap())
+        u16::from_le_bytes(self.take_array())
     }
 
     pub fn take_u32(&mut self) -> u32 {
-        u32::from_le_bytes(self.take(4).try_into().unwrap())
+        u32::from_le_bytes(self.take_array())
     }
 
     pub fn take_length(&mut self, kind: LengthKind) -> u16 {
@@ -106,9 +157,19 @@ impl<'a> Reader<'a> {
         match style {
             NumberStyle::U16 => self.take_u16() as u32,
             NumberStyle::VarInt => {
+                let position = self.position();
                 let t = self.take_u8();
 
-                match var_int_size(t) {
+                let Some(size) = try_var_int_size(t) else {
+                    self.set_error(ReadError::UnknownNumberSpec {
+                        position,
+                        t,
+                        p: (t & 0x70) >> 4,
+                    });
+                    return 0;
+                };
+
+                match size {
                     VarIntSize::Zero => u32::from_le_bytes([t, 0, 0, 0]),
                     VarIntSize::One => u32::from_le_bytes([t, self.take_u8(), 0, 0]),
                     VarIntSize::Two => u32::from_le_bytes([t, self.take_u8(), self.take_u8(), 0]),
@@ -180,6 +241,10 @@ impl<'a> Reader<'a> {
         true
     }
 
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
     pub fn position(&self) -> u32 {
         self.pos as u32
     }
@@ -0,0 +1,217 @@
+//! Replacements for characters that can't be encoded in Shift-JIS.
+
+use std::{borrow::Cow, collections::HashMap, io};
+
+use serde::Deserialize;
+use shin_text::CharRemap;
+use shin_versions::StringEncoding;
+
+/// Latin letters with diacritics, grouped by the letter they are replaced with
+const LATIN_LETTERS: &[(&str, &str)] = &[
+    ("ÀÁÂÃÄÅĀĂĄ", "A"),
+    ("àáâãäåāăą", "a"),
+    ("ÇĆĈĊČ", "C"),
+    ("çćĉċč", "c"),
+    ("ĎĐ", "D"),
+    ("ďđ", "d"),
+    ("ÈÉÊËĒĔĖĘĚ", "E"),
+    ("èéêëēĕėęě", "e"),
+    ("ĜĞĠĢ", "G"),
+    ("ĝğġģ", "g"),
+    ("ÌÍÎÏĨĪĬĮİ", "I"),
+    ("ìíîïĩīĭįı", "i"),
+    ("ŁĹĻĽ", "L"),
+    ("łĺļľ", "l"),
+    ("ÑŃŅŇ", "N"),
+    ("ñńņň", "n"),
+    ("ÒÓÔÕÖØŌŎŐ", "O"),
+    ("òóôõöøōŏő", "o"),
+    ("ŔŖŘ", "R"),
+    ("ŕŗř", "r"),
+    ("ŚŜŞŠ", "S"),
+    ("śŝşš", "s"),
+    ("ŢŤ", "T"),
+    ("ţť", "t"),
+    ("ÙÚÛÜŨŪŬŮŰŲ", "U"),
+    ("ùúûüũūŭůűų", "u"),
+    ("ÝŸ", "Y"),
+    ("ýÿ", "y"),
+    ("ŹŻŽ", "Z"),
+    ("źżž", "z"),
+    ("Æ", "AE"),
+    ("æ", "ae"),
+    ("Œ", "OE"),
+    ("œ", "oe"),
+    ("ß", "ss"),
+];
+
+/// Typographic characters commonly produced by word processors
+const PUNCTUATION: &[(char, &str)] = &[
+    ('\u{a0}', " "),
+    ('‚', "'"),
+    ('‛', "'"),
+    ('„', "\""),
+    ('‟', "\""),
+    ('«', "\""),
+    ('»', "\""),
+    ('–', "-"),
+    // the closest thing Shift-JIS has to an em dash
+    ('—', "―"),
+    ('−', "－"),
+    ('〜', "～"),
+    ('•', "・"),
+    ('·', "・"),
+    ('™', "TM"),
+    ('©', "(c)"),
+    ('®', "(R)"),
+    ('€', "EUR"),
+];
+
+/// Curly quotes, which Shift-JIS only has as full-width characters that look out of place in Latin text
+///
+/// Unlike the other replacements, these are applied even though the characters can be encoded.
+const FULL_WIDTH_QUOTES: &[(char, &str)] = &[('“', "\""), ('”', "\""), ('‘', "'"), ('’', "'")];
+
+/// Checks whether the character can be written in the encoding, taking the remapped characters into account
+pub fn is_encodable(c: char, encoding: StringEncoding, char_remap: &CharRemap) -> bool {
+    match encoding {
+        StringEncoding::ShiftJis => char_remap.encode_codepoint(c, false).is_some(),
+        StringEncoding::Utf8 => true,
+    }
+}
+
+/// Checks whether the character should be replaced before writing it in the encoding
+fn needs_substitution(c: char, encoding: StringEncoding, char_remap: &CharRemap) -> bool {
+    !is_encodable(c, encoding, char_remap)
+        || matches!(encoding, StringEncoding::ShiftJis)
+            && FULL_WIDTH_QUOTES.iter().any(|&(quote, _)| quote == c)
+}
+
+#[derive(Deserialize)]
+struct Record {
+    from: char,
+    to: String,
+}
+
+/// A table of replacements for characters that can't be encoded.
+///
+/// Can be extended with a CSV file with `from` and `to` columns.
+pub struct SubstitutionTable {
+    map: HashMap<char, String>,
+}
+
+impl Default for SubstitutionTable {
+    fn default() -> Self {
+        let mut map = HashMap::new();
+        for &(from, to) in LATIN_LETTERS {
+            for c in from.chars() {
+                map.insert(c, to.to_string());
+            }
+        }
+        for &(from, to) in PUNCTUATION.iter().chain(FULL_WIDTH_QUOTES) {
+            map.insert(from, to.to_string());
+        }
+
+        Self { map }
+    }
+}
+
+impl SubstitutionTable {
+    /// Creates a table with the built-in replacements
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds replacements from a CSV file, overriding the existing ones
+    pub fn extend_from_csv<R: io::Read>(&mut self, reader: csv::Reader<R>) -> csv::Result<()> {
+        for record in reader.into_deserialize() {
+            let record: Record = record?;
+            self.map.insert(record.from, record.to);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the replacement for the character, if there is one
+    pub fn suggest(&self, c: char) -> Option<&str> {
+        self.map.get(&c).map(|s| s.as_str())
+    }
+
+    /// Replaces the characters that can't be encoded (and the curly quotes for Shift-JIS), leaving the rest as-is.
+    ///
+    /// Characters without a replacement are kept, so they would still be reported by the linter.
+    pub fn apply<'s>(
+        &self,
+        s: &'s str,
+        encoding: StringEncoding,
+        char_remap: &CharRemap,
+    ) -> Cow<'s, str> {
+        if s.chars()
+            .all(|c| !needs_substitution(c, encoding, char_remap))
+        {
+            return Cow::Borrowed(s);
+        }
+
+        let mut result = String::with_capacity(s.len());
+        for c in s.chars() {
+            match self.suggest(c) {
+                Some(replacement) if needs_substitution(c, encoding, char_remap) => {
+                    result.push_str(replacement)
+                }
+                _ => result.push(c),
+            }
+        }
+
+        Cow::Owned(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use shin_text::CharRemap;
+    use shin_versions::StringEncoding;
+
+    use super::{SubstitutionTable, is_encodable, needs_substitution};
+
+    #[test]
+    fn builtin_replacements_are_encodable() {
+        let table = SubstitutionTable::new();
+        let remap = CharRemap::new();
+        for (&from, to) in &table.map {
+            assert!(
+                needs_substitution(from, StringEncoding::ShiftJis, &remap),
+                "{:?}",
+                from
+            );
+            assert!(
+                to.chars()
+                    .all(|c| is_encodable(c, StringEncoding::ShiftJis, &remap)),
+                "{:?} -> {:?}",
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn apply() {
+        let table = SubstitutionTable::new();
+        let mut remap = CharRemap::new();
+        assert_eq!(
+            table.apply("Café — “naïve” 〜", StringEncoding::ShiftJis, &remap),
+            "Cafe ― \"naive\" ～"
+        );
+        assert_eq!(
+            table.apply("“naive”", StringEncoding::Utf8, &remap),
+            "“naive”"
+        );
+        assert_eq!(table.apply("Café", StringEncoding::Utf8, &remap), "Café");
+
+        // remapped characters are left alone
+        remap.insert('é', 0xf040).unwrap();
+        assert_eq!(
+            table.apply("Café — naïve", StringEncoding::ShiftJis, &remap),
+            "Café ― naive"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! A translation memory, allowing to reuse translations between translation files (and other tools).
+//!
+//! Stored in the TMX 1.4 format.
+
+use std::{collections::HashMap, io};
+
+use quick_xml::{
+    escape::escape,
+    events::{BytesStart, Event},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TranslationMemoryError {
+    #[error("Failed to process the CSV file: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("The CSV file doesn't have a `{0}` column")]
+    MissingColumn(&'static str),
+    #[error("Failed to parse the TMX file: {0}")]
+    Xml(#[from] quick_xml::Error),
+    #[error("Failed to parse the TMX file: {0}")]
+    Attribute(#[from] quick_xml::events::attributes::AttrError),
+}
+
+pub struct TranslationUnit {
+    pub source: String,
+    pub target: String,
+}
+
+#[derive(Default)]
+pub struct TranslationMemory {
+    units: Vec<TranslationUnit>,
+    /// Indices of the units with the given source text, in order of insertion
+    by_source: HashMap<String, Vec<usize>>,
+}
+
+/// Finds the indices of `s` and `translated` columns in the translation CSV
+fn csv_columns<R: io::Read>(
+    reader: &mut csv::Reader<R>,
+) -> Result<(usize, usize), TranslationMemoryError> {
+    let headers = reader.headers()?;
+    let column = |name| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or(TranslationMemoryError::MissingColumn(name))
+    };
+
+    Ok((column("s")?, column("translated")?))
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, TranslationMemoryError> {
+    Ok(match element.try_get_attribute(name)? {
+        Some(attribute) => Some(attribute.unescape_value()?.into_owned()),
+        None => None,
+    })
+}
+
+/// Checks whether the language tags refer to the same language (`ja-JP` matches `ja`)
+fn lang_matches(a: &str, b: &str) -> bool {
+    let primary = |tag: &str| {
+        tag.split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    };
+    primary(a) == primary(b)
+}
+
+impl TranslationMemory {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a translation to the memory, unless the exact same pair is already there
+    pub fn insert(&mut self, source: &str, target: &str) {
+        let indices = self.by_source.entry(source.to_string()).or_default();
+        if indices
+            .iter()
+            .any(|&index| self.units[index].target == target)
+        {
+            return;
+        }
+
+        indices.push(self.units.len());
+        self.units.push(TranslationUnit {
+            source: source.to_string(),
+            target: target.to_string(),
+        });
+    }
+
+    /// Returns the first translation of the source text
+    pub fn lookup(&self, source: &str) -> Option<&str> {
+        let &index = self.by_source.get(source)?.first()?;
+        Some(&self.units[index].target)
+    }
+
+    pub fn units(&self) -> &[TranslationUnit] {
+        &self.units
+    }
+
+    /// Collects the translated strings from a translation CSV file (as produced by `CsvTraceListener`)
+    pub fn from_csv<R: io::Read>(
+        mut reader: csv::Reader<R>,
+    ) -> Result<Self, TranslationMemoryError> {
+        let (s_column, translated_column) = csv_columns(&mut reader)?;
+
+        let mut result = Self::new();
+        for record in reader.records() {
+            let record = record?;
+            if let (Some(s), Some(translated)) =
+                (record.get(s_column), record.get(translated_column))
+                && !translated.is_empty()
+            {
+                result.insert(s, translated);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Fills in the empty `translated` cells of a translation CSV file with translations from the memory.
+    ///
+    /// All the other columns are copied as-is. Returns the number of filled cells.
+    pub fn fill_csv<R: io::Read, W: io::Write>(
+        &self,
+        mut reader: csv::Reader<R>,
+        mut writer: csv::Writer<W>,
+    ) -> Result<usize, TranslationMemoryError> {
+        let (s_column, translated_column) = csv_columns(&mut reader)?;
+
+        writer.write_record(reader.headers()?)?;
+
+        let mut filled = 0;
+        for record in reader.records() {
+            let record = record?;
+            let translation = match (record.get(s_column), record.get(translated_column)) {
+                (Some(s), Some("")) => self.lookup(s),
+                _ => None,
+            };
+
+            match translation {
+                Some(translation) => {
+                    writer.write_record(record.iter().enumerate().map(|(i, field)| {
+                        if i == translated_column {
+                            translation
+                        } else {
+                            field
+                        }
+                    }))?;
+                    filled += 1;
+                }
+                None => writer.write_record(&record)?,
+            }
+        }
+        writer.flush().map_err(csv::Error::from)?;
+
+        Ok(filled)
+    }
+
+    /// Writes the memory as a TMX 1.4 file, with Japanese as the source language
+    pub fn write_tmx<W: io::Write>(&self, mut writer: W, target_lang: &str) -> io::Result<()> {
+        write!(
+            writer,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<tmx version=\"1.4\">\n\
+<header creationtool=\"shin-tl\" creationtoolversion=\"{}\" segtype=\"sentence\" o-tmf=\"shin-tl\" adminlang=\"en\" srclang=\"ja\" datatype=\"plaintext\"/>\n\
+<body>\n",
+            env!("CARGO_PKG_VERSION")
+        )?;
+
+        let target_lang = escape(target_lang);
+        for unit in &self.units {
+            writeln!(
+                writer,
+                "<tu>\n<tuv xml:lang=\"ja\"><seg>{}</seg></tuv>\n<tuv xml:lang=\"{}\"><seg>{}</seg></tuv>\n</tu>",
+                escape(&unit.source),
+                target_lang,
+                escape(&unit.target)
+            )?;
+        }
+
+        writer.write_all(b"</body>\n</tmx>\n")?;
+        writer.flush()
+    }
+
+    /// Parses a TMX file.
+    ///
+    /// The source language is taken from the header (defaulting to Japanese), and the first other variant in each unit is used as a translation.
+    /// Inline markup in the segments is ignored.
+    pub fn parse_tmx(source: &str) -> Result<Self, TranslationMemoryError> {
+        let mut reader = quick_xml::Reader::from_str(source);
+
+        let mut result = Self::new();
+        let mut src_lang = "ja".to_string();
+        // (language, segment) pairs of the current unit
+        let mut variants = Vec::<(String, String)>::new();
+        let mut lang = String::new();
+        // nesting depth inside `<seg>`, 0 if outside
+        let mut seg_depth = 0;
+
+        loop {
+            match reader.read_event()? {
+                Event::Eof => break,
+                Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"header" => {
+                    if let Some(header_lang) = attribute(&e, "srclang")?
+                        && header_lang != "*all*"
+                    {
+                        src_lang = header_lang;
+                    }
+                }
+                Event::Start(e) if e.local_name().as_ref() == b"tu" => {
+                    variants.clear();
+                }
+                Event::End(e) if e.local_name().as_ref() == b"tu" => {
+                    let source = variants
+                        .iter()
+                        .find(|(lang, _)| lang_matches(lang, &src_lang));
+                    let target = variants
+                        .iter()
+                        .find(|(lang, _)| !lang_matches(lang, &src_lang));
+                    if let (Some((_, source)), Some((_, target))) = (source, target) {
+                        result.insert(source, target);
+                    }
+                }
+                Event::Start(e) if e.local_name().as_ref() == b"tuv" => {
+                    // TMX 1.1 used `lang` instead of `xml:lang`
+                    lang = match attribute(&e, "xml:lang")? {
+                        Some(lang) => lang,
+                        None => attribute(&e, "lang")?.unwrap_or_default(),
+                    };
+                }
+                Event::Start(e) if e.local_name().as_ref() == b"seg" => {
+                    seg_depth = 1;
+                    variants.push((lang.clone(), String::new()));
+                }
+                Event::Start(_) if seg_depth > 0 => seg_depth += 1,
+                Event::End(_) if seg_depth > 0 => seg_depth -= 1,
+                Event::Text(text) if seg_depth == 1 => {
+                    if let Some((_, seg)) = variants.last_mut() {
+                        seg.push_str(&text.unescape()?);
+                    }
+                }
+                Event::CData(cdata) if seg_depth == 1 => {
+                    if let Some((_, seg)) = variants.last_mut() {
+                        seg.push_str(&String::from_utf8_lossy(&cdata));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TranslationMemory;
+
+    #[test]
+    fn roundtrip() {
+        let memory = TranslationMemory::from_csv(csv::Reader::from_reader(
+            "index,offset,source,source_subindex,voice,s,translated
+0,0x00000010,msgset,1,,@r「はい」,\"@r\"\"Yes\"\"\"
+1,0x00000020,msgset,2,,@rここは庭だった。,
+2,0x00000030,msgset,3,,@r「はい」,\"@r\"\"Yes\"\"\"
+3,0x00000040,select_choice,0,,いいえ,No & never
+"
+            .as_bytes(),
+        ))
+        .unwrap();
+        assert_eq!(memory.units().len(), 2);
+
+        let mut tmx = Vec::new();
+        memory.write_tmx(&mut tmx, "en").unwrap();
+        let memory = TranslationMemory::parse_tmx(std::str::from_utf8(&tmx).unwrap()).unwrap();
+
+        assert_eq!(memory.lookup("@r「はい」"), Some("@r\"Yes\""));
+        assert_eq!(memory.lookup("いいえ"), Some("No & never"));
+        assert_eq!(memory.lookup("@rここは庭だった。"), None);
+
+        let mut output = Vec::new();
+        let filled = memory
+            .fill_csv(
+                csv::Reader::from_reader(
+                    "index,s,translated\n0,いいえ,\n1,@r「はい」,\"@r\"\"Yep\"\"\"\n".as_bytes(),
+                ),
+                csv::Writer::from_writer(&mut output),
+            )
+            .unwrap();
+        assert_eq!(filled, 1);
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "index,s,translated\n0,いいえ,No & never\n1,@r「はい」,\"@r\"\"Yep\"\"\"\n"
+        );
+    }
+}
@@ -3,7 +3,7 @@
 //! (copied from shin: https://github.com/DCNick3/shin/blob/master/shin-core/src/format/text/mod.rs)
 // Maybe it should be polished and made into a separate library?
 
-use std::io;
+use std::{collections::HashMap, io};
 
 use bumpalo::Bump;
 
@@ -270,15 +270,23 @@ fn map_char_to_sjis(c: char) -> Option<u16> {
 /// Calculate the size of a string in Shift-JIS
 // TODO: this is missing fixup support
 pub fn measure_sjis_zstring(s: &str) -> io::Result<usize> {
+    measure_sjis_zstring_with_remap(s, false, &CharRemap::new())
+}
+
+/// Calculate the size of a string in Shift-JIS, encoding the characters from `remap` with their assigned codepoints
+///
+/// The `fixup` policy is applied the same way as by [`encode_sjis_zstring_with_remap`].
+pub fn measure_sjis_zstring_with_remap<P: FixupEncodePolicy>(
+    s: &str,
+    mut fixup: P,
+    remap: &CharRemap,
+) -> io::Result<usize> {
     let mut result = 0;
 
     for c in s.chars() {
-        let sjis = map_char_to_sjis(c).ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("unmappable char: {} (U+{:04X})", c, c as u32),
-            )
-        })?;
+        let sjis = remap
+            .encode_codepoint(c, fixup.should_fixup())
+            .ok_or_else(|| unmappable_char_error(c))?;
 
         match sjis {
             0x00..=0xff => {
@@ -299,6 +307,100 @@ pub fn measure_sjis_zstring(s: &str) -> io::Result<usize> {
     Ok(result + 1)
 }
 
+fn unmappable_char_error(c: char) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unmappable char: {} (U+{:04X})", c, c as u32),
+    )
+}
+
+/// Checks whether the double-byte codepoint doesn't correspond to any real character
+///
+/// Those are either not defined (decoded as `・`) or from the user-defined area (decoded as private use characters).
+fn is_unused_codepoint(first: u8, second: u8) -> bool {
+    match decode_double_sjis_char(first, second) {
+        '\0' | '\u{e000}'..='\u{f8ff}' => true,
+        '・' => (first, second) != (0x81, 0x45),
+        _ => false,
+    }
+}
+
+/// Assigns characters that Shift-JIS can't represent (like `é`) to unused Shift-JIS codepoints.
+///
+/// The game font needs to have glyphs for those characters drawn in the corresponding slots.
+#[derive(Debug, Default, Clone)]
+pub struct CharRemap {
+    map: HashMap<char, u16>,
+}
+
+impl CharRemap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Assigns a codepoint to the character.
+    ///
+    /// The codepoint must be a double-byte Shift-JIS codepoint in `F000`-`F0FF` or `E000`-`EAFF` that doesn't correspond to any character, and the character must not be representable in Shift-JIS.
+    pub fn insert(&mut self, c: char, codepoint: u16) -> io::Result<()> {
+        let error = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "can't remap {} (U+{:04X}) to 0x{:04x}: {}",
+                    c, c as u32, codepoint, message
+                ),
+            )
+        };
+
+        if !matches!(codepoint, 0xf000..=0xf0ff | 0xe000..=0xeaff) {
+            return Err(error("only F000-F0FF and E000-EAFF can be remapped"));
+        }
+        let [first, second] = codepoint.to_be_bytes();
+        if !is_extended(first) || !matches!(second, 0x40..=0x7e | 0x80..=0xfc) {
+            return Err(error("not a valid double-byte Shift-JIS codepoint"));
+        }
+        if !is_unused_codepoint(first, second) {
+            return Err(error("the codepoint is already used by another character"));
+        }
+        if map_char_to_sjis(c).is_some() {
+            return Err(error("the character can already be encoded in Shift-JIS"));
+        }
+        if self
+            .map
+            .iter()
+            .any(|(&other, &other_codepoint)| other_codepoint == codepoint && other != c)
+        {
+            return Err(error(
+                "the codepoint is already assigned to another character",
+            ));
+        }
+
+        self.map.insert(c, codepoint);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the codepoint assigned to the character, if any
+    pub fn get(&self, c: char) -> Option<u16> {
+        self.map.get(&c).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (char, u16)> + '_ {
+        self.map.iter().map(|(&c, &codepoint)| (c, codepoint))
+    }
+
+    /// Encodes a character, checking the remapped characters first
+    pub fn encode_codepoint(&self, c: char, fixup: bool) -> Option<u16> {
+        match self.get(c) {
+            Some(codepoint) => Some(codepoint),
+            None => encode_sjis_codepoint(c, fixup),
+        }
+    }
+}
+
 pub trait FixupEncodePolicy {
     fn should_fixup(&mut self) -> bool;
 }
@@ -352,21 +454,35 @@ pub fn encode_sjis_codepoint(codepoint: char, fixup: bool) -> Option<u16> {
 /// This is necessary because when fixup encoding is in use there is a redundancy: the hiragana characters can either be encoded as double-byte Shift-JIS characters or as single-byte Shift-JIS characters in the range 0xA0-0xDF.
 /// The authoring tools are somewhat sloppy with this and do end up using both encodings in the same string, so to byte-for-byte reproducibility we need to expose this toggle on character level.
 pub fn encode_sjis_zstring<'bump, P: FixupEncodePolicy>(
+    bump: &'bump Bump,
+    s: &str,
+    fixup: P,
+) -> io::Result<&'bump [u8]> {
+    encode_sjis_zstring_with_remap(bump, s, fixup, &CharRemap::new())
+}
+
+/// Encode a UTF-8 string into a zero-terminated Shift-JIS string, encoding the characters from `remap` with their assigned codepoints
+///
+/// See [`encode_sjis_zstring`] for the description of the `fixup` argument.
+pub fn encode_sjis_zstring_with_remap<'bump, P: FixupEncodePolicy>(
     bump: &'bump Bump,
     s: &str,
     mut fixup: P,
+    remap: &CharRemap,
 ) -> io::Result<&'bump [u8]> {
     let mut output = bumpalo::collections::Vec::with_capacity_in(s.len(), bump);
 
     for c in s.chars() {
+        if let Some(sjis) = remap.get(c) {
+            // the fixup policy still expects to be queried for every character
+            fixup.should_fixup();
+            output.extend_from_slice(&sjis.to_be_bytes());
+            continue;
+        }
+
         // NOTE: the game impl emits ※ (81A6 in Shift-JIS) for unmappable chars
         // we are more conservative and just error out
-        let mut sjis = map_char_to_sjis(c).ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("unmappable char: {} (U+{:04X})", c, c as u32),
-            )
-        })?;
+        let mut sjis = map_char_to_sjis(c).ok_or_else(|| unmappable_char_error(c))?;
 
         // apply fixup
         // TODO: linearly looking through 64 entries might be too much..
@@ -457,4 +573,31 @@ mod tests {
         let encoded = encode_sjis_zstring(&bump, &s, false).unwrap();
         assert_eq!(encoded, b"\x82\xa0\x82\xa2\x82\xa4\x82\xa6\x82\xa8\x00");
     }
+
+    #[test]
+    fn test_remap() {
+        let bump = Bump::new();
+        let mut remap = CharRemap::new();
+        remap.insert('é', 0xf040).unwrap();
+        // already used by `あ`
+        assert!(remap.insert('è', 0x82a0).is_err());
+        // representable in Shift-JIS
+        assert!(remap.insert('あ', 0xf041).is_err());
+        // outside of the remappable ranges
+        assert!(remap.insert('è', 0xf140).is_err());
+
+        let encoded = encode_sjis_zstring_with_remap(&bump, "Caféあ", false, &remap).unwrap();
+        assert_eq!(encoded, b"Caf\xf0\x40\x82\xa0\x00");
+        assert_eq!(
+            measure_sjis_zstring_with_remap("Caféあ", false, &remap).unwrap(),
+            encoded.len()
+        );
+        let encoded = encode_sjis_zstring_with_remap(&bump, "Caféあ", true, &remap).unwrap();
+        assert_eq!(encoded, b"Caf\xf0\x40\xb1\x00");
+        assert_eq!(
+            measure_sjis_zstring_with_remap("Caféあ", true, &remap).unwrap(),
+            encoded.len()
+        );
+        assert!(encode_sjis_zstring(&bump, "Café", false).is_err());
+    }
 }
@@ -0,0 +1,97 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Subcommand;
+use serde::Deserialize;
+use shin_text::CharRemap;
+use tracing::info;
+
+/// Modify .fnt files used by shin-based games
+///
+/// For more usage documentation see https://github.com/DCNick3/shin-translation-tools
+#[derive(Subcommand)]
+pub enum Command {
+    /// Add glyphs for the characters from a character remap table to a font
+    ///
+    /// Only FNT0 fonts (used by older Shift-JIS games) are supported.
+    Remap {
+        /// Path to the original font file
+        font_file: Utf8PathBuf,
+        /// Path to the character remap CSV file (with `char` and `sjis` columns)
+        char_remap: Utf8PathBuf,
+        /// Directory with the glyph images, named after the code of the character (like `U+00E9.png` for `é`)
+        ///
+        /// The images should be as tall as the font line, and the glyph width is taken from the image width.
+        glyph_dir: Utf8PathBuf,
+        /// Path to the output font file
+        output: Utf8PathBuf,
+    },
+}
+
+#[derive(Deserialize)]
+struct CharRemapRecord {
+    char: char,
+    sjis: String,
+}
+
+/// Reads a character remap table from a CSV file with `char` and `sjis` columns. The codepoints are in hex (like `F040` or `0xF040`)
+pub fn read_char_remap(path: &Utf8Path) -> CharRemap {
+    let reader = csv::Reader::from_path(path).expect("Opening the character remap CSV file failed");
+
+    let mut char_remap = CharRemap::new();
+    for record in reader.into_deserialize() {
+        let record: CharRemapRecord = record.expect("Reading the character remap CSV file failed");
+        let sjis = record.sjis.trim();
+        let sjis = sjis
+            .strip_prefix("0x")
+            .or_else(|| sjis.strip_prefix("0X"))
+            .unwrap_or(sjis);
+        let codepoint = u16::from_str_radix(sjis, 16).unwrap_or_else(|_| {
+            panic!(
+                "Invalid Shift-JIS codepoint `{}` in the character remap CSV file",
+                record.sjis
+            )
+        });
+        char_remap
+            .insert(record.char, codepoint)
+            .expect("Invalid character remap");
+    }
+
+    char_remap
+}
+
+impl Command {
+    pub fn run(self) {
+        match self {
+            Command::Remap {
+                font_file,
+                char_remap,
+                glyph_dir,
+                output,
+            } => {
+                let char_remap = read_char_remap(&char_remap);
+                let font = std::fs::read(font_file).expect("Reading the font file failed");
+
+                let mut glyphs = char_remap
+                    .iter()
+                    .map(|(c, codepoint)| {
+                        let path = glyph_dir.join(format!("U+{:04X}.png", c as u32));
+                        let image = image::open(&path)
+                            .unwrap_or_else(|e| panic!("Reading the glyph {} failed: {}", path, e))
+                            .into_luma8();
+                        (codepoint, image)
+                    })
+                    .collect::<Vec<_>>();
+                // keep the output deterministic
+                glyphs.sort_by_key(|&(codepoint, _)| codepoint);
+
+                let font = shin_font::font0_add_glyphs(
+                    &font,
+                    glyphs.iter().map(|(codepoint, image)| (*codepoint, image)),
+                )
+                .expect("Adding the glyphs to the font failed");
+                std::fs::write(output, font).expect("Writing the output file failed");
+
+                info!("Added {} glyphs", glyphs.len());
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+mod font;
 mod rom;
 mod snr;
 
@@ -19,6 +20,8 @@ enum Command {
     Snr(snr::Command),
     #[clap(subcommand)]
     Rom(rom::Command),
+    #[clap(subcommand)]
+    Font(font::Command),
     /// Generate shell complete script for the given shell
     GenerateCompletion {
         /// The shell to generate the completion for
@@ -52,6 +55,7 @@ fn main() {
     match command {
         Command::Snr(action) => action.run(),
         Command::Rom(action) => action.run(),
+        Command::Font(action) => action.run(),
         Command::GenerateCompletion { shell } => {
             info!("Generating completion file for {:?}...", shell);
 
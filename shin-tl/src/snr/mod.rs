@@ -1,40 +1,117 @@
 use std::{
     fs::File,
-    io::{BufWriter, Read, Write},
+    io::{BufWriter, Cursor, Read, Write},
 };
 
-use camino::Utf8PathBuf;
-use clap::{Args, Subcommand};
-use shin_font::FontMetrics;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args, CommandFactory, Subcommand, error::ErrorKind};
+use regex::RegexBuilder;
+use shin_font::{FontGlyphs, FontMetrics};
 use shin_snr::{
-    layout::{layouter::GameLayoutInfo, message_parser::MessageReflowMode},
+    glossary::Glossary,
+    header::SnrHeader,
+    layout::{
+        hyphenate::Hyphenator,
+        layouter::{ChoiceBoxInfo, GameLayoutInfo, find_missing_glyph},
+        message_parser::{
+            self, MessageReflowMode,
+            lint::{OverflowCheck, diagnostics::LineReport},
+        },
+        preview::place_message,
+        reflow::{ChoiceFitPolicy, ReflowAlgorithm, ReflowParams},
+    },
     location_painter,
     operation::schema::{ENGINE_SCHEMAS, EngineSchema},
     reactor::{
+        AnyStringSource, Reactor, StringSource,
         dump_bin::DumpBinReactor,
         offset_validator::OffsetValidatorReactor,
         react_with,
         rewrite::{
-            CsvData, CsvRewriter, NoopRewriter, RewriteReactor, StringReplacementMode,
-            StringRewriter,
+            CsvData, CsvRewriter, LintOptions, NoopRewriter, PoData, PoRewriter, RewriteReactor,
+            StringReplacementMode, StringRewriter, TranslationStats, XliffData, XliffRewriter,
+            migrate,
         },
+        speaker_names::SpeakerNames,
         string_roundrip_validator::StringRoundtripValidatorReactor,
-        trace::{ConsoleTraceListener, CsvTraceListener, StringTraceReactor},
+        trace::{
+            ConsoleTraceListener, CsvTraceListener, PoTraceListener, StringTraceReactor,
+            TracedString, XliffTraceListener,
+        },
     },
     reader::Reader,
+    substitution::SubstitutionTable,
+    translation_memory::TranslationMemory,
 };
-use shin_versions::{MessageCommandStyle, ShinVersion};
-use tracing::{error, info};
+use shin_text::CharRemap;
+use shin_versions::{MessageCommandStyle, ShinVersion, StringEncoding};
+use tracing::{error, info, warn};
+
+use crate::snr::{
+    preview::{PreviewRenderer, message_pages},
+    version::EngineVersionSpecifier,
+};
+
+mod preview;
+mod version;
 
 #[derive(Args, Clone)]
 pub struct CommonArgs {
-    /// Version of the engine the SNR file is from
-    #[clap(value_enum)]
-    engine_version: ShinVersion,
+    /// Version of the engine the SNR file is from, or `auto` to detect it
+    #[clap(value_parser = EngineVersionSpecifier::parser())]
+    engine_version: EngineVersionSpecifier,
     /// Path to the SNR file
     snr_file: Utf8PathBuf,
 }
 
+/// Overrides of the message box layout used for reflowing and checking the messages
+///
+/// Only the layout of `higurashi-sui` was measured against the game, for the other versions it has to be specified.
+#[derive(Args, Clone)]
+pub struct LayoutArgs {
+    /// Width of the text in the message box, in font units multiplied by --layout-scale
+    #[clap(long)]
+    layout_width: Option<f32>,
+    /// How many lines fit into the message box, not counting the speaker name
+    #[clap(long)]
+    layout_max_lines: Option<usize>,
+    /// Factor the glyphs of the font are scaled by when displayed
+    #[clap(long)]
+    layout_scale: Option<f32>,
+    /// Close the furigana left open at the end of the line implicitly
+    #[clap(long)]
+    layout_auto_close_furigana: bool,
+}
+
+impl LayoutArgs {
+    /// Returns the message box layout of the version with the overrides applied. Exits if the version's layout is not known and wasn't fully specified.
+    pub fn resolve(&self, version: ShinVersion) -> GameLayoutInfo {
+        let measured = GameLayoutInfo::for_version(version);
+
+        let (Some(width), Some(max_lines), Some(overall_scale_factor)) = (
+            self.layout_width.or(measured.map(|l| l.width)),
+            self.layout_max_lines.or(measured.map(|l| l.max_lines)),
+            self.layout_scale
+                .or(measured.map(|l| l.overall_scale_factor)),
+        ) else {
+            error!(
+                "The message box layout of {} wasn't measured yet, specify it with --layout-width, --layout-max-lines and --layout-scale",
+                version.name()
+            );
+            std::process::exit(1);
+        };
+
+        GameLayoutInfo {
+            default_scale: measured.map_or(1.0, |l| l.default_scale),
+            overall_scale_factor,
+            width,
+            max_lines,
+            auto_close_furigana: self.layout_auto_close_furigana
+                || measured.is_some_and(|l| l.auto_close_furigana),
+        }
+    }
+}
+
 /// Determines the policy on transforming message commands style
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq, clap::ValueEnum)]
 pub enum MessageStylePolicy {
@@ -54,6 +131,51 @@ impl MessageStylePolicy {
     }
 }
 
+/// Format of the file with strings for translation
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, clap::ValueEnum)]
+pub enum TranslationFormat {
+    /// A CSV file, see README for the description of the columns
+    #[default]
+    Csv,
+    /// A gettext PO file, suitable for tools like Poedit or Weblate
+    Po,
+    /// An XLIFF 2.0 file, suitable for CAT tools. Message commands are converted to placeholders
+    Xliff,
+}
+
+impl TranslationFormat {
+    /// Determines the format by the file extension, defaulting to CSV
+    pub fn from_path(path: &Utf8Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("po") => TranslationFormat::Po,
+            Some(extension)
+                if extension.eq_ignore_ascii_case("xlf")
+                    || extension.eq_ignore_ascii_case("xliff") =>
+            {
+                TranslationFormat::Xliff
+            }
+            _ => TranslationFormat::Csv,
+        }
+    }
+
+    /// Exits with an argument conflict error if any of the options only supported for CSV files is used with another format
+    ///
+    /// The format can be inferred from the file extension, so clap can't check this by itself.
+    fn check_csv_only(self, options: &[(&str, bool)]) {
+        if self == TranslationFormat::Csv {
+            return;
+        }
+        if let Some((name, _)) = options.iter().find(|(_, used)| *used) {
+            crate::Command::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("{} is only supported for CSV files", name),
+                )
+                .exit();
+        }
+    }
+}
+
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq, clap::ValueEnum)]
 pub enum CliMessageReflowMode {
     /// Do not attempt to reflow text to introduce additional line breaks.
@@ -63,42 +185,45 @@ pub enum CliMessageReflowMode {
     ///
     /// This uses UAX#14 to identify possible line break points.
     Greedy,
+    /// Same as `greedy`, but messages that don't fit into the message box are split into several messages, each waiting for a click.
+    ///
+    /// The messages with a voice are not split, as the game finds the voice by the message id, which all the pages share.
+    GreedySplit,
+    /// Choose the line breaks across the whole paragraph to make the lines as even as possible, avoiding short last lines.
+    ///
+    /// This uses the same break points as `greedy`.
+    Optimal,
 }
 
 impl CliMessageReflowMode {
-    pub fn materialize(
+    pub fn materialize<'a>(
         self,
         shin_version: ShinVersion,
-        font: Option<&FontMetrics>,
-    ) -> MessageReflowMode<'_> {
-        match self {
-            CliMessageReflowMode::NoReflow => {
-                MessageReflowMode::NoReflow
-            }
-            CliMessageReflowMode::Greedy => {
-                MessageReflowMode::Greedy {
-                    metrics: &font.expect("Message reflowing requires a corresponding font file (supplied with --font-file option)"),
-                    layout: GameLayoutInfo::for_version(shin_version).expect("This engine version doesn't support text reflowing yet"),
-                }
+        layout: &LayoutArgs,
+        font: Option<&'a FontMetrics>,
+        hyphenator: Option<&'a Hyphenator>,
+    ) -> MessageReflowMode<'a> {
+        let params = || {
+            ReflowParams {
+                metrics: font.expect("Message reflowing requires a corresponding font file (supplied with --font-file option)"),
+                layout: layout.resolve(shin_version),
+                hyphenator,
             }
+        };
+
+        match self {
+            CliMessageReflowMode::NoReflow => MessageReflowMode::NoReflow,
+            CliMessageReflowMode::Greedy => MessageReflowMode::Greedy(params()),
+            CliMessageReflowMode::GreedySplit => MessageReflowMode::GreedySplit(params()),
+            CliMessageReflowMode::Optimal => MessageReflowMode::Optimal(params()),
         }
     }
 }
 
-/// Rewrite shin SNR file with translated strings
-///
-/// TL;DR:
-///
-/// 1. shin-tl snr read <engine-version> <main.snr> <strings.csv>
-///
-/// 2. Translate strings in strings.csv
-///
-/// 3. shin-tl snr rewrite <engine-version> <main.snr> <strings.csv> <main_translated.snr>
-///
-/// For more usage documentation see https://github.com/DCNick3/shin-translation-tools/blob/master/README.md
+/// Commands working on an SNR file of a known engine version
 #[derive(Subcommand)]
-pub enum Command {
-    /// Read strings from an SNR file to a CSV file for translation
+pub enum SnrCommand {
+    /// Read strings from an SNR file to a CSV (or PO, or XLIFF) file for translation
     Read {
         #[clap(flatten)]
         common: CommonArgs,
@@ -107,7 +232,22 @@ pub enum Command {
         /// NOTE: make sure that the same value of this option is used in `shin-tl snr rewrite`
         #[clap(long, value_enum, default_value_t)]
         message_style: MessageStylePolicy,
-        /// Path to the output CSV file
+        /// Split speaker names off the messages into a separate `speaker` column, collecting them into the specified CSV file
+        ///
+        /// If the file already exists, the translations in it are preserved. Pass the same file to `shin-tl snr rewrite`.
+        #[clap(long)]
+        speaker_names: Option<Utf8PathBuf>,
+        /// Add a `repeat_of` column with the index of the first string with the same text, so that repeated lines can be grouped
+        ///
+        /// Combine with `--propagate-repeats` in `shin-tl snr rewrite` to only translate the first occurrence.
+        #[clap(long)]
+        mark_repeats: bool,
+        /// Format of the output file
+        ///
+        /// If not specified, it is determined by the extension of the output file (`.po` for PO, `.xlf` or `.xliff` for XLIFF, CSV otherwise)
+        #[clap(long, value_enum)]
+        format: Option<TranslationFormat>,
+        /// Path to the output CSV (or PO, or XLIFF) file
         output: Utf8PathBuf,
     },
     /// Read strings from an SNR file and dump them to the console
@@ -141,7 +281,7 @@ pub enum Command {
         font_file: Option<Utf8PathBuf>,
         layout_dump_file: Option<Utf8PathBuf>,
     },
-    /// Rewrite an SNR file to use translated strings from a CSV file
+    /// Rewrite an SNR file to use translated strings from a CSV (or PO, or XLIFF) file
     Rewrite {
         #[clap(flatten)]
         common: CommonArgs,
@@ -159,32 +299,300 @@ pub enum Command {
         /// Reflow the text to insert line breaks for line wrapping
         #[clap(long, value_enum, default_value_t)]
         reflow_mode: CliMessageReflowMode,
-        /// Path to the font file for --reflow-mode option
+        /// Path to the font file for --reflow-mode and --check-overflow options
         #[clap(long, value_enum)]
         font_file: Option<Utf8PathBuf>,
-        /// Path to the CSV file with translations
+        #[clap(flatten)]
+        layout: LayoutArgs,
+        /// Lay out the translated strings and report the messages with more lines than the message box fits, and the choices wider than the choice box
+        ///
+        /// The messages are reflowed before the check, the same way --reflow-mode does it (or as with --reflow-mode greedy if the messages are not reflowed).
+        #[clap(long, requires = "font_file", conflicts_with = "no_lint")]
+        check_overflow: bool,
+        /// Break long words with a hyphen when reflowing the text, using the hyphenation patterns for the specified language (like `en-us`, `de-1996` or `ru`)
+        ///
+        /// Requires --reflow-mode, and is also used by --check-overflow and --choice-fit.
+        #[clap(long, requires = "reflow_mode")]
+        hyphenate: Option<String>,
+        /// Make the translated choices that are wider than the choice box fit into it
+        ///
+        /// Only the SELECT choices are fitted, as the sizes of the other boxes outside of the message box are not known. The game has to interpret the layout commands in the choices for this to work. The choices that still don't fit are reported by --check-overflow.
+        #[clap(long, value_enum, default_value_t, requires_all = ["font_file", "choice_width"])]
+        choice_fit: ChoiceFitPolicy,
+        /// Width of the text in the choice buttons, in the same units as the message box width
+        ///
+        /// It wasn't measured for any of the games, so it has to be specified for --choice-fit, and for --check-overflow to check the choices.
+        #[clap(long)]
+        choice_width: Option<f32>,
+        /// How many lines fit into the choice buttons
+        #[clap(long, default_value_t = 1)]
+        choice_max_lines: usize,
+        /// Path to the speaker names CSV file created by `shin-tl snr read --speaker-names`
+        ///
+        /// Translated names from this file are used for messages with a `speaker` column.
+        #[clap(long)]
+        speaker_names: Option<Utf8PathBuf>,
+        /// Fill untranslated strings with the translation of another string with the same text
+        #[clap(long)]
+        propagate_repeats: bool,
+        /// Replace characters that can't be encoded for the engine with similar ones (like `é` with `e`)
+        #[clap(long)]
+        auto_substitute: bool,
+        /// Path to a CSV file with `from` and `to` columns, adding replacements for `--auto-substitute` (and lint suggestions)
+        #[clap(long)]
+        substitution_table: Option<Utf8PathBuf>,
+        /// Path to a CSV file with `char` and `sjis` columns, assigning unused Shift-JIS codepoints to characters the encoding doesn't have
+        ///
+        /// The font has to be patched with `shin-tl font remap` to display them.
+        #[clap(long)]
+        char_remap: Option<Utf8PathBuf>,
+        /// Path to a glossary CSV file with `source`, `translation` and optional `case_sensitive` columns
+        ///
+        /// Lint warns about translated strings whose original contains a glossary term, but that don't use its translation. The glossary is only checked by lint, so it can't be used with --no-lint.
+        #[clap(long, conflicts_with = "no_lint")]
+        glossary: Option<Utf8PathBuf>,
+        /// Format of the translations file
+        ///
+        /// If not specified, it is determined by the extension of the translations file (`.po` for PO, `.xlf` or `.xliff` for XLIFF, CSV otherwise)
+        #[clap(long, value_enum)]
+        format: Option<TranslationFormat>,
+        /// Path to the CSV (or PO, or XLIFF) file with translations
         ///
         /// A template can be created with `shin-tl snr read`
         translations: Utf8PathBuf,
         /// Path to the output SNR file
         output: Utf8PathBuf,
     },
+    /// Render the translated messages to PNG images, laid out the way the game would display them
+    ///
+    /// Each message is drawn with the glyphs from the font file over the message box of the engine version, with the text colors and furigana. The lines that don't fit into the message box are drawn over a red background.
+    ///
+    /// Only the older (PSVita) FNT fonts are supported.
+    Preview {
+        #[clap(flatten)]
+        common: CommonArgs,
+        /// Change the way message commands are transformed
+        ///
+        /// NOTE: make sure that the same value of this option was used when creating the CSV file
+        #[clap(long, value_enum, default_value_t)]
+        message_style: MessageStylePolicy,
+        /// Reflow the text before rendering it, the same way `shin-tl snr rewrite` does it
+        #[clap(long, value_enum, default_value_t)]
+        reflow_mode: CliMessageReflowMode,
+        /// Break long words with a hyphen when reflowing the text, the same way `shin-tl snr rewrite` does it
+        #[clap(long, requires = "reflow_mode")]
+        hyphenate: Option<String>,
+        /// Path to the font file used for the messages
+        #[clap(long)]
+        font_file: Utf8PathBuf,
+        #[clap(flatten)]
+        layout: LayoutArgs,
+        /// Path to a CSV file with `char` and `sjis` columns, assigning unused Shift-JIS codepoints to characters the encoding doesn't have
+        ///
+        /// The font has to be patched with `shin-tl font remap` to display them, the same way as for `shin-tl snr rewrite`.
+        #[clap(long)]
+        char_remap: Option<Utf8PathBuf>,
+        /// Path to the speaker names CSV file created by `shin-tl snr read --speaker-names`
+        ///
+        /// Translated names from this file are used for messages with a `speaker` column, the same way `shin-tl snr rewrite` does it.
+        #[clap(long)]
+        speaker_names: Option<Utf8PathBuf>,
+        /// Path to the CSV file with translations
+        translations: Utf8PathBuf,
+        /// Path to the directory to write the images to. The images are named by the index of the string in the CSV file
+        #[clap(long)]
+        out: Utf8PathBuf,
+    },
+    /// Carry translations over from a CSV file made for a different version of the SNR file (for example, before a game patch)
+    ///
+    /// Strings are matched by their text, kind, message id and neighboring strings. Ambiguous and unmatched strings are reported.
+    Migrate {
+        #[clap(flatten)]
+        common: CommonArgs,
+        /// Change the way message commands are transformed
+        ///
+        /// NOTE: make sure that the same value of this option was used when creating the old CSV file
+        #[clap(long, value_enum, default_value_t)]
+        message_style: MessageStylePolicy,
+        /// Path to the CSV file with translations for the old SNR file
+        old_translations: Utf8PathBuf,
+        /// Path to the output CSV file for the new SNR file
+        output: Utf8PathBuf,
+    },
+    /// Report the translation progress and text statistics of a CSV (or PO, or XLIFF) file
+    ///
+    /// Strings are counted by their kind, by message id ranges and by scene (each `SAVEINFO` starting a new one).
+    Stats {
+        #[clap(flatten)]
+        common: CommonArgs,
+        /// Change the way message commands are transformed
+        ///
+        /// NOTE: make sure that the same value of this option was used when creating the CSV file
+        #[clap(long, value_enum, default_value_t)]
+        message_style: MessageStylePolicy,
+        /// Size of the message id ranges
+        #[clap(long, default_value_t = 1000)]
+        id_range_size: u32,
+        /// Print the statistics as JSON
+        #[clap(long)]
+        json: bool,
+        /// Format of the translations file
+        ///
+        /// If not specified, it is determined by the extension of the translations file (`.po` for PO, `.xlf` or `.xliff` for XLIFF, CSV otherwise)
+        #[clap(long, value_enum)]
+        format: Option<TranslationFormat>,
+        /// Path to the CSV (or PO, or XLIFF) file with translations
+        translations: Utf8PathBuf,
+    },
+    /// Print the SNR header and the asset tables it contains (pictures, BGM, voices, etc.)
+    Header {
+        #[clap(flatten)]
+        common: CommonArgs,
+        /// Print the header as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Search for strings in an SNR file with a regular expression
+    ///
+    /// Prints the index, offset and source of each matching string, like in the CSV file.
+    Grep {
+        #[clap(flatten)]
+        common: CommonArgs,
+        /// Change the way message commands are transformed
+        #[clap(long, value_enum, default_value_t)]
+        message_style: MessageStylePolicy,
+        /// Search in the text without layout commands
+        #[clap(long)]
+        strip_commands: bool,
+        /// Match case-insensitively
+        #[clap(short, long)]
+        ignore_case: bool,
+        /// Number of strings to print before and after each match
+        #[clap(short = 'C', long, default_value_t = 0)]
+        context: usize,
+        /// Search in the `translated` column of this CSV file instead of the original strings
+        #[clap(long)]
+        translations: Option<Utf8PathBuf>,
+        /// Path to the speaker names CSV file created by `shin-tl snr read --speaker-names`
+        ///
+        /// The translated names are joined with the translations, so that the messages are searched as they are displayed in the game. Without it, the speaker names of the translations are not searched.
+        #[clap(long, requires = "translations")]
+        speaker_names: Option<Utf8PathBuf>,
+        /// The regular expression to search for
+        pattern: String,
+    },
 }
 
-fn rewrite_snr<'a, R, O>(
-    snr_file: &[u8],
-    reader: Reader,
-    code_offset: u32,
-    schema: &EngineSchema,
+/// Rewrite shin SNR file with translated strings
+///
+/// TL;DR:
+///
+/// 1. shin-tl snr read <engine-version> <main.snr> <strings.csv>
+///
+/// 2. Translate strings in strings.csv
+///
+/// 3. shin-tl snr rewrite <engine-version> <main.snr> <strings.csv> <main_translated.snr>
+///
+/// For more usage documentation see https://github.com/DCNick3/shin-translation-tools/blob/master/README.md
+#[derive(Subcommand)]
+pub enum Command {
+    #[clap(flatten)]
+    Snr(Box<SnrCommand>),
+    /// Detect the engine version of an SNR file
+    ///
+    /// Tries to parse the file with each of the known versions, checking that it parses to the end, that the jumps are valid and that the strings can be decoded.
+    Detect {
+        /// Path to the SNR file
+        snr_file: Utf8PathBuf,
+    },
+    /// Export translated strings from a CSV file to a TMX translation memory
+    TmExport {
+        /// Language of the translations, written to the TMX file
+        #[clap(long, default_value = "en")]
+        target_lang: String,
+        /// Path to the CSV file with translations
+        translations: Utf8PathBuf,
+        /// Path to the output TMX file
+        output: Utf8PathBuf,
+    },
+    /// Fill untranslated strings in a CSV file from a TMX translation memory
+    ///
+    /// Only strings with exactly the same text are filled. Existing translations are kept.
+    TmImport {
+        /// Path to the TMX file
+        memory: Utf8PathBuf,
+        /// Path to the CSV file with translations
+        translations: Utf8PathBuf,
+        /// Path to the output CSV file. Can be the same as the input file
+        output: Utf8PathBuf,
+    },
+}
+
+/// Reads the `--char-remap` table, exiting if the game's encoding can't use it
+fn read_char_remap(path: Option<&Utf8Path>, version: ShinVersion) -> CharRemap {
+    let char_remap = path.map(crate::font::read_char_remap).unwrap_or_default();
+    if !char_remap.is_empty() && !matches!(version.string_encoding(), StringEncoding::ShiftJis) {
+        error!("--char-remap is only supported for Shift-JIS games");
+        std::process::exit(1);
+    }
+    char_remap
+}
+
+/// Exits if the font can't display the remapped characters
+fn check_char_remap_font(char_remap: &CharRemap, font: &FontMetrics) {
+    if !char_remap.is_empty() && !font.supports_char_remap() {
+        error!(
+            "--char-remap requires a font in the older FNT0 format, the newer fonts can't display the remapped characters"
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Checks the SNR file magic and returns the offset of the code section
+fn code_offset(snr_file: &[u8]) -> u32 {
+    assert_eq!(&snr_file[0..4], b"SNR ", "SNR file magic mismatch");
+    u32::from_le_bytes(snr_file[0x20..0x24].try_into().unwrap())
+}
+
+/// Parses the SNR code, feeding it to the reactor
+///
+/// Exits with the parse error if the file can't be parsed, which usually means that the engine version is wrong.
+fn react_or_exit<R: Reactor>(reader: Reader, schema: &EngineSchema, reactor: &mut R) {
+    if let Err(error) = react_with(reader, schema, reactor) {
+        error!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Describes how [`rewrite_snr`] encodes the strings
+struct RewriteOptions<'a> {
     version: ShinVersion,
     user_style: MessageCommandStyle,
     reflow_mode: MessageReflowMode<'a>,
+    choice_fit: Option<(ReflowParams<'a>, ChoiceBoxInfo, ChoiceFitPolicy)>,
+    char_remap: CharRemap,
+}
+
+fn rewrite_snr<R, O>(
+    snr_file: &[u8],
+    reader: Reader,
+    header: &SnrHeader,
+    schema: &EngineSchema,
+    options: RewriteOptions,
     rewriter: R,
     output: &mut O,
 ) where
     R: StringRewriter,
     O: Write,
 {
+    let RewriteOptions {
+        version,
+        user_style,
+        reflow_mode,
+        choice_fit,
+        char_remap,
+    } = options;
+
     let mut reactor = RewriteReactor::new(
         version.number_style(),
         version.message_command_style(),
@@ -193,9 +601,26 @@ fn rewrite_snr<'a, R, O>(
         reflow_mode,
         version.string_policy(),
         rewriter,
-        code_offset,
-    );
-    react_with(reader.clone(), schema, &mut reactor);
+        header.code_offset,
+    )
+    .with_char_remap(char_remap.clone());
+    if let Some((params, choice_box, policy)) = choice_fit {
+        reactor = reactor.with_choice_fit(params, choice_box, policy);
+    }
+    react_or_exit(reader.clone(), schema, &mut reactor);
+
+    // the header strings can only be rewritten after the code, as they are numbered after the code strings
+    let mut header = header.clone();
+    reactor.rewrite_header(&mut header);
+    let header_bytes = header
+        .encode(snr_file, version.string_encoding(), &char_remap)
+        .expect("Encoding the SNR header failed");
+    let code_offset = header_bytes.len() as u32;
+    if code_offset != header.code_offset {
+        // the header changed its size, so the code (and all the offsets in it) moved
+        reactor = reactor.restart_at(code_offset);
+        react_or_exit(reader.clone(), schema, &mut reactor);
+    }
 
     let output_size = reactor.output_size();
 
@@ -213,9 +638,9 @@ fn rewrite_snr<'a, R, O>(
     output_buffer
         .write_all(&output_size.to_le_bytes())
         .expect("Writing to the output file failed");
-    // copy the rest of the header
+    // write the rest of the header
     output_buffer
-        .write_all(&snr_file[8..code_offset as usize])
+        .write_all(&header_bytes[8..])
         .expect("Writing to the output file failed");
 
     assert_eq!(
@@ -227,7 +652,7 @@ fn rewrite_snr<'a, R, O>(
     );
 
     let mut reactor = reactor.into_emit(output_buffer);
-    react_with(reader, schema, &mut reactor);
+    react_or_exit(reader, schema, &mut reactor);
 
     let output_buffer = reactor.finish();
 
@@ -242,6 +667,108 @@ fn rewrite_snr<'a, R, O>(
         .expect("Writing to the output file failed")
 }
 
+/// Translation file data, regardless of its format
+enum TranslationData {
+    Csv(CsvData),
+    Po(PoData),
+    Xliff(XliffData),
+}
+
+impl TranslationData {
+    /// Reads the translations file in the given format, using `read_csv` to read the CSV files
+    fn read(
+        path: &Utf8Path,
+        format: TranslationFormat,
+        style: MessageCommandStyle,
+        read_csv: impl FnOnce(csv::Reader<File>) -> CsvData,
+    ) -> Self {
+        match format {
+            TranslationFormat::Csv => TranslationData::Csv(read_csv(
+                csv::Reader::from_path(path).expect("Opening the CSV file failed"),
+            )),
+            TranslationFormat::Po => {
+                let translations =
+                    std::fs::read_to_string(path).expect("Reading the PO file failed");
+                TranslationData::Po(
+                    PoData::parse(&translations).expect("Parsing the PO file failed"),
+                )
+            }
+            TranslationFormat::Xliff => {
+                let translations =
+                    std::fs::read_to_string(path).expect("Reading the XLIFF file failed");
+                TranslationData::Xliff(
+                    XliffData::parse(&translations, style).expect("Parsing the XLIFF file failed"),
+                )
+            }
+        }
+    }
+
+    fn propagate_repeats(&mut self) -> usize {
+        match self {
+            TranslationData::Csv(data) => data.propagate_repeats(),
+            TranslationData::Po(data) => data.propagate_repeats(),
+            TranslationData::Xliff(data) => data.propagate_repeats(),
+        }
+    }
+
+    fn substitute(
+        &mut self,
+        substitutions: &SubstitutionTable,
+        encoding: StringEncoding,
+        char_remap: &CharRemap,
+    ) -> usize {
+        match self {
+            TranslationData::Csv(data) => data.substitute(substitutions, encoding, char_remap),
+            TranslationData::Po(data) => data.substitute(substitutions, encoding, char_remap),
+            TranslationData::Xliff(data) => data.substitute(substitutions, encoding, char_remap),
+        }
+    }
+
+    fn lint(&self, options: &LintOptions) -> Result<(), Vec<LineReport>> {
+        match self {
+            TranslationData::Csv(data) => data.lint(options),
+            TranslationData::Po(data) => data.lint(options),
+            TranslationData::Xliff(data) => data.lint(options),
+        }
+    }
+
+    fn stats(
+        &self,
+        strings: &[TracedString],
+        style: MessageCommandStyle,
+        id_range_size: u32,
+    ) -> TranslationStats {
+        match self {
+            TranslationData::Csv(data) => data.stats(strings, style, id_range_size),
+            TranslationData::Po(data) => data.stats(strings, style, id_range_size),
+            TranslationData::Xliff(data) => data.stats(strings, style, id_range_size),
+        }
+    }
+}
+
+fn report_lint_errors(result: Result<(), Vec<LineReport>>) {
+    if let Err(e) = result {
+        println!("There are some issues with strings in the provided translation file");
+
+        // warnings are reported, but don't stop the rewrite
+        let has_errors = e.iter().any(|report| report.has_errors());
+        for report in e {
+            let report = miette::Report::from(report);
+
+            println!("{:?}", report);
+        }
+        if has_errors {
+            println!("NOTE: You can disable linting by passing --no-lint");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn create_output(path: &Utf8Path) -> BufWriter<File> {
+    let output = File::create(path).expect("Opening the output file failed");
+    BufWriter::new(output)
+}
+
 fn bindiff_snr(schema: &EngineSchema, snr1: &[u8], snr2: &[u8]) {
     let code_offset1 = u32::from_le_bytes(snr1[0x20..0x24].try_into().unwrap());
     let code_offset2 = u32::from_le_bytes(snr2[0x20..0x24].try_into().unwrap());
@@ -283,49 +810,201 @@ fn bindiff_snr(schema: &EngineSchema, snr1: &[u8], snr2: &[u8]) {
 }
 
 impl Command {
+    pub fn run(self) {
+        match self {
+            Command::Snr(command) => command.run(),
+            // this doesn't need the engine version
+            Command::Detect { snr_file } => {
+                let snr_file = std::fs::read(snr_file).expect("Reading the SNR file failed");
+                let code_offset = code_offset(&snr_file);
+                version::print_detection(Reader::new(&snr_file, code_offset as usize));
+            }
+            // these don't need an SNR file
+            Command::TmExport {
+                target_lang,
+                translations,
+                output,
+            } => {
+                let translations =
+                    csv::Reader::from_path(translations).expect("Opening the CSV file failed");
+                let memory =
+                    TranslationMemory::from_csv(translations).expect("Reading the CSV file failed");
+
+                let output = File::create(output).expect("Opening the TMX file failed");
+                memory
+                    .write_tmx(BufWriter::new(output), &target_lang)
+                    .expect("Writing the TMX file failed");
+
+                info!("Exported {} translation units", memory.units().len());
+            }
+            Command::TmImport {
+                memory,
+                translations,
+                output,
+            } => {
+                let memory = std::fs::read_to_string(memory).expect("Reading the TMX file failed");
+                let memory =
+                    TranslationMemory::parse_tmx(&memory).expect("Parsing the TMX file failed");
+
+                // read the whole file first, so that the output can overwrite it
+                let translations =
+                    std::fs::read(translations).expect("Reading the CSV file failed");
+
+                let filled = memory
+                    .fill_csv(
+                        csv::Reader::from_reader(translations.as_slice()),
+                        csv::Writer::from_path(output).expect("Opening the output CSV file failed"),
+                    )
+                    .expect("Filling the CSV file failed");
+
+                info!("Filled {} strings from the translation memory", filled);
+            }
+        }
+    }
+}
+
+impl SnrCommand {
     pub fn run(self) {
         let common = match &self {
-            Command::Read { common, .. } => common,
-            Command::ReadConsole { common, .. } => common,
-            Command::ReadToBin { common, .. } => common,
-            Command::ReadValidateOffsets { common, .. } => common,
-            Command::Test { common, .. } => common,
-            Command::Rewrite { common, .. } => common,
+            SnrCommand::Read { common, .. } => common,
+            SnrCommand::ReadConsole { common, .. } => common,
+            SnrCommand::ReadToBin { common, .. } => common,
+            SnrCommand::ReadValidateOffsets { common, .. } => common,
+            SnrCommand::Test { common, .. } => common,
+            SnrCommand::Rewrite { common, .. } => common,
+            SnrCommand::Preview { common, .. } => common,
+            SnrCommand::Migrate { common, .. } => common,
+            SnrCommand::Stats { common, .. } => common,
+            SnrCommand::Header { common, .. } => common,
+            SnrCommand::Grep { common, .. } => common,
         };
 
         let snr_file = std::fs::read(&common.snr_file).expect("Reading the SNR file failed");
-        let version = common.engine_version;
-
-        let schema = &ENGINE_SCHEMAS[version];
-
-        assert_eq!(&snr_file[0..4], b"SNR ", "SNR file magic mismatch");
-        let code_offset = u32::from_le_bytes(snr_file[0x20..0x24].try_into().unwrap());
+        let code_offset = code_offset(&snr_file);
 
         let reader = Reader::new(&snr_file, code_offset as usize);
 
+        let version = common.engine_version.resolve(reader.clone());
+        let schema = &ENGINE_SCHEMAS[version];
+        let header = match SnrHeader::parse(&snr_file, version) {
+            Ok(header) => header,
+            Err(error) => {
+                error!("Parsing the SNR header failed: {}", error);
+                std::process::exit(1);
+            }
+        };
+
         match self {
-            Command::Read {
+            SnrCommand::Read {
                 common: _,
                 message_style,
+                speaker_names,
+                mark_repeats,
+                format,
                 output,
             } => {
-                let writer = csv::Writer::from_path(output).expect("Opening the CSV file failed");
-
                 let encoding = version.string_encoding();
                 let snr_style = version.message_command_style();
                 let user_style = message_style.apply(snr_style);
 
-                let mut reactor = StringTraceReactor::new(
-                    encoding,
-                    snr_style,
-                    user_style,
-                    version.has_needless_escapes(),
-                    CsvTraceListener::new(writer),
-                );
+                let format = format.unwrap_or_else(|| TranslationFormat::from_path(&output));
+                format.check_csv_only(&[
+                    ("--speaker-names", speaker_names.is_some()),
+                    ("--mark-repeats", mark_repeats),
+                ]);
+
+                match format {
+                    TranslationFormat::Csv => {
+                        let writer =
+                            csv::Writer::from_path(output).expect("Opening the CSV file failed");
+
+                        let mut listener = match &speaker_names {
+                            Some(path) => {
+                                let names = if path.exists() {
+                                    SpeakerNames::read(
+                                        csv::Reader::from_path(path)
+                                            .expect("Opening the speaker names CSV file failed"),
+                                    )
+                                } else {
+                                    SpeakerNames::new()
+                                };
+                                CsvTraceListener::with_speaker_names(writer, user_style, names)
+                            }
+                            None => CsvTraceListener::new(writer),
+                        };
+                        if mark_repeats {
+                            listener = listener.mark_repeats();
+                        }
+
+                        let mut reactor = StringTraceReactor::new(
+                            encoding,
+                            snr_style,
+                            user_style,
+                            version.has_needless_escapes(),
+                            listener,
+                        )
+                        .with_header(&header);
+
+                        react_or_exit(reader, schema, &mut reactor);
 
-                react_with(reader, schema, &mut reactor);
+                        if let Some(path) = speaker_names
+                            && let Some(names) = reactor.into_listener().into_speaker_names()
+                        {
+                            names
+                                .write(
+                                    csv::Writer::from_path(path)
+                                        .expect("Opening the speaker names CSV file failed"),
+                                )
+                                .expect("Writing the speaker names CSV file failed");
+                        }
+                    }
+                    TranslationFormat::Po => {
+                        let writer = BufWriter::new(
+                            File::create(output).expect("Opening the PO file failed"),
+                        );
+
+                        let mut reactor = StringTraceReactor::new(
+                            encoding,
+                            snr_style,
+                            user_style,
+                            version.has_needless_escapes(),
+                            PoTraceListener::new(writer),
+                        )
+                        .with_header(&header);
+
+                        react_or_exit(reader, schema, &mut reactor);
+
+                        reactor
+                            .into_listener()
+                            .into_inner()
+                            .flush()
+                            .expect("Writing the PO file failed");
+                    }
+                    TranslationFormat::Xliff => {
+                        let writer = BufWriter::new(
+                            File::create(output).expect("Opening the XLIFF file failed"),
+                        );
+
+                        let mut reactor = StringTraceReactor::new(
+                            encoding,
+                            snr_style,
+                            user_style,
+                            version.has_needless_escapes(),
+                            XliffTraceListener::new(writer, user_style),
+                        )
+                        .with_header(&header);
+
+                        react_or_exit(reader, schema, &mut reactor);
+
+                        reactor
+                            .into_listener()
+                            .finish()
+                            .flush()
+                            .expect("Writing the XLIFF file failed");
+                    }
+                }
             }
-            Command::ReadConsole {
+            SnrCommand::ReadConsole {
                 common: _,
                 message_style,
             } => {
@@ -339,21 +1018,22 @@ impl Command {
                     user_style,
                     version.has_needless_escapes(),
                     ConsoleTraceListener,
-                );
+                )
+                .with_header(&header);
 
-                react_with(reader, schema, &mut reactor);
+                react_or_exit(reader, schema, &mut reactor);
             }
-            Command::ReadToBin { common: _, output } => {
+            SnrCommand::ReadToBin { common: _, output } => {
                 let mut output = File::create(&output).expect("Opening the BIN file failed");
 
                 let mut reactor = DumpBinReactor::new(&mut output);
 
-                react_with(reader, schema, &mut reactor);
+                react_or_exit(reader, schema, &mut reactor);
             }
-            Command::ReadValidateOffsets { common: _ } => {
+            SnrCommand::ReadValidateOffsets { common: _ } => {
                 let mut reactor = OffsetValidatorReactor::new();
 
-                react_with(reader, schema, &mut reactor);
+                react_or_exit(reader, schema, &mut reactor);
 
                 match reactor.validate() {
                     Ok(_) => {
@@ -364,7 +1044,7 @@ impl Command {
                     }
                 }
             }
-            Command::Test {
+            SnrCommand::Test {
                 common: _,
                 font_file,
                 layout_dump_file,
@@ -381,7 +1061,7 @@ impl Command {
                         version.string_policy(),
                     );
 
-                    react_with(reader.clone(), schema, &mut reactor);
+                    react_or_exit(reader.clone(), schema, &mut reactor);
 
                     // 2. test SNR roundtrip
                     // NOTE: we have to pass in out_style to both NoopRewriter and RewriteReactor
@@ -396,11 +1076,15 @@ impl Command {
                     rewrite_snr(
                         &snr_file,
                         reader.clone(),
-                        code_offset,
+                        &header,
                         schema,
-                        version,
-                        user_style,
-                        MessageReflowMode::NoReflow,
+                        RewriteOptions {
+                            version,
+                            user_style,
+                            reflow_mode: MessageReflowMode::NoReflow,
+                            choice_fit: None,
+                            char_remap: CharRemap::new(),
+                        },
                         rewriter,
                         &mut output,
                     );
@@ -439,8 +1123,8 @@ impl Command {
                     drop(layout_dump_file);
 
                     let mut font = File::open(&font_file).expect("Failed to open font file");
-                    let font = shin_font::FontMetrics::from_font0(&mut font)
-                        .expect("Failed to parse font");
+                    let font =
+                        shin_font::FontMetrics::read(&mut font).expect("Failed to parse font");
 
                     shin_snr::layout::layouter::validate_light_layouter_against_dump(
                         &font,
@@ -448,62 +1132,543 @@ impl Command {
                     );
                 }
             }
-            Command::Rewrite {
+            SnrCommand::Rewrite {
                 common: _,
                 message_style,
                 no_lint,
                 replacement_mode,
                 reflow_mode,
                 font_file,
+                layout,
+                check_overflow,
+                hyphenate,
+                choice_fit,
+                choice_width,
+                choice_max_lines,
+                speaker_names,
+                propagate_repeats,
+                auto_substitute,
+                substitution_table,
+                char_remap,
+                glossary,
+                format,
                 translations,
                 output,
             } => {
                 let snr_style = version.message_command_style();
                 let user_style = message_style.apply(snr_style);
 
+                let char_remap = read_char_remap(char_remap.as_deref(), version);
+
                 let font_file = font_file.map(|path| {
                     let mut font_file = File::open(&path).expect("Opening the font file failed");
-                    // TODO: support font formats other than font0
-                    FontMetrics::from_font0(&mut font_file).expect("Failed to read font file")
+                    FontMetrics::read(&mut font_file)
+                        .expect("Failed to read font file")
+                        .with_char_remap(char_remap.clone())
                 });
+                if let Some(font_file) = &font_file {
+                    check_char_remap_font(&char_remap, font_file);
+                }
 
-                let reflow_mode = reflow_mode.materialize(version, font_file.as_ref());
+                let hyphenator = hyphenate.map(|language| {
+                    Hyphenator::for_language(&language).unwrap_or_else(|e| {
+                        error!("{}", e);
+                        std::process::exit(1);
+                    })
+                });
 
-                let translations =
-                    csv::Reader::from_path(translations).expect("Opening the CSV file failed");
-                let data = CsvData::new(translations);
+                let reflow_mode = reflow_mode.materialize(
+                    version,
+                    &layout,
+                    font_file.as_ref(),
+                    hyphenator.as_ref(),
+                );
+
+                if choice_fit == ChoiceFitPolicy::Reflow && choice_max_lines < 2 {
+                    error!(
+                        "The choices only fit a single line, so they can't be reflowed. Use --choice-fit shrink or pass --choice-max-lines"
+                    );
+                    std::process::exit(1);
+                }
+                let choice_box = choice_width.map(|width| ChoiceBoxInfo {
+                    width,
+                    max_lines: choice_max_lines,
+                });
+                let choice_params = (choice_fit != ChoiceFitPolicy::Keep).then(|| ReflowParams {
+                    metrics: font_file
+                        .as_ref()
+                        .expect("--choice-fit requires --font-file"),
+                    layout: layout.resolve(version),
+                    hyphenator: hyphenator.as_ref(),
+                });
+
+                let format = format.unwrap_or_else(|| TranslationFormat::from_path(&translations));
+                format.check_csv_only(&[("--speaker-names", speaker_names.is_some())]);
+
+                let mut data =
+                    TranslationData::read(&translations, format, user_style, |translations| {
+                        let speaker_names = speaker_names.map(|path| {
+                            SpeakerNames::read(
+                                csv::Reader::from_path(path)
+                                    .expect("Opening the speaker names CSV file failed"),
+                            )
+                        });
+                        CsvData::new(translations, speaker_names.as_ref())
+                    });
+
+                let mut substitutions = SubstitutionTable::new();
+                if let Some(path) = substitution_table {
+                    substitutions
+                        .extend_from_csv(
+                            csv::Reader::from_path(path)
+                                .expect("Opening the substitution table CSV file failed"),
+                        )
+                        .expect("Reading the substitution table CSV file failed");
+                }
+
+                let glossary = glossary
+                    .map(|path| {
+                        Glossary::from_csv(
+                            csv::Reader::from_path(path)
+                                .expect("Opening the glossary CSV file failed"),
+                        )
+                        .expect("Reading the glossary CSV file failed")
+                    })
+                    .unwrap_or_default();
+
+                if propagate_repeats {
+                    info!("Filled {} repeated strings", data.propagate_repeats());
+                }
+                if auto_substitute {
+                    info!(
+                        "Substituted characters in {} strings",
+                        data.substitute(&substitutions, version.string_encoding(), &char_remap)
+                    );
+                }
                 if !no_lint {
-                    if let Err(e) = data.lint(replacement_mode, user_style) {
-                        println!("There are some issues with strings in the provided CSV file");
+                    report_lint_errors(data.lint(&LintOptions {
+                        mode: replacement_mode,
+                        style: user_style,
+                        version,
+                        substitutions: &substitutions,
+                        char_remap: &char_remap,
+                        glossary: &glossary,
+                        font: font_file.as_ref(),
+                        overflow: check_overflow.then(|| {
+                            let (params, algorithm) =
+                                reflow_mode.reflow_params().unwrap_or_else(|| {
+                                    (
+                                        ReflowParams {
+                                            metrics: font_file
+                                                .as_ref()
+                                                .expect("--check-overflow requires --font-file"),
+                                            layout: layout.resolve(version),
+                                            hyphenator: hyphenator.as_ref(),
+                                        },
+                                        ReflowAlgorithm::Greedy,
+                                    )
+                                });
+                            OverflowCheck {
+                                params,
+                                algorithm,
+                                split_pages: matches!(
+                                    reflow_mode,
+                                    MessageReflowMode::GreedySplit(_)
+                                ),
+                                choices: choice_box.map(|choice_box| (choice_box, choice_fit)),
+                            }
+                        }),
+                        reflow: reflow_mode,
+                    }));
+                }
 
-                        for report in e {
-                            let report = miette::Report::from(report);
+                let options = RewriteOptions {
+                    version,
+                    user_style,
+                    reflow_mode,
+                    choice_fit: choice_params
+                        .zip(choice_box)
+                        .map(|(params, choice_box)| (params, choice_box, choice_fit)),
+                    char_remap,
+                };
 
-                            println!("{:?}", report);
-                        }
-                        println!("NOTE: You can disable linting by passing --no-lint");
-                        std::process::exit(1);
+                let mut output = create_output(&output);
+                match data {
+                    TranslationData::Csv(data) => {
+                        let rewriter = CsvRewriter::new(data, replacement_mode);
+                        rewrite_snr(
+                            &snr_file,
+                            reader,
+                            &header,
+                            schema,
+                            options,
+                            rewriter,
+                            &mut output,
+                        );
+                    }
+                    TranslationData::Po(data) => {
+                        let rewriter = PoRewriter::new(data, replacement_mode);
+                        rewrite_snr(
+                            &snr_file,
+                            reader,
+                            &header,
+                            schema,
+                            options,
+                            rewriter,
+                            &mut output,
+                        );
+                    }
+                    TranslationData::Xliff(data) => {
+                        let rewriter = XliffRewriter::new(data, replacement_mode);
+                        rewrite_snr(
+                            &snr_file,
+                            reader,
+                            &header,
+                            schema,
+                            options,
+                            rewriter,
+                            &mut output,
+                        );
                     }
                 }
+                output.flush().unwrap();
+            }
+            SnrCommand::Preview {
+                common: _,
+                message_style,
+                reflow_mode,
+                hyphenate,
+                font_file,
+                layout,
+                char_remap,
+                speaker_names,
+                translations,
+                out,
+            } => {
+                let snr_style = version.message_command_style();
+                let user_style = message_style.apply(snr_style);
 
-                let rewriter = CsvRewriter::new(data, replacement_mode);
+                let char_remap = read_char_remap(char_remap.as_deref(), version);
 
-                let output = File::create(output).expect("Opening the output file failed");
-                let mut output = BufWriter::new(output);
+                let font_file = std::fs::read(&font_file).expect("Reading the font file failed");
+                let font_metrics = FontMetrics::read(&mut Cursor::new(&font_file))
+                    .expect("Failed to read font file")
+                    .with_char_remap(char_remap.clone());
+                check_char_remap_font(&char_remap, &font_metrics);
+                let font_glyphs = FontGlyphs::read(&mut Cursor::new(&font_file))
+                    .unwrap_or_else(|e| {
+                        error!("Failed to read the glyph images from the font file: {}", e);
+                        std::process::exit(1);
+                    })
+                    .with_char_remap(char_remap);
 
-                rewrite_snr(
-                    &snr_file,
-                    reader,
-                    code_offset,
-                    schema,
+                let hyphenator = hyphenate.map(|language| {
+                    Hyphenator::for_language(&language).unwrap_or_else(|e| {
+                        error!("{}", e);
+                        std::process::exit(1);
+                    })
+                });
+                let reflow_mode = reflow_mode.materialize(
                     version,
+                    &layout,
+                    Some(&font_metrics),
+                    hyphenator.as_ref(),
+                );
+                let layout_info = layout.resolve(version);
+
+                let mut reactor = StringTraceReactor::new(
+                    version.string_encoding(),
+                    snr_style,
                     user_style,
-                    reflow_mode,
-                    rewriter,
-                    &mut output,
+                    version.has_needless_escapes(),
+                    Vec::<TracedString>::new(),
+                )
+                .with_header(&header);
+                react_or_exit(reader, schema, &mut reactor);
+                let strings = reactor.into_listener();
+
+                let speaker_names = speaker_names.map(|path| {
+                    SpeakerNames::read(
+                        csv::Reader::from_path(path)
+                            .expect("Opening the speaker names CSV file failed"),
+                    )
+                });
+                let translations =
+                    csv::Reader::from_path(translations).expect("Opening the CSV file failed");
+                let translations = CsvData::new(translations, speaker_names.as_ref());
+
+                std::fs::create_dir_all(&out).expect("Creating the output directory failed");
+
+                let mut renderer = PreviewRenderer::new(&font_glyphs, layout_info);
+                let mut bump = bumpalo::Bump::new();
+                let mut rendered = 0;
+                let mut overflowing = 0;
+                for (index, string) in (0..).zip(&strings) {
+                    if !matches!(
+                        string.source,
+                        AnyStringSource::Singular(StringSource::Msgset(_))
+                    ) {
+                        continue;
+                    }
+                    let Some(translated) =
+                        translations.get_translated(index, string.offset, string.source)
+                    else {
+                        continue;
+                    };
+
+                    let translated = bump.alloc_str(translated);
+                    let mut tokens = bumpalo::collections::Vec::new_in(&bump);
+                    message_parser::parse(user_style, translated, &mut tokens);
+                    // the layouter can't measure the missing characters, so they have to be found before reflowing
+                    if let Some(c) = find_missing_glyph(&font_metrics, &tokens) {
+                        warn!(
+                            "Skipping message {}: the font has no glyph for {:?}",
+                            index, c
+                        );
+                    } else {
+                        let pages = message_pages(&bump, &tokens, reflow_mode);
+                        for (page_index, page) in (1..).zip(&pages) {
+                            let preview = place_message(&bump, &font_metrics, layout_info, page);
+                            if preview.lines > layout_info.max_lines {
+                                warn!(
+                                    "Message {} has {} lines, but the message box only fits {}",
+                                    index, preview.lines, layout_info.max_lines
+                                );
+                                overflowing += 1;
+                            }
+
+                            let path = if pages.len() > 1 {
+                                out.join(format!("{index:05}-{page_index}.png"))
+                            } else {
+                                out.join(format!("{index:05}.png"))
+                            };
+                            renderer
+                                .render(&preview)
+                                .save(&path)
+                                .expect("Writing the preview image failed");
+                        }
+                        rendered += 1;
+                    }
+
+                    drop(tokens);
+                    bump.reset();
+                }
+
+                info!(
+                    "Rendered {} messages, {} of them don't fit into the message box",
+                    rendered, overflowing
                 );
+            }
+            SnrCommand::Migrate {
+                common: _,
+                message_style,
+                old_translations,
+                output,
+            } => {
+                let snr_style = version.message_command_style();
+                let user_style = message_style.apply(snr_style);
 
-                output.flush().unwrap();
+                let mut reactor = StringTraceReactor::new(
+                    version.string_encoding(),
+                    snr_style,
+                    user_style,
+                    version.has_needless_escapes(),
+                    Vec::<TracedString>::new(),
+                )
+                .with_header(&header);
+                react_or_exit(reader, schema, &mut reactor);
+                let strings = reactor.into_listener();
+
+                let old_translations = csv::Reader::from_path(old_translations)
+                    .expect("Opening the old CSV file failed");
+                // the translations are carried over as they are, without joining the speaker names back
+                let old_translations = CsvData::new_unmerged(old_translations);
+
+                let result = migrate(&old_translations, &strings);
+
+                let writer = csv::Writer::from_path(output).expect("Opening the CSV file failed");
+                let mut listener = if old_translations.has_speakers() {
+                    CsvTraceListener::with_speaker_names(writer, user_style, SpeakerNames::new())
+                } else {
+                    CsvTraceListener::new(writer)
+                };
+                for (string, translated) in strings.iter().zip(&result.translations) {
+                    listener.on_translated_string(
+                        string.offset,
+                        string.source,
+                        &string.s,
+                        string.voice.as_deref(),
+                        translated.as_deref(),
+                    );
+                }
+
+                println!("{}", result.report());
+            }
+            SnrCommand::Stats {
+                common: _,
+                message_style,
+                id_range_size,
+                json,
+                format,
+                translations,
+            } => {
+                let snr_style = version.message_command_style();
+                let user_style = message_style.apply(snr_style);
+
+                let mut reactor = StringTraceReactor::new(
+                    version.string_encoding(),
+                    snr_style,
+                    user_style,
+                    version.has_needless_escapes(),
+                    Vec::<TracedString>::new(),
+                )
+                .with_header(&header);
+                react_or_exit(reader, schema, &mut reactor);
+                let strings = reactor.into_listener();
+
+                let format = format.unwrap_or_else(|| TranslationFormat::from_path(&translations));
+                // the speaker names are translated separately, so they are not counted
+                let translations =
+                    TranslationData::read(&translations, format, user_style, CsvData::new_unmerged);
+
+                let stats = translations.stats(&strings, user_style, id_range_size);
+
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&stats)
+                            .expect("Serializing the statistics failed")
+                    );
+                } else {
+                    print!("{}", stats);
+                }
+            }
+            SnrCommand::Header { common: _, json } => {
+                if shin_snr::header::section_order(version).is_none() {
+                    warn!(
+                        "The header layout of {} is not known, so the sections are not decoded",
+                        version.name()
+                    );
+                }
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&header)
+                            .expect("Serializing the header failed")
+                    );
+                } else {
+                    print!("{}", header);
+                }
+            }
+            SnrCommand::Grep {
+                common: _,
+                message_style,
+                strip_commands,
+                ignore_case,
+                context,
+                translations,
+                speaker_names,
+                pattern,
+            } => {
+                let snr_style = version.message_command_style();
+                let user_style = message_style.apply(snr_style);
+
+                let pattern = match RegexBuilder::new(&pattern)
+                    .case_insensitive(ignore_case)
+                    .build()
+                {
+                    Ok(pattern) => pattern,
+                    Err(error) => {
+                        error!("Invalid regular expression: {}", error);
+                        std::process::exit(1);
+                    }
+                };
+
+                let mut reactor = StringTraceReactor::new(
+                    version.string_encoding(),
+                    snr_style,
+                    user_style,
+                    version.has_needless_escapes(),
+                    Vec::<TracedString>::new(),
+                )
+                .with_header(&header);
+                react_or_exit(reader, schema, &mut reactor);
+                let strings = reactor.into_listener();
+
+                let translations = translations.map(|path| {
+                    let translations =
+                        csv::Reader::from_path(path).expect("Opening the CSV file failed");
+                    match &speaker_names {
+                        Some(path) => {
+                            let speaker_names = SpeakerNames::read(
+                                csv::Reader::from_path(path)
+                                    .expect("Opening the speaker names CSV file failed"),
+                            );
+                            CsvData::new(translations, Some(&speaker_names))
+                        }
+                        // don't search in the untranslated speaker names
+                        None => CsvData::new_unmerged(translations),
+                    }
+                });
+
+                let mut bump = bumpalo::Bump::new();
+                // the text to search in and to print, `None` if there is no translation to search in
+                let texts = (0..)
+                    .zip(&strings)
+                    .map(|(index, string)| {
+                        let text = match &translations {
+                            Some(translations) => {
+                                translations.get_translated(index, string.offset, string.source)?
+                            }
+                            None => &string.s,
+                        };
+                        let text = if strip_commands && string.source.contains_commands() {
+                            let stripped = message_parser::strip_commands(
+                                &bump,
+                                user_style,
+                                bump.alloc_str(text),
+                            )
+                            .to_string();
+                            bump.reset();
+                            stripped
+                        } else {
+                            text.to_string()
+                        };
+                        Some(text)
+                    })
+                    .collect::<Vec<_>>();
+
+                let matches = texts
+                    .iter()
+                    .map(|text| text.as_ref().is_some_and(|text| pattern.is_match(text)))
+                    .collect::<Vec<_>>();
+
+                let mut last_printed = None;
+                for (index, string) in strings.iter().enumerate() {
+                    let start = index.saturating_sub(context);
+                    let end = (index + context + 1).min(strings.len());
+                    if !matches[start..end].contains(&true) {
+                        continue;
+                    }
+
+                    if let Some(last_printed) = last_printed
+                        && last_printed + 1 != index
+                    {
+                        println!("--");
+                    }
+                    last_printed = Some(index);
+
+                    // like in grep, `:` marks the matches and `-` marks the context
+                    println!(
+                        "{}{} 0x{:08x} {}:{} {}",
+                        index,
+                        if matches[index] { ':' } else { '-' },
+                        string.offset,
+                        string.source.kind().name(),
+                        string.source.subindex(),
+                        texts[index].as_deref().unwrap_or(&string.s)
+                    );
+                }
             }
         }
     }
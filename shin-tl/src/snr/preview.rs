@@ -0,0 +1,154 @@
+//! Drawing the messages laid out by [`shin_snr::layout::preview`] into images
+
+use std::collections::HashMap;
+
+use bumpalo::Bump;
+use image::{GrayImage, Rgba, RgbaImage, imageops};
+use shin_font::FontGlyphs;
+use shin_snr::layout::{
+    layouter::GameLayoutInfo,
+    message_parser::{MessageReflowMode, MessageToken},
+    preview::MessagePreview,
+    reflow,
+};
+
+/// Space around the message box, in pixels
+const MARGIN: u32 = 16;
+const BOX_COLOR: Rgba<u8> = Rgba([0x20, 0x20, 0x28, 0xff]);
+/// Background of the text that doesn't fit into the message box
+const OVERFLOW_COLOR: Rgba<u8> = Rgba([0x70, 0x10, 0x10, 0xff]);
+const MARGIN_COLOR: Rgba<u8> = Rgba([0x08, 0x08, 0x0c, 0xff]);
+
+/// Reflows the parsed message the same way the rewrite does, returning the tokens of each page without the speaker name
+pub fn message_pages<'bump>(
+    bump: &'bump Bump,
+    tokens: &[MessageToken<'bump>],
+    reflow_mode: MessageReflowMode,
+) -> Vec<bumpalo::collections::Vec<'bump, MessageToken<'bump>>> {
+    // the name isn't drawn in the message box, and it has to be found before the reflow inserts any line breaks
+    let body = &tokens[reflow::speaker_name_len(tokens)..];
+
+    let Some((params, algorithm)) = reflow_mode.reflow_params() else {
+        return vec![bumpalo::collections::Vec::from_iter_in(
+            body.iter().copied(),
+            bump,
+        )];
+    };
+    let mut reflowed = bumpalo::collections::Vec::new_in(bump);
+    reflow::reflow_message(bump, params, algorithm, body, &mut reflowed);
+
+    match reflow_mode {
+        MessageReflowMode::GreedySplit(_) => {
+            reflow::split_pages(bump, params.layout.max_lines, 0, &reflowed)
+                .into_iter()
+                .collect()
+        }
+        _ => vec![reflowed],
+    }
+}
+
+pub struct PreviewRenderer<'a> {
+    glyphs: &'a FontGlyphs,
+    layout_info: GameLayoutInfo,
+    /// Glyph images, scaled to the size they are drawn at
+    scaled_glyphs: HashMap<(char, u32), Option<GrayImage>>,
+}
+
+impl<'a> PreviewRenderer<'a> {
+    pub fn new(glyphs: &'a FontGlyphs, layout_info: GameLayoutInfo) -> Self {
+        Self {
+            glyphs,
+            layout_info,
+            scaled_glyphs: HashMap::new(),
+        }
+    }
+
+    /// Draws the message over the message box, with one pixel per layout unit
+    ///
+    /// The lines below the message box, and the text sticking out of it to the right are drawn over a red background.
+    pub fn render(&mut self, preview: &MessagePreview) -> RgbaImage {
+        let box_width = self.layout_info.width.ceil() as u32;
+        let box_height = (self.layout_info.max_lines as f32 * preview.line_height).ceil() as u32;
+
+        let text_width = preview
+            .chars
+            .iter()
+            .filter_map(|c| {
+                let glyph = self.glyphs.get_glyph(c.codepoint)?;
+                Some(c.x + glyph.info.advance_width as f32 * c.scale)
+            })
+            .fold(0.0f32, f32::max)
+            .ceil() as u32;
+        let text_height = (preview.lines as f32 * preview.line_height).ceil() as u32;
+
+        let width = box_width.max(text_width) + 2 * MARGIN;
+        let height = box_height.max(text_height) + 2 * MARGIN;
+        let mut image = RgbaImage::from_fn(width, height, |x, y| {
+            let (Some(x), Some(y)) = (x.checked_sub(MARGIN), y.checked_sub(MARGIN)) else {
+                return MARGIN_COLOR;
+            };
+            if x < box_width && y < box_height {
+                BOX_COLOR
+            } else if x < text_width.max(box_width) && y < text_height.max(box_height) {
+                OVERFLOW_COLOR
+            } else {
+                MARGIN_COLOR
+            }
+        });
+
+        for c in &preview.chars {
+            let Some(glyph) = self.glyphs.get_glyph(c.codepoint) else {
+                continue;
+            };
+            let left = MARGIN as f32 + c.x + glyph.info.bearing_x as f32 * c.scale;
+            let top = MARGIN as f32 + c.baseline_y - glyph.info.bearing_y as f32 * c.scale;
+
+            let Some(glyph_image) = self.scaled_glyph(c.codepoint, c.scale) else {
+                continue;
+            };
+            let [r, g, b] = c.color;
+            for (x, y, coverage) in glyph_image.enumerate_pixels() {
+                let (x, y) = (
+                    left.round() as i64 + x as i64,
+                    top.round() as i64 + y as i64,
+                );
+                let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) else {
+                    continue;
+                };
+                if x >= width || y >= height {
+                    continue;
+                }
+
+                let alpha = coverage.0[0] as f32 / 255.0;
+                let pixel = image.get_pixel_mut(x, y);
+                for (channel, color) in pixel.0.iter_mut().zip([r, g, b]) {
+                    *channel =
+                        (*channel as f32 * (1.0 - alpha) + color as f32 * alpha).round() as u8;
+                }
+            }
+        }
+
+        image
+    }
+
+    fn scaled_glyph(&mut self, codepoint: char, scale: f32) -> Option<&GrayImage> {
+        let glyphs = self.glyphs;
+        self.scaled_glyphs
+            .entry((codepoint, scale.to_bits()))
+            .or_insert_with(|| {
+                let image = &glyphs.get_glyph(codepoint)?.image;
+                let width = (image.width() as f32 * scale).round() as u32;
+                let height = (image.height() as f32 * scale).round() as u32;
+                if width == 0 || height == 0 {
+                    return None;
+                }
+                Some(imageops::resize(
+                    image,
+                    width,
+                    height,
+                    imageops::FilterType::Triangle,
+                ))
+            })
+            .as_ref()
+    }
+}
@@ -0,0 +1,126 @@
+use clap::builder::TypedValueParser;
+use shin_snr::{
+    detect::{VersionCandidate, detect_version},
+    reader::Reader,
+};
+use shin_versions::ShinVersion;
+use tracing::{error, info};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum EngineVersionSpecifier {
+    /// Detect the version by trying to parse the file with each of them
+    Auto,
+    ShinVersion(ShinVersion),
+}
+
+impl EngineVersionSpecifier {
+    /// Returns the specified version, detecting it if needed. Exits if it can't be detected unambiguously.
+    pub fn resolve(self, reader: Reader) -> ShinVersion {
+        match self {
+            EngineVersionSpecifier::ShinVersion(version) => version,
+            EngineVersionSpecifier::Auto => {
+                let candidates = detect_version(reader);
+                let plausible = candidates
+                    .iter()
+                    .filter(|c| c.is_plausible())
+                    .collect::<Vec<_>>();
+                match plausible.as_slice() {
+                    [candidate] => {
+                        info!("Detected engine version: {}", candidate.version.name());
+                        candidate.version
+                    }
+                    [] => {
+                        error!(
+                            "Could not detect the engine version: none of the known versions can parse the file. Run `shin-tl snr detect` for details"
+                        );
+                        std::process::exit(1);
+                    }
+                    plausible => {
+                        error!(
+                            "Could not detect the engine version: the file can be parsed as any of {}. Please specify the version explicitly",
+                            format_versions(plausible)
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+    }
+
+    fn to_possible_value(self) -> Option<clap::builder::PossibleValue> {
+        use clap::ValueEnum;
+
+        match self {
+            EngineVersionSpecifier::Auto => Some(
+                clap::builder::PossibleValue::new("auto")
+                    .help("Detect the version by trying to parse the file with each of them"),
+            ),
+            EngineVersionSpecifier::ShinVersion(v) => v.to_possible_value(),
+        }
+    }
+
+    pub(super) fn parser() -> impl TypedValueParser<Value = Self> {
+        use clap::ValueEnum;
+
+        let variants = std::iter::once(EngineVersionSpecifier::Auto)
+            .chain(
+                ShinVersion::value_variants()
+                    .iter()
+                    .copied()
+                    .map(EngineVersionSpecifier::ShinVersion),
+            )
+            .collect::<Vec<_>>();
+
+        clap::builder::PossibleValuesParser::new(
+            variants.iter().copied().flat_map(|v| v.to_possible_value()),
+        )
+        .map(move |v| {
+            variants
+                .iter()
+                .find_map(|&x| x.to_possible_value()?.matches(&v, true).then_some(x))
+                .unwrap()
+        })
+    }
+}
+
+fn format_versions(candidates: &[&VersionCandidate]) -> String {
+    candidates
+        .iter()
+        .map(|c| c.version.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Prints how well the file matches each of the versions, for the `snr detect` command
+pub fn print_detection(reader: Reader) {
+    let candidates = detect_version(reader);
+
+    for candidate in &candidates {
+        let status = match candidate.parse_error {
+            Some((position, kind)) => format!("fails to parse at 0x{:08x}: {}", position, kind),
+            None if !candidate.valid_offsets => {
+                "parses, but has jumps to invalid offsets".to_string()
+            }
+            None if candidate.undecodable_strings > 0 => format!(
+                "parses, but {} of {} strings can't be decoded",
+                candidate.undecodable_strings, candidate.strings
+            ),
+            None => format!("parses cleanly, {} strings", candidate.strings),
+        };
+        println!("{:<20} {}", candidate.version.name(), status);
+    }
+    println!();
+
+    let plausible = candidates
+        .iter()
+        .filter(|c| c.is_plausible())
+        .collect::<Vec<_>>();
+    match plausible.as_slice() {
+        [] => println!("None of the known engine versions match this file"),
+        [candidate] => println!("Detected engine version: {}", candidate.version.name()),
+        plausible => println!(
+            "Several engine versions match this file: {}",
+            format_versions(plausible)
+        ),
+    }
+}
@@ -96,7 +96,7 @@ pub enum NumberStyle {
     VarInt,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(untagged))]
 pub enum AnyStringKind {
@@ -104,7 +104,38 @@ pub enum AnyStringKind {
     Array(StringArrayKind),
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+impl AnyStringKind {
+    pub const ALL: [Self; 14] = [
+        Self::Singular(StringKind::Saveinfo),
+        Self::Singular(StringKind::Select),
+        Self::Singular(StringKind::Msgset),
+        Self::Singular(StringKind::Dbgout),
+        Self::Singular(StringKind::Logset),
+        Self::Singular(StringKind::Voiceplay),
+        Self::Singular(StringKind::Chatset),
+        Self::Singular(StringKind::Named),
+        Self::Singular(StringKind::Stageinfo),
+        Self::Singular(StringKind::BgmName),
+        Self::Singular(StringKind::TipsTitle),
+        Self::Singular(StringKind::TipsContent),
+        Self::Singular(StringKind::CharacterName),
+        Self::Array(StringArrayKind::SelectChoice),
+    ];
+
+    /// The name of the string kind, as used in translation files
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnyStringKind::Singular(kind) => kind.name(),
+            AnyStringKind::Array(kind) => kind.name(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum StringKind {
@@ -123,9 +154,37 @@ pub enum StringKind {
     // Alias Carnival
     Named,
     Stageinfo,
+
+    // Strings from the SNR header tables
+    /// BGM track name shown in the music room
+    BgmName,
+    TipsTitle,
+    TipsContent,
+    /// Character name shown in the character list
+    CharacterName,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+impl StringKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StringKind::Saveinfo => "saveinfo",
+            StringKind::Select => "select",
+            StringKind::Msgset => "msgset",
+            StringKind::Dbgout => "dbgout",
+            StringKind::Logset => "logset",
+            StringKind::Voiceplay => "voiceplay",
+            StringKind::Chatset => "chatset",
+            StringKind::Named => "named",
+            StringKind::Stageinfo => "stageinfo",
+            StringKind::BgmName => "bgm_name",
+            StringKind::TipsTitle => "tips_title",
+            StringKind::TipsContent => "tips_content",
+            StringKind::CharacterName => "character_name",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum StringArrayKind {
@@ -134,6 +193,14 @@ pub enum StringArrayKind {
     SelectChoice,
 }
 
+impl StringArrayKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StringArrayKind::SelectChoice => "select_choice",
+        }
+    }
+}
+
 /// Describes how a particular string kind is encoded
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct StringStyle {
@@ -179,6 +246,22 @@ impl StringPolicy {
 }
 
 impl ShinVersion {
+    /// The name of the version, as used in the CLI (`kebab-case`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShinVersion::HigurashiSui => "higurashi-sui",
+            ShinVersion::AliasCarnival => "alias-carnival",
+            ShinVersion::WorldRe => "world-re",
+            ShinVersion::WhiteEternity => "white-eternity",
+            ShinVersion::HigurashiHou => "higurashi-hou",
+            ShinVersion::HigurashiHouV2 => "higurashi-hou-v2",
+            ShinVersion::DC4 => "dc4",
+            ShinVersion::Konosuba => "konosuba",
+            ShinVersion::Umineko => "umineko",
+            ShinVersion::Gerokasu2 => "gerokasu2",
+        }
+    }
+
     pub fn number_style(&self) -> NumberStyle {
         use NumberStyle::*;
         use ShinVersion::*;
@@ -218,83 +301,87 @@ impl ShinVersion {
         use ShinVersion::*;
         use StringKind::*;
 
-        let size_kind = match self {
-            HigurashiSui => match kind {
-                Saveinfo | Select | Voiceplay => U8Length,
-                Msgset | Logset => U16Length,
-                Dbgout | Chatset | Named | Stageinfo => {
-                    // not in this game
-                    unreachable!()
-                }
-            },
-            AliasCarnival => match kind {
-                Saveinfo | Select | Dbgout | Voiceplay | Stageinfo | Named => U8Length,
-                Msgset | Logset => U16Length,
-                Chatset => {
-                    // not in this game
-                    unreachable!()
-                }
-            },
-            WorldRe => match kind {
-                Saveinfo | Select | Voiceplay => U8Length,
-                Msgset | Logset => U16Length,
-                Dbgout | Chatset | Named | Stageinfo => {
-                    // not in this game
-                    unreachable!()
-                }
-            },
-            WhiteEternity => match kind {
-                Saveinfo | Select | Dbgout | Voiceplay => U8Length,
-                Msgset | Logset => U16Length,
-                Chatset | Named | Stageinfo => {
-                    // not in this game
-                    unreachable!()
-                }
-            },
-            HigurashiHou => match kind {
-                Saveinfo | Select | Dbgout | Voiceplay => U8Length,
-                Msgset | Logset => U16Length,
-                Chatset | Named | Stageinfo => {
-                    // not in this game
-                    unreachable!()
-                }
-            },
-            HigurashiHouV2 => match kind {
-                Saveinfo | Select | Dbgout | Voiceplay => U8Length,
-                Msgset | Logset => U16Length,
-                Chatset | Named | Stageinfo => {
-                    // not in this game
-                    unreachable!()
-                }
-            },
-            DC4 => match kind {
-                Saveinfo | Select | Dbgout | Voiceplay | Msgset | Chatset => U16Length,
-                Logset | Named | Stageinfo => {
-                    // not in this game
-                    unreachable!()
-                }
-            },
-            Konosuba => match kind {
-                Saveinfo | Select | Dbgout | Voiceplay => U8Length,
-                Msgset => U16Length,
-                Chatset | Logset | Named | Stageinfo => {
-                    // not in this game
-                    unreachable!()
-                }
-            },
-            Umineko => match kind {
-                Msgset | Select | Voiceplay | Saveinfo | Dbgout => U16Length,
-                Logset | Named | Stageinfo | Chatset => {
-                    // not in this game
-                    unreachable!()
-                }
-            },
-            Gerokasu2 => match kind {
-                Msgset | Select | Voiceplay | Saveinfo | Dbgout => U16Length,
-                Logset | Named | Stageinfo | Chatset => {
-                    // not in this game
-                    unreachable!()
-                }
+        let size_kind = match kind {
+            // the header tables store the strings the same way in all versions
+            BgmName | TipsTitle | TipsContent | CharacterName => U16Length,
+            _ => match self {
+                HigurashiSui => match kind {
+                    Saveinfo | Select | Voiceplay => U8Length,
+                    Msgset | Logset => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
+                AliasCarnival => match kind {
+                    Saveinfo | Select | Dbgout | Voiceplay | Stageinfo | Named => U8Length,
+                    Msgset | Logset => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
+                WorldRe => match kind {
+                    Saveinfo | Select | Voiceplay => U8Length,
+                    Msgset | Logset => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
+                WhiteEternity => match kind {
+                    Saveinfo | Select | Dbgout | Voiceplay => U8Length,
+                    Msgset | Logset => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
+                HigurashiHou => match kind {
+                    Saveinfo | Select | Dbgout | Voiceplay => U8Length,
+                    Msgset | Logset => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
+                HigurashiHouV2 => match kind {
+                    Saveinfo | Select | Dbgout | Voiceplay => U8Length,
+                    Msgset | Logset => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
+                DC4 => match kind {
+                    Saveinfo | Select | Dbgout | Voiceplay | Msgset | Chatset => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
+                Konosuba => match kind {
+                    Saveinfo | Select | Dbgout | Voiceplay => U8Length,
+                    Msgset => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
+                Umineko => match kind {
+                    Msgset | Select | Voiceplay | Saveinfo | Dbgout => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
+                Gerokasu2 => match kind {
+                    Msgset | Select | Voiceplay | Saveinfo | Dbgout => U16Length,
+                    _ => {
+                        // not in this game
+                        unreachable!()
+                    }
+                },
             },
         };
 
@@ -520,6 +607,22 @@ impl RomVersion {
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "clap")]
+    #[test]
+    fn version_names() {
+        use clap::ValueEnum;
+
+        use crate::ShinVersion;
+
+        // make sure the names match the ones accepted by the CLI
+        for version in ShinVersion::value_variants() {
+            assert_eq!(
+                version.to_possible_value().unwrap().get_name(),
+                version.name()
+            );
+        }
+    }
+
     #[cfg(feature = "serde")]
     #[test]
     fn serde_any_string_kind() {
@@ -533,5 +636,14 @@ mod tests {
             serde_json::from_str::<AnyStringKind>("\"select_choice\"").unwrap(),
             AnyStringKind::Array(StringArrayKind::SelectChoice)
         );
+
+        // make sure the names match the serde representation
+        for kind in AnyStringKind::ALL {
+            assert_eq!(
+                serde_json::to_string(&kind).unwrap(),
+                format!("\"{}\"", kind.name())
+            );
+            assert_eq!(AnyStringKind::from_name(kind.name()), Some(kind));
+        }
     }
 }